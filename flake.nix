@@ -51,6 +51,11 @@
           lockFile = ./Cargo.lock;
         };
         inherit nativeBuildInputs buildInputs;
+        # The golden-image tests render with Mesa's llvmpipe through a surfaceless EGL context.
+        nativeCheckInputs = [pkgs.mesa];
+        preCheck = ''
+          export __EGL_VENDOR_LIBRARY_DIRS=${pkgs.mesa}/share/glvnd/egl_vendor.d
+        '';
       };
 
       devShells.default = pkgs.mkShell {
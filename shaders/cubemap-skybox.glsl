@@ -0,0 +1,10 @@
+// Looks around inside the cubemap bound to iChannel2, see --cubemap.
+void mainImage(out vec4 fragColor,in vec2 fragCoord){
+    vec2 p=(2.*fragCoord-iResolution.xy)/iResolution.y;
+    float yaw=.4*iTime;
+    float pitch=.3*sin(.3*iTime);
+    vec3 direction=normalize(vec3(p,-1.2));
+    direction.yz*=mat2(cos(pitch),-sin(pitch),sin(pitch),cos(pitch));
+    direction.xz*=mat2(cos(yaw),-sin(yaw),sin(yaw),cos(yaw));
+    fragColor=vec4(texture(iChannel2,direction).rgb,1.);
+}
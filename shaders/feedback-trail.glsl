@@ -0,0 +1,9 @@
+// A dot wandering around, leaving a fading trail. Needs --feedback.
+void mainImage(out vec4 fragColor,in vec2 fragCoord){
+    vec2 p=(2.*fragCoord-iResolution.xy)/iResolution.y;
+    vec2 centre=.6*vec2(cos(iTime*2.),sin(iTime*3.));
+    float spot=smoothstep(.12,.08,length(p-centre));
+    vec3 colour=.5+.5*cos(iTime+vec3(0.,2.,4.));
+    vec3 previous=texture(u_backbuffer,fragCoord/iResolution.xy).rgb*.92;
+    fragColor=vec4(max(previous,spot*colour),1.);
+}
@@ -0,0 +1,12 @@
+// Slowly drifts the hue of the wallpaper, with a gentle ripple.
+vec3 hue_rotate(vec3 colour,float angle){
+    const vec3 k=vec3(0.57735);
+    float c=cos(angle);
+    return colour*c+cross(k,colour)*sin(angle)+k*dot(k,colour)*(1.-c);
+}
+
+void mainImage(out vec4 fragColor,in vec2 fragCoord){
+    vec2 ripple=vec2(0.,sin(fragCoord.x*.05+iTime)*2.);
+    vec4 colour=wallpaper(fragCoord+ripple);
+    fragColor=vec4(hue_rotate(colour.rgb,iTime*.1),colour.a);
+}
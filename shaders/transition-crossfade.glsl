@@ -0,0 +1,11 @@
+#version 300 es
+precision mediump float;
+uniform sampler2D u_from;
+uniform sampler2D u_to;
+uniform float u_progress;
+uniform vec2 u_resolution;
+out vec4 fragColor;
+void main(){
+    vec2 uv=gl_FragCoord.xy/u_resolution;
+    fragColor=mix(texture(u_from,uv),texture(u_to,uv),smoothstep(0.,1.,u_progress));
+}
@@ -0,0 +1,6 @@
+// A slice through the volume bound to iChannel3 sweeping back and forth, see --volume.
+void mainImage(out vec4 fragColor,in vec2 fragCoord){
+    vec2 uv=fragCoord/iResolution.xy;
+    float depth=.5+.5*sin(.5*iTime);
+    fragColor=vec4(texture(iChannel3,vec3(uv,depth)).rgb,1.);
+}
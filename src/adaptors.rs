@@ -1,13 +1,35 @@
-pub fn shader_toy_adaptor(fragment_shader: String) -> String {
-    return format!(
+use crate::channels::ChannelKind;
+
+/// Wrap a Shadertoy `mainImage` into a complete fragment shader, declaring each
+/// `iChannel` with the sampler type of its kind.
+pub fn shader_toy_adaptor(fragment_shader: String, channel_kinds: &[ChannelKind]) -> String {
+    let samplers: String = channel_kinds
+        .iter()
+        .enumerate()
+        .map(|(index, kind)| format!("uniform {} iChannel{};\n", kind.sampler_type(), index))
+        .collect();
+    format!(
         "
         #version 300 es
         precision highp float;
+        precision highp sampler3D;
         
         // uniforms
         uniform float u_time;
         uniform vec2 u_resolution;
         uniform vec2 u_mouse;
+        uniform float u_mouse_inside;
+        uniform vec4 u_shadertoy_mouse;
+        uniform vec2 u_mouse_velocity;
+        uniform float u_mouse_idle_time;
+        uniform float u_daytime;
+        uniform float u_sun_elevation;
+        uniform float u_bass;
+        uniform float u_mid;
+        uniform float u_treble;
+        {samplers}
+        uniform vec3 iChannelResolution[4];
+        uniform sampler2D u_backbuffer;
 
         // input: vertex position
         in vec2 v_position;
@@ -18,7 +40,7 @@ pub fn shader_toy_adaptor(fragment_shader: String) -> String {
         // Shadertoy style uniforms definition
         float iTime;
         vec3 iResolution;
-        vec2 iMouse;
+        vec4 iMouse;
 
         // Shadertoy main function
         {fragment_shader}
@@ -27,10 +49,10 @@ pub fn shader_toy_adaptor(fragment_shader: String) -> String {
         void main() {{
             iResolution=vec3(u_resolution,u_resolution.x/u_resolution.y);
             iTime=u_time;
-            iMouse=u_mouse;
+            iMouse=u_shadertoy_mouse;
             vec2 w=(v_position * 0.5 + 0.5) * u_resolution.xy;
             mainImage(fragColor,w);
         }}
         "
-    );
+    )
 }
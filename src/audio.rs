@@ -0,0 +1,372 @@
+use std::collections::VecDeque;
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+use rustfft::num_complex::Complex;
+
+use crate::channels::{ChannelSource, upload_red_texture};
+use crate::error::{Error, Result};
+
+/// Sample rate PCM input is expected in, 16 bit signed little endian stereo.
+/// This is also the default format of MPD's fifo output.
+pub const SAMPLE_RATE: u32 = 44100;
+const CHANNELS: usize = 2;
+const FFT_SIZE: usize = 1024;
+/// Width of the audio texture. The first row holds the spectrum, the second the waveform.
+pub const TEXTURE_WIDTH: usize = FFT_SIZE / 2;
+/// Decibel range mapped to 0..1, the defaults of a Web Audio `AnalyserNode` as used by Shadertoy.
+const MIN_DECIBELS: f32 = -100.0;
+const MAX_DECIBELS: f32 = -30.0;
+/// Weight of the previous spectrum, like `AnalyserNode::smoothingTimeConstant`.
+const SMOOTHING: f32 = 0.8;
+const BASS_RANGE: (f32, f32) = (20.0, 250.0);
+const MID_RANGE: (f32, f32) = (250.0, 4000.0);
+const TREBLE_RANGE: (f32, f32) = (4000.0, 20000.0);
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AudioSource {
+    /// Record a PulseAudio (or PipeWire through pipewire-pulse) source with `parec`.
+    /// `None` records the monitor of the default sink, i.e. what is playing.
+    Pulse { device: Option<String> },
+    /// Read raw PCM from a named pipe, such as MPD's fifo output, or a regular file.
+    Fifo(PathBuf),
+}
+
+impl std::str::FromStr for AudioSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "pulse" => Ok(AudioSource::Pulse { device: None }),
+            Some(("pulse", device)) => Ok(AudioSource::Pulse {
+                device: Some(device.to_string()),
+            }),
+            Some(("fifo" | "file", path)) => Ok(AudioSource::Fifo(PathBuf::from(path))),
+            _ => Err(format!(
+                "unknown audio source '{}', expected 'pulse', 'pulse:<DEVICE>' or 'fifo:<PATH>'",
+                s
+            )),
+        }
+    }
+}
+
+/// Audio captured on a background thread and analysed once per frame.
+///
+/// As a channel it is a 512x2 texture like Shadertoy's audio inputs: the spectrum in the
+/// first row and the waveform in the second. It also provides the mean level of three
+/// frequency bands as `u_bass`, `u_mid` and `u_treble`.
+pub struct AudioInput {
+    // Mono samples, the newest at the back.
+    samples: Arc<Mutex<VecDeque<f32>>>,
+    fft: Arc<dyn rustfft::Fft<f32>>,
+    window: Vec<f32>,
+    spectrum: Vec<f32>,
+    texture: Vec<u8>,
+    bands: [f32; 3],
+}
+
+impl AudioInput {
+    pub fn spawn(source: AudioSource) -> Result<Self> {
+        tracing::info!("Capturing audio from {:?}", source);
+        let samples = Arc::new(Mutex::new(VecDeque::from(vec![0.0; FFT_SIZE])));
+        let weak_samples = Arc::downgrade(&samples);
+        std::thread::Builder::new()
+            .name("audio-capture".to_string())
+            .spawn(move || capture(&source, weak_samples))
+            .map_err(|e| Error::io("failed to spawn the audio capture thread", e))?;
+
+        // Hann window against spectral leakage.
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+        Ok(AudioInput {
+            samples,
+            fft: rustfft::FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            spectrum: vec![0.0; TEXTURE_WIDTH],
+            texture: vec![0; TEXTURE_WIDTH * 2],
+            bands: [0.0; 3],
+        })
+    }
+
+    /// Recompute the spectrum, waveform and band levels from the latest samples.
+    fn analyze(&mut self) {
+        let latest: Vec<f32> = {
+            let samples = self.samples.lock().unwrap_or_else(PoisonError::into_inner);
+            samples.range(samples.len() - FFT_SIZE..).copied().collect()
+        };
+
+        let mut buffer: Vec<Complex<f32>> = latest
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, weight)| Complex::new(sample * weight, 0.0))
+            .collect();
+        self.fft.process(&mut buffer);
+
+        for (bin, value) in buffer.iter().take(TEXTURE_WIDTH).enumerate() {
+            let magnitude = value.norm() / FFT_SIZE as f32;
+            self.spectrum[bin] = SMOOTHING * self.spectrum[bin] + (1.0 - SMOOTHING) * magnitude;
+            let decibels = 20.0 * self.spectrum[bin].max(1e-12).log10();
+            let level = ((decibels - MIN_DECIBELS) / (MAX_DECIBELS - MIN_DECIBELS)).clamp(0.0, 1.0);
+            self.texture[bin] = (level * 255.0) as u8;
+        }
+        for (i, sample) in latest[FFT_SIZE - TEXTURE_WIDTH..].iter().enumerate() {
+            self.texture[TEXTURE_WIDTH + i] = (128.0 + sample.clamp(-1.0, 1.0) * 127.0) as u8;
+        }
+
+        self.bands = [BASS_RANGE, MID_RANGE, TREBLE_RANGE].map(|range| self.band_level(range));
+    }
+
+    /// Mean spectrum level (0..1) of the bins within a frequency range in Hz.
+    fn band_level(&self, (low, high): (f32, f32)) -> f32 {
+        let bin_width = SAMPLE_RATE as f32 / FFT_SIZE as f32;
+        let first = ((low / bin_width) as usize).max(1);
+        let last = ((high / bin_width) as usize).min(TEXTURE_WIDTH - 1);
+        if first > last {
+            return 0.0;
+        }
+        let sum: u32 = self.texture[first..=last].iter().map(|&v| v as u32).sum();
+        sum as f32 / ((last - first + 1) as f32 * 255.0)
+    }
+}
+
+impl ChannelSource for AudioInput {
+    fn init_texture(&mut self, gl: &glow::Context, _texture: glow::Texture) {
+        upload_red_texture(gl, TEXTURE_WIDTH, 2, &self.texture, true);
+    }
+
+    fn advance(&mut self) {
+        self.analyze();
+    }
+
+    fn update(&mut self, gl: &glow::Context, _texture: glow::Texture) {
+        upload_red_texture(gl, TEXTURE_WIDTH, 2, &self.texture, false);
+    }
+
+    fn resolution(&self) -> [f32; 3] {
+        [TEXTURE_WIDTH as f32, 2.0, 1.0]
+    }
+
+    fn uniforms(&self) -> Vec<(&'static str, f32)> {
+        vec![
+            ("u_bass", self.bands[0]),
+            ("u_mid", self.bands[1]),
+            ("u_treble", self.bands[2]),
+        ]
+    }
+}
+
+fn open(source: &AudioSource) -> std::io::Result<(Box<dyn Read + Send>, Option<Child>)> {
+    match source {
+        AudioSource::Pulse { device } => {
+            let mut child = Command::new("parec")
+                .args(["--raw", "--format=s16le", "--channels=2"])
+                .arg(format!("--rate={}", SAMPLE_RATE))
+                .arg(format!(
+                    "--device={}",
+                    device.as_deref().unwrap_or("@DEFAULT_MONITOR@")
+                ))
+                .stdout(Stdio::piped())
+                .stdin(Stdio::null())
+                .spawn()?;
+            let Some(stdout) = child.stdout.take() else {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(std::io::Error::other("parec has no standard output"));
+            };
+            Ok((Box::new(stdout), Some(child)))
+        }
+        // Opening a fifo blocks until a writer shows up, which is fine on this thread.
+        AudioSource::Fifo(path) => Ok((Box::new(std::fs::File::open(path)?), None)),
+    }
+}
+
+fn capture(source: &AudioSource, samples: Weak<Mutex<VecDeque<f32>>>) {
+    let mut failing = false;
+    loop {
+        let (stream, child) = match open(source) {
+            Ok(opened) => opened,
+            Err(e) => {
+                if !failing {
+                    tracing::warn!("Failed to open audio source {:?}: {}", source, e);
+                    failing = true;
+                }
+                if samples.strong_count() == 0 {
+                    return;
+                }
+                std::thread::sleep(RETRY_INTERVAL);
+                continue;
+            }
+        };
+        failing = false;
+        let stopped = read_stream(stream, &samples);
+        if let Some(mut child) = child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        if stopped {
+            tracing::debug!("Audio input dropped, stopping capture");
+            return;
+        }
+        // The writer went away or the file ended: start over.
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Read PCM into `samples` until the stream ends. Returns `true` once `samples` is dropped.
+///
+/// Reading is paced to real time, so a regular file plays back at its sample rate.
+fn read_stream(stream: Box<dyn Read + Send>, samples: &Weak<Mutex<VecDeque<f32>>>) -> bool {
+    const FRAMES_PER_READ: usize = 256;
+    let mut reader = BufReader::new(stream);
+    let mut buffer = [0u8; FRAMES_PER_READ * CHANNELS * 2];
+    let started = Instant::now();
+    let mut frames_read: u64 = 0;
+    loop {
+        if let Err(e) = reader.read_exact(&mut buffer) {
+            if e.kind() != std::io::ErrorKind::UnexpectedEof {
+                tracing::warn!("Failed to read audio: {}", e);
+            }
+            return samples.strong_count() == 0;
+        }
+        let Some(samples) = samples.upgrade() else {
+            return true;
+        };
+        {
+            let mut samples = samples.lock().unwrap_or_else(PoisonError::into_inner);
+            for frame in buffer.chunks_exact(CHANNELS * 2) {
+                let left = i16::from_le_bytes([frame[0], frame[1]]) as f32;
+                let right = i16::from_le_bytes([frame[2], frame[3]]) as f32;
+                samples.push_back((left + right) / (2.0 * 32768.0));
+            }
+            let excess = samples.len().saturating_sub(FFT_SIZE);
+            samples.drain(..excess);
+        }
+        drop(samples);
+
+        frames_read += FRAMES_PER_READ as u64;
+        let position = Duration::from_secs_f64(frames_read as f64 / SAMPLE_RATE as f64);
+        let ahead = position.saturating_sub(started.elapsed());
+        if ahead > Duration::from_millis(20) {
+            std::thread::sleep(ahead);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write a second of a stereo sine wave at `frequency` as raw PCM.
+    fn sine_file(name: &str, frequency: f32) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "wayggle-bg-audio-{}-{}.pcm",
+            std::process::id(),
+            name
+        ));
+        let pcm: Vec<u8> = (0..SAMPLE_RATE)
+            .flat_map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE as f32;
+                let sample = ((0.5 * phase.sin()) * 32767.0) as i16;
+                [sample.to_le_bytes(), sample.to_le_bytes()].concat()
+            })
+            .collect();
+        std::fs::write(&path, pcm).unwrap();
+        path
+    }
+
+    /// Play `frequency` through a file source and analyse it until the spectrum settles.
+    fn analyze_sine(name: &str, frequency: f32) -> AudioInput {
+        let path = sine_file(name, frequency);
+        let source: AudioSource = format!("file:{}", path.display()).parse().unwrap();
+        let mut input = AudioInput::spawn(source).unwrap();
+        // Reading is paced to real time; wait until the file fills a whole FFT window,
+        // pushing out the silence the window starts with.
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while {
+            let samples = input.samples.lock().unwrap();
+            let oldest = samples.len() - FFT_SIZE;
+            samples
+                .range(oldest..oldest + 16)
+                .all(|sample| *sample == 0.0)
+        } {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "no samples from {}",
+                path.display()
+            );
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        for _ in 0..50 {
+            input.analyze();
+        }
+        std::fs::remove_file(path).unwrap();
+        input
+    }
+
+    /// The loudest bin, from the spectrum since loud bins all reach the top of the texture.
+    fn peak_bin(input: &AudioInput) -> usize {
+        (0..TEXTURE_WIDTH)
+            .max_by(|&a, &b| input.spectrum[a].total_cmp(&input.spectrum[b]))
+            .unwrap()
+    }
+
+    #[test]
+    fn bass_fills_the_low_bins() {
+        let input = analyze_sine("bass", 100.0);
+        let bin_width = SAMPLE_RATE as f32 / FFT_SIZE as f32;
+        assert_eq!(peak_bin(&input), (100.0 / bin_width).round() as usize);
+        let [bass, mid, treble] = input.bands;
+        assert!(bass > 0.3, "{:?}", input.bands);
+        assert!(bass > mid && mid > treble, "{:?}", input.bands);
+
+        let uniforms = input.uniforms();
+        assert_eq!(uniforms[0], ("u_bass", bass));
+        assert_eq!(uniforms[2], ("u_treble", treble));
+    }
+
+    #[test]
+    fn treble_fills_the_high_bins() {
+        let input = analyze_sine("treble", 8000.0);
+        let bin_width = SAMPLE_RATE as f32 / FFT_SIZE as f32;
+        assert_eq!(peak_bin(&input), (8000.0 / bin_width).round() as usize);
+        let [bass, _, treble] = input.bands;
+        assert!(treble > bass, "{:?}", input.bands);
+    }
+
+    #[test]
+    fn the_second_row_is_the_waveform() {
+        let input = analyze_sine("waveform", 440.0);
+        let waveform = &input.texture[TEXTURE_WIDTH..];
+        let (low, high) = (
+            *waveform.iter().min().unwrap(),
+            *waveform.iter().max().unwrap(),
+        );
+        // Half amplitude around the middle of the range.
+        assert!((60..=70).contains(&low), "{}", low);
+        assert!((186..=196).contains(&high), "{}", high);
+    }
+
+    #[test]
+    fn parses_sources() {
+        assert_eq!("pulse".parse(), Ok(AudioSource::Pulse { device: None }));
+        assert_eq!(
+            "pulse:mic".parse(),
+            Ok(AudioSource::Pulse {
+                device: Some("mic".to_string())
+            })
+        );
+        assert_eq!(
+            "fifo:/tmp/mpd.fifo".parse(),
+            Ok(AudioSource::Fifo(PathBuf::from("/tmp/mpd.fifo")))
+        );
+        assert!("alsa".parse::<AudioSource>().is_err());
+    }
+}
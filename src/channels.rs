@@ -0,0 +1,193 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use glow::HasContext;
+
+use crate::error::{Error, Result};
+
+/// Number of `iChannel` samplers a shader can read.
+pub const CHANNEL_COUNT: u32 = 4;
+
+/// The type of texture a channel is, and so the sampler shaders read it with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChannelKind {
+    #[default]
+    Texture2D,
+    /// Six square faces, sampled with a direction.
+    Cube,
+    /// A 3D texture.
+    Volume,
+}
+
+impl ChannelKind {
+    /// The texture target the channel's texture is bound to.
+    pub fn target(self) -> u32 {
+        match self {
+            ChannelKind::Texture2D => glow::TEXTURE_2D,
+            ChannelKind::Cube => glow::TEXTURE_CUBE_MAP,
+            ChannelKind::Volume => glow::TEXTURE_3D,
+        }
+    }
+
+    /// The GLSL type of the `iChannel` sampler.
+    pub fn sampler_type(self) -> &'static str {
+        match self {
+            ChannelKind::Texture2D => "sampler2D",
+            ChannelKind::Cube => "samplerCube",
+            ChannelKind::Volume => "sampler3D",
+        }
+    }
+}
+
+/// A texture input bound to one of the Shadertoy style `iChannel` samplers.
+pub trait ChannelSource {
+    /// Called when a pipeline creates its `texture` for the channel, bound like in
+    /// [`update`](ChannelSource::update). Every pipeline has a texture of its own, and
+    /// several exist at once during transitions. Sources with fixed contents upload them
+    /// here; others allocate storage or forget what they knew about a texture of the
+    /// same name.
+    fn init_texture(&mut self, _gl: &glow::Context, _texture: glow::Texture) {}
+
+    /// Called once per frame before any pipeline draws, to take in new input such as
+    /// key presses or decoded frames.
+    fn advance(&mut self) {}
+
+    /// Called by every pipeline drawing a frame, with the channel's `texture` bound to
+    /// the target of its [`kind`](ChannelSource::kind) on the active texture unit; twice
+    /// per frame during a transition. Uploads the state [`advance`](ChannelSource::advance)
+    /// left if the texture doesn't hold it yet.
+    fn update(&mut self, _gl: &glow::Context, _texture: glow::Texture) {}
+
+    /// The type of texture the channel is.
+    fn kind(&self) -> ChannelKind {
+        ChannelKind::Texture2D
+    }
+
+    /// Size of the texture, as passed in `iChannelResolution`.
+    fn resolution(&self) -> [f32; 3];
+
+    /// Extra float uniforms derived from the input. Names and their order must not change
+    /// between calls.
+    fn uniforms(&self) -> Vec<(&'static str, f32)> {
+        Vec::new()
+    }
+
+    /// Called when the wallpaper stops rendering, e.g. while the session is idle, and
+    /// when it starts again. Inputs that play on by themselves should hold still.
+    fn set_paused(&mut self, _paused: bool) {}
+
+    /// Called with the connector name of the output the wallpaper is shown on, such as
+    /// `DP-1`, whenever it changes. `None` while it isn't known.
+    fn set_output(&mut self, _name: Option<&str>) {}
+}
+
+pub struct ChannelBinding {
+    /// Index `n` of the `iChannel<n>` sampler.
+    pub index: u32,
+    pub source: Rc<RefCell<dyn ChannelSource>>,
+}
+
+/// Fail if two channels are bound to the same `iChannel`, or to one that doesn't exist.
+pub fn check_bindings(channels: &[ChannelBinding]) -> Result<()> {
+    for (i, binding) in channels.iter().enumerate() {
+        if binding.index >= CHANNEL_COUNT {
+            return Err(Error::Configuration(format!(
+                "there is no iChannel{}, only iChannel0 to iChannel{}",
+                binding.index,
+                CHANNEL_COUNT - 1
+            )));
+        }
+        if channels[..i]
+            .iter()
+            .any(|other| other.index == binding.index)
+        {
+            return Err(Error::Configuration(format!(
+                "two inputs are bound to iChannel{}",
+                binding.index
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The kind of each `iChannel` sampler, 2D for the ones nothing is bound to.
+pub fn channel_kinds(channels: &[ChannelBinding]) -> [ChannelKind; CHANNEL_COUNT as usize] {
+    let mut kinds = [ChannelKind::Texture2D; CHANNEL_COUNT as usize];
+    for binding in channels {
+        if let Some(kind) = kinds.get_mut(binding.index as usize) {
+            *kind = binding.source.borrow().kind();
+        }
+    }
+    kinds
+}
+
+/// Upload single channel 8 bit pixels into the texture bound to `TEXTURE_2D`,
+/// allocating its storage first with `allocate`.
+pub fn upload_red_texture(
+    gl: &glow::Context,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+    allocate: bool,
+) {
+    unsafe {
+        gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+        let pixels = glow::PixelUnpackData::Slice(Some(pixels));
+        if !allocate {
+            gl.tex_sub_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                0,
+                0,
+                width as i32,
+                height as i32,
+                glow::RED,
+                glow::UNSIGNED_BYTE,
+                pixels,
+            );
+        } else {
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::R8 as i32,
+                width as i32,
+                height as i32,
+                0,
+                glow::RED,
+                glow::UNSIGNED_BYTE,
+                pixels,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Blank;
+
+    impl ChannelSource for Blank {
+        fn resolution(&self) -> [f32; 3] {
+            [1.0, 1.0, 1.0]
+        }
+    }
+
+    fn bindings(indices: &[u32]) -> Vec<ChannelBinding> {
+        indices
+            .iter()
+            .map(|&index| ChannelBinding {
+                index,
+                source: Rc::new(RefCell::new(Blank)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn checks_bindings() {
+        assert!(check_bindings(&bindings(&[])).is_ok());
+        assert!(check_bindings(&bindings(&[0, 1, 2, 3])).is_ok());
+        assert!(check_bindings(&bindings(&[2, 0, 2])).is_err());
+        assert!(check_bindings(&bindings(&[CHANNEL_COUNT])).is_err());
+    }
+}
@@ -9,10 +9,280 @@ pub struct Cli {
     pub command: Command,
     #[clap(short = 'c', long = "enable-cursor-support", value_name = "COMPOSITOR", value_enum, default_value_t = CursorSupportKind::Disabled)]
     pub cursor_support: CursorSupportKind,
+    #[clap(long = "cursor-smoothing", value_name = "KIND", value_enum, default_value_t = CursorSmoothingKind::None)]
+    pub cursor_smoothing: CursorSmoothingKind,
+    #[clap(
+        long = "cursor-smoothing-time",
+        value_name = "SECONDS",
+        default_value_t = 0.1,
+        help = "How long the smoothed cursor takes to catch up with the real one."
+    )]
+    pub cursor_smoothing_time: f32,
+    #[clap(
+        long = "fallback-shader",
+        value_name = "FILE",
+        help = "Fragment shader shown when the main one fails to compile, in the same format."
+    )]
+    pub fallback_shader: Option<std::path::PathBuf>,
+    #[clap(
+        long = "feedback",
+        help = "Give the shader the previous frame as u_backbuffer, and as iChannel0 unless an input is bound to it."
+    )]
+    pub feedback: bool,
+    #[clap(
+        long = "idle-timeout",
+        value_name = "SECONDS",
+        default_value_t = 300,
+        help = "Pause rendering after the session has been idle for this long. 0 disables it."
+    )]
+    pub idle_timeout: u64,
+    #[clap(
+        long = "battery-fps",
+        value_name = "FPS",
+        help = "Limit the frame rate while running on battery. 0 freezes the current frame."
+    )]
+    pub battery_fps: Option<u32>,
+    #[clap(
+        long = "low-battery-threshold",
+        value_name = "PERCENT",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        help = "Freeze the current frame while running on battery below this charge."
+    )]
+    pub low_battery_threshold: Option<u8>,
+    #[clap(
+        long = "power-supply-root",
+        value_name = "DIR",
+        default_value = wayggle_bg::power_supply::DEFAULT_SYSFS_ROOT,
+        help = "sysfs directory the power supply state is read from."
+    )]
+    pub power_supply_root: std::path::PathBuf,
+    #[clap(
+        long = "latitude",
+        value_name = "DEGREES",
+        requires = "longitude",
+        allow_negative_numbers = true,
+        help = "Latitude for u_sun_elevation and playlists following sunrise and sunset, north positive."
+    )]
+    pub latitude: Option<f64>,
+    #[clap(
+        long = "longitude",
+        value_name = "DEGREES",
+        requires = "latitude",
+        allow_negative_numbers = true,
+        help = "Longitude for u_sun_elevation and playlists following sunrise and sunset, east positive."
+    )]
+    pub longitude: Option<f64>,
+    #[clap(
+        long = "time-mode",
+        value_name = "MODE",
+        default_value = "elapsed",
+        help = "What u_time counts: seconds since start, or since local midnight so restarts don't jump."
+    )]
+    pub time_mode: TimeMode,
+    #[clap(
+        long = "time-speed",
+        value_name = "FACTOR",
+        default_value_t = 1.0,
+        allow_negative_numbers = true,
+        help = "Playback speed of u_time."
+    )]
+    pub time_speed: f64,
+    #[clap(
+        long = "time-offset",
+        value_name = "SECONDS",
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        help = "Added to u_time, e.g. to start at a nicer moment of the animation."
+    )]
+    pub time_offset: f64,
+    #[clap(
+        long = "freeze",
+        conflicts_with = "time_speed",
+        help = "Stop u_time at --time-offset, e.g. for a still wallpaper that still follows the cursor."
+    )]
+    pub freeze: bool,
+    #[clap(
+        long = "time-wrap",
+        value_name = "DURATION",
+        value_parser = wayggle_bg::headless::parse_duration,
+        help = "Wrap u_time around after this long to keep float precision on long uptimes. Use a multiple of the shader's loop."
+    )]
+    pub time_wrap: Option<std::time::Duration>,
+    #[clap(
+        long = "audio",
+        value_name = "SOURCE",
+        help = "Audio to visualize: 'pulse', 'pulse:<DEVICE>' or 'fifo:<PATH>' with 44.1 kHz s16le stereo PCM."
+    )]
+    pub audio: Option<wayggle_bg::audio::AudioSource>,
+    #[clap(
+        long = "audio-channel",
+        value_name = "INDEX",
+        value_parser = clap::value_parser!(u32).range(0..wayggle_bg::channels::CHANNEL_COUNT as i64),
+        help = "The iChannel the audio texture is bound to. Defaults to the shader package's choice, else 0."
+    )]
+    pub audio_channel: Option<u32>,
+    #[clap(
+        long = "keyboard",
+        value_name = "DEVICE",
+        help = "evdev keyboard (e.g. /dev/input/by-id/...-event-kbd) to feed the ShaderToy keyboard texture."
+    )]
+    pub keyboard: Option<std::path::PathBuf>,
+    #[clap(
+        long = "keyboard-channel",
+        value_name = "INDEX",
+        value_parser = clap::value_parser!(u32).range(0..wayggle_bg::channels::CHANNEL_COUNT as i64),
+        help = "The iChannel the keyboard texture is bound to. Defaults to the shader package's choice, else 1."
+    )]
+    pub keyboard_channel: Option<u32>,
+    #[clap(
+        long = "screencopy",
+        value_name = "OUTPUT",
+        help = "Output (e.g. DP-1) to capture with wlr-screencopy. Not captured while the wallpaper is on it."
+    )]
+    pub screencopy: Option<String>,
+    #[clap(
+        long = "screencopy-channel",
+        value_name = "INDEX",
+        default_value_t = 3,
+        value_parser = clap::value_parser!(u32).range(0..wayggle_bg::channels::CHANNEL_COUNT as i64),
+        help = "The iChannel the captured output is bound to."
+    )]
+    pub screencopy_channel: u32,
+    #[clap(
+        long = "screencopy-interval",
+        value_name = "FRAMES",
+        default_value_t = 30,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Capture the output every this many rendered frames."
+    )]
+    pub screencopy_interval: u32,
+    #[clap(flatten)]
+    pub textures: Textures,
+}
+
+/// Cubemap and volume textures for the shader.
+#[derive(clap::Args)]
+pub struct Textures {
+    #[clap(
+        long = "cubemap",
+        value_name = "FILE",
+        help = "Cubemap read as a samplerCube: six faces in the order +X -X +Y -Y +Z -Z, or one equirectangular image."
+    )]
+    pub cubemap: Vec<std::path::PathBuf>,
+    #[clap(
+        long = "cubemap-channel",
+        value_name = "INDEX",
+        value_parser = clap::value_parser!(u32).range(0..wayggle_bg::channels::CHANNEL_COUNT as i64),
+        help = "The iChannel the cubemap is bound to. Defaults to 2, or the lowest one no other input uses."
+    )]
+    pub cubemap_channel: Option<u32>,
+    #[clap(
+        long = "volume",
+        value_name = "FILE",
+        help = "Volume in Shadertoy's binary format, read as a sampler3D."
+    )]
+    pub volume: Option<std::path::PathBuf>,
+    #[clap(
+        long = "volume-channel",
+        value_name = "INDEX",
+        value_parser = clap::value_parser!(u32).range(0..wayggle_bg::channels::CHANNEL_COUNT as i64),
+        help = "The iChannel the volume is bound to. Defaults to 3, or the lowest one no other input uses."
+    )]
+    pub volume_channel: Option<u32>,
 }
 
 #[derive(clap::Subcommand)]
 pub enum Command {
+    #[clap(flatten)]
+    Shader(ShaderCommand),
+    #[clap(
+        about = "List the built-in shaders and installed shader packages usable with 'default --name'."
+    )]
+    List,
+    #[clap(
+        about = "Show the shader each running wallpaper shows, and why the ones before it failed."
+    )]
+    Status,
+    #[clap(about = "Make the running wallpapers read their shader files again.")]
+    Reload,
+    #[clap(about = "Rotate through the shaders of a playlist file, with transitions.")]
+    Playlist {
+        #[clap(
+            long = "file",
+            value_name = "FILE",
+            help = "TOML playlist naming the shaders, how long each is shown and the transition."
+        )]
+        file: std::path::PathBuf,
+    },
+    #[clap(about = "Render a single frame to a PNG file without a Wayland session.")]
+    Render {
+        #[clap(
+            long = "time",
+            value_name = "SECONDS",
+            default_value_t = 0.0,
+            help = "Shader time of the frame."
+        )]
+        time: f32,
+        #[clap(
+            long = "size",
+            value_name = "WIDTHxHEIGHT",
+            value_parser = wayggle_bg::headless::parse_size,
+            default_value = "1920x1080"
+        )]
+        size: (u32, u32),
+        #[clap(short, long = "out", value_name = "FILE")]
+        out: std::path::PathBuf,
+        #[clap(subcommand)]
+        shader: ShaderCommand,
+    },
+    #[clap(
+        about = "Render an animation to numbered PNG files or an encoder without a Wayland session."
+    )]
+    Record {
+        #[clap(
+            long = "duration",
+            value_name = "DURATION",
+            value_parser = wayggle_bg::headless::parse_duration,
+            help = "Length of the animation, e.g. '10s', '500ms' or '2m'."
+        )]
+        duration: std::time::Duration,
+        #[clap(long = "fps", value_name = "FPS", default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+        fps: u32,
+        #[clap(
+            long = "start",
+            value_name = "SECONDS",
+            default_value_t = 0.0,
+            help = "Shader time of the first frame."
+        )]
+        start: f32,
+        #[clap(
+            long = "size",
+            value_name = "WIDTHxHEIGHT",
+            value_parser = wayggle_bg::headless::parse_size,
+            default_value = "1920x1080"
+        )]
+        size: (u32, u32),
+        #[clap(
+            short,
+            long = "out",
+            value_name = "DIR",
+            required_unless_present = "pipe",
+            help = "Directory the numbered PNG frames are written to."
+        )]
+        out: Option<std::path::PathBuf>,
+        #[clap(
+            long = "pipe",
+            value_name = "COMMAND",
+            help = "Shell command fed raw RGBA frames on stdin, e.g. an ffmpeg invocation."
+        )]
+        pipe: Option<String>,
+        #[clap(subcommand)]
+        shader: ShaderCommand,
+    },
+}
+
+#[derive(Clone, clap::Subcommand)]
+pub enum ShaderCommand {
     #[clap(name = "shadertoy")]
     ShaderToy {
         #[clap(short, long, value_name = "FILE")]
@@ -27,16 +297,191 @@ pub enum Command {
     },
     #[clap(
         name = "default",
-        about = "Run the default shader with a given name<'box'>."
+        about = "Run a built-in shader or installed shader package by name, see 'list'."
     )]
     Default {
         #[clap(short, long, value_name = "NAME")]
         name: String,
     },
+    #[clap(
+        name = "image",
+        about = "Show an image, optionally through an effect shader reading it with wallpaper(fragCoord)."
+    )]
+    Image {
+        #[clap(long, value_name = "FILE", help = "PNG, JPEG or WebP image.")]
+        file: String,
+        #[clap(flatten)]
+        placement: Placement,
+    },
+    #[cfg(feature = "video")]
+    #[clap(
+        name = "video",
+        about = "Play a video without sound, optionally through an effect shader reading it with wallpaper(fragCoord)."
+    )]
+    Video {
+        #[clap(long, value_name = "FILE", help = "Any video GStreamer can decode.")]
+        file: String,
+        #[clap(long, help = "Stop on the last frame instead of starting over.")]
+        no_loop: bool,
+        #[clap(flatten)]
+        placement: Placement,
+    },
 }
 
-#[derive(Clone, clap::ValueEnum)]
+/// How an image or video is shown.
+#[derive(Clone, clap::Args)]
+pub struct Placement {
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Shadertoy style mainImage calling wallpaper(fragCoord) for the picture."
+    )]
+    pub effect: Option<String>,
+    #[clap(long, value_name = "MODE", default_value = "fill")]
+    pub fit: FitKind,
+    #[clap(
+        long,
+        value_name = "COLOUR",
+        default_value = "#000000",
+        value_parser = wayggle_bg::image::parse_colour,
+        help = "Colour around the picture with --fit fit or center."
+    )]
+    pub background: [f32; 3],
+    #[clap(
+        long,
+        value_name = "INDEX",
+        default_value_t = 2,
+        value_parser = clap::value_parser!(u32).range(0..wayggle_bg::channels::CHANNEL_COUNT as i64),
+        help = "The iChannel the picture is bound to."
+    )]
+    pub channel: u32,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum CursorSmoothingKind {
+    None,
+    Exponential,
+    Spring,
+}
+
+/// [`wayggle_bg::cursor_support::CursorSupportKind`] as a command line value.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
 pub enum CursorSupportKind {
+    /// Detect the compositor from the environment.
+    Auto,
     Hyprland,
+    /// Track the pointer while it is over the wallpaper. Works on any compositor, and is
+    /// what sway, niri and river get, as their IPC doesn't report the cursor position.
+    Pointer,
     Disabled,
 }
+
+impl From<CursorSupportKind> for wayggle_bg::cursor_support::CursorSupportKind {
+    fn from(kind: CursorSupportKind) -> Self {
+        match kind {
+            CursorSupportKind::Auto => Self::Auto,
+            CursorSupportKind::Hyprland => Self::Hyprland,
+            CursorSupportKind::Pointer => Self::Pointer,
+            CursorSupportKind::Disabled => Self::Disabled,
+        }
+    }
+}
+
+/// [`wayggle_bg::clock::TimeMode`] as a command line value.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum TimeMode {
+    /// Seconds since the wallpaper started.
+    Elapsed,
+    /// Seconds since local midnight, so restarts carry on where they left off.
+    WallClock,
+}
+
+impl From<TimeMode> for wayggle_bg::clock::TimeMode {
+    fn from(mode: TimeMode) -> Self {
+        match mode {
+            TimeMode::Elapsed => Self::Elapsed,
+            TimeMode::WallClock => Self::WallClock,
+        }
+    }
+}
+
+/// [`wayggle_bg::image::Fit`] as a command line value.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum FitKind {
+    /// Scale to cover the whole surface, cropping what sticks out.
+    Fill,
+    /// Scale to fit inside the surface, with bars of the background colour.
+    Fit,
+    /// Centre at its own size.
+    Center,
+    /// Repeat at its own size from the top left corner.
+    Tile,
+    /// Scale to the surface size, ignoring the aspect ratio.
+    Stretch,
+}
+
+impl From<FitKind> for wayggle_bg::image::Fit {
+    fn from(fit: FitKind) -> Self {
+        match fit {
+            FitKind::Fill => Self::Fill,
+            FitKind::Fit => Self::Fit,
+            FitKind::Center => Self::Center,
+            FitKind::Tile => Self::Tile,
+            FitKind::Stretch => Self::Stretch,
+        }
+    }
+}
+
+impl From<ShaderCommand> for wayggle_bg::ShaderChoice {
+    fn from(command: ShaderCommand) -> Self {
+        match command {
+            ShaderCommand::ShaderToy { fragment_shader } => Self::ShaderToy {
+                fragment_shader: fragment_shader.into(),
+            },
+            ShaderCommand::TheBookOfShaders {
+                fragment_shader,
+                vertex_shader,
+            } => Self::TheBookOfShaders {
+                fragment_shader: fragment_shader.into(),
+                vertex_shader: vertex_shader.map(Into::into),
+            },
+            ShaderCommand::Default { name } => Self::Named(name),
+            ShaderCommand::Image { file, placement } => Self::Image {
+                file: file.into(),
+                placement: placement.into(),
+            },
+            #[cfg(feature = "video")]
+            ShaderCommand::Video {
+                file,
+                no_loop,
+                placement,
+            } => Self::Video {
+                file: file.into(),
+                looping: !no_loop,
+                placement: placement.into(),
+            },
+        }
+    }
+}
+
+impl From<Placement> for wayggle_bg::scene::Placement {
+    fn from(placement: Placement) -> Self {
+        Self {
+            effect: placement.effect.map(Into::into),
+            fit: placement.fit.into(),
+            background: placement.background,
+            channel: placement.channel,
+        }
+    }
+}
+
+impl From<Textures> for wayggle_bg::scene::Textures {
+    fn from(textures: Textures) -> Self {
+        Self {
+            cubemap: textures.cubemap,
+            cubemap_channel: textures.cubemap_channel,
+            volume: textures.volume,
+            volume_channel: textures.volume_channel,
+        }
+    }
+}
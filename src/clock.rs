@@ -0,0 +1,125 @@
+//! The value of `u_time`.
+
+use chrono::{DateTime, Local, Timelike as _};
+
+/// What `u_time` counts from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeMode {
+    /// Seconds since the wallpaper started.
+    Elapsed,
+    /// Seconds since local midnight, so restarts carry on where they left off.
+    WallClock,
+}
+
+/// How the shader time is derived from the real one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShaderClock {
+    pub mode: TimeMode,
+    /// Playback speed; 0 freezes the shader at `offset`.
+    pub speed: f64,
+    /// Shader time when the clock reads zero, in seconds.
+    pub offset: f64,
+    /// Period in seconds the time wraps around at, if any.
+    ///
+    /// An `f32` only resolves a few milliseconds after a day and tens of milliseconds
+    /// after a week, so unwrapped time makes animations stutter on long uptimes. The time
+    /// jumps back at every wrap, so the period should be a multiple of the length of the
+    /// shader's loop for the jump not to show.
+    pub wrap: Option<f64>,
+}
+
+impl Default for ShaderClock {
+    fn default() -> Self {
+        ShaderClock {
+            mode: TimeMode::Elapsed,
+            speed: 1.0,
+            offset: 0.0,
+            wrap: None,
+        }
+    }
+}
+
+impl ShaderClock {
+    /// `u_time` after `elapsed` seconds of running, at local time `now`.
+    pub fn time(&self, elapsed: f64, now: &DateTime<Local>) -> f32 {
+        let base = match self.mode {
+            TimeMode::Elapsed => elapsed,
+            TimeMode::WallClock => {
+                now.num_seconds_from_midnight() as f64 + now.nanosecond() as f64 * 1e-9
+            }
+        };
+        // Wrap in f64, before the precision is lost.
+        let time = self.offset + base * self.speed;
+        match self.wrap {
+            Some(period) if period > 0.0 => time.rem_euclid(period) as f32,
+            _ => time as f32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone as _;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, 1, 7, 30, 15).unwrap()
+    }
+
+    fn clock(speed: f64, offset: f64, wrap: Option<f64>) -> ShaderClock {
+        ShaderClock {
+            speed,
+            offset,
+            wrap,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn default_counts_elapsed_seconds_without_wrapping() {
+        let clock = ShaderClock::default();
+        assert_eq!(clock.time(12.5, &now()), 12.5);
+        assert_eq!(clock.time(7200.0, &now()), 7200.0);
+    }
+
+    #[test]
+    fn speed_and_offset() {
+        assert_eq!(clock(0.5, 30.0, None).time(10.0, &now()), 35.0);
+        assert_eq!(clock(-1.0, 100.0, None).time(40.0, &now()), 60.0);
+    }
+
+    #[test]
+    fn zero_speed_freezes_at_offset() {
+        let frozen = clock(0.0, 12.0, None);
+        assert_eq!(frozen.time(0.0, &now()), 12.0);
+        assert_eq!(frozen.time(1000.0, &now()), 12.0);
+    }
+
+    #[test]
+    fn wraps_at_the_period() {
+        let wrapping = clock(1.0, 0.0, Some(60.0));
+        assert_eq!(wrapping.time(59.5, &now()), 59.5);
+        assert_eq!(wrapping.time(60.0, &now()), 0.0);
+        assert_eq!(wrapping.time(61.25, &now()), 1.25);
+        // A period of zero means no wrapping.
+        assert_eq!(clock(1.0, 0.0, Some(0.0)).time(61.25, &now()), 61.25);
+    }
+
+    #[test]
+    fn negative_times_wrap_into_the_period() {
+        let backwards = clock(-1.0, 0.0, Some(60.0));
+        assert_eq!(backwards.time(15.0, &now()), 45.0);
+        assert_eq!(backwards.time(60.0, &now()), 0.0);
+        assert_eq!(clock(1.0, -90.0, Some(60.0)).time(0.0, &now()), 30.0);
+    }
+
+    #[test]
+    fn wall_clock_counts_from_midnight() {
+        let wall_clock = ShaderClock {
+            mode: TimeMode::WallClock,
+            ..Default::default()
+        };
+        let seconds = (7 * 60 + 30) * 60 + 15;
+        assert_eq!(wall_clock.time(3.0, &now()), seconds as f32);
+    }
+}
@@ -0,0 +1,308 @@
+//! Control sockets of running wallpapers, answering the `status` and `reload` subcommands.
+//!
+//! Each wallpaper listens on `$XDG_RUNTIME_DIR/wayggle-bg/<pid>.sock`. A request is a
+//! single line naming the command; the reply is text, after which the wallpaper closes
+//! the connection.
+
+use std::io::{BufRead as _, BufReader, Read as _, Write as _};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+
+/// How long either side waits for the other to send its part.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What a control request asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// Report which shader is shown and why the ones before it failed.
+    Status,
+    /// Read the shader from disk again and switch to it.
+    Reload,
+}
+
+impl ControlCommand {
+    pub fn name(self) -> &'static str {
+        match self {
+            ControlCommand::Status => "status",
+            ControlCommand::Reload => "reload",
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        match line.trim() {
+            "status" => Some(ControlCommand::Status),
+            "reload" => Some(ControlCommand::Reload),
+            _ => None,
+        }
+    }
+}
+
+/// Directory holding the sockets of all running wallpapers of the user.
+pub fn socket_directory() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("wayggle-bg"))
+}
+
+/// The listening end in a running wallpaper. The socket file is removed on drop.
+///
+/// Connections are accepted and their requests read on a background thread, so a client
+/// that is slow to send its request doesn't hold up rendering.
+pub struct ControlSocket {
+    path: PathBuf,
+    requests: mpsc::Receiver<ControlRequest>,
+    /// Readable while requests are waiting: the thread writes a byte for each one.
+    wake: UnixStream,
+    stopped: Arc<AtomicBool>,
+}
+
+impl ControlSocket {
+    /// Listen on the socket of this process in [`socket_directory`].
+    pub fn bind() -> Result<Self> {
+        let directory = socket_directory().ok_or_else(|| {
+            Error::Configuration("XDG_RUNTIME_DIR is not set, no control socket".to_string())
+        })?;
+        std::fs::create_dir_all(&directory).map_err(|e| {
+            Error::io(
+                format!("failed to create control directory {}", directory.display()),
+                e,
+            )
+        })?;
+        Self::bind_at(directory.join(format!("{}.sock", std::process::id())))
+    }
+
+    pub fn bind_at(path: PathBuf) -> Result<Self> {
+        let context = || format!("failed to listen on {}", path.display());
+        // A socket left behind by a crashed process of the same pid.
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).map_err(|e| Error::io(context(), e))?;
+        let (wake, wake_sender) = UnixStream::pair().map_err(|e| Error::io(context(), e))?;
+        wake.set_nonblocking(true)
+            .map_err(|e| Error::io(context(), e))?;
+        let (sender, requests) = mpsc::channel();
+        let stopped = Arc::new(AtomicBool::new(false));
+        std::thread::Builder::new()
+            .name("control".to_string())
+            .spawn({
+                let stopped = stopped.clone();
+                move || serve(listener, sender, wake_sender, &stopped)
+            })
+            .map_err(|e| Error::io(context(), e))?;
+        Ok(ControlSocket {
+            path,
+            requests,
+            wake,
+            stopped,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The next waiting request, if there is one. Doesn't block.
+    pub fn accept(&self) -> Option<ControlRequest> {
+        // Every request sends its byte after the request itself, so none is missed.
+        let mut bytes = [0; 64];
+        while matches!((&self.wake).read(&mut bytes), Ok(read) if read > 0) {}
+        self.requests.try_recv().ok()
+    }
+}
+
+impl AsFd for ControlSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.wake.as_fd()
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        // Wake the thread up from waiting for a connection.
+        let _ = UnixStream::connect(&self.path);
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Accept connections and pass their requests on until the socket is dropped.
+fn serve(
+    listener: UnixListener,
+    requests: mpsc::Sender<ControlRequest>,
+    mut wake: UnixStream,
+    stopped: &AtomicBool,
+) {
+    for stream in listener.incoming() {
+        if stopped.load(Ordering::Relaxed) {
+            return;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!("Failed to accept a control connection: {}", e);
+                continue;
+            }
+        };
+        match read_request(&stream) {
+            Ok(command) => {
+                if requests.send(ControlRequest { command, stream }).is_err() {
+                    return;
+                }
+                let _ = wake.write_all(&[0]);
+            }
+            Err(e) => tracing::warn!("Ignoring control request: {}", e),
+        }
+    }
+}
+
+fn read_request(stream: &UnixStream) -> std::io::Result<ControlCommand> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(stream).take(64).read_line(&mut line)?;
+    ControlCommand::parse(&line).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("unknown command {:?}", line.trim()),
+        )
+    })
+}
+
+/// A request accepted by a [`ControlSocket`], waiting for its reply.
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    stream: UnixStream,
+}
+
+impl ControlRequest {
+    pub fn reply(mut self, reply: &str) {
+        if let Err(e) = self.stream.write_all(reply.as_bytes()) {
+            tracing::warn!("Failed to answer a {} request: {}", self.command.name(), e);
+        }
+    }
+}
+
+/// Send `command` to the wallpaper listening on `path` and return its reply.
+pub fn send_to(path: &Path, command: ControlCommand) -> std::io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream.write_all(format!("{}\n", command.name()).as_bytes())?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+/// Send `command` to every running wallpaper. Returns the socket of each with its reply.
+pub fn send(command: ControlCommand) -> Result<Vec<(PathBuf, String)>> {
+    let directory = socket_directory().ok_or_else(|| {
+        Error::Configuration("XDG_RUNTIME_DIR is not set, can't find wallpapers".to_string())
+    })?;
+    let entries = match std::fs::read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(Error::io(
+                format!("failed to list {}", directory.display()),
+                e,
+            ));
+        }
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "sock")
+        })
+        .collect();
+    paths.sort();
+    let mut replies = Vec::new();
+    for path in paths {
+        match send_to(&path, command) {
+            Ok(reply) => replies.push((path, reply)),
+            // Left behind by a wallpaper that didn't exit cleanly.
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+                let _ = std::fs::remove_file(&path);
+            }
+            Err(e) => tracing::warn!("No reply from {}: {}", path.display(), e),
+        }
+    }
+    Ok(replies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            ControlCommand::parse("status\n"),
+            Some(ControlCommand::Status)
+        );
+        assert_eq!(
+            ControlCommand::parse("reload"),
+            Some(ControlCommand::Reload)
+        );
+        assert_eq!(ControlCommand::parse("restart\n"), None);
+        assert_eq!(ControlCommand::parse(""), None);
+    }
+
+    #[test]
+    fn answers_requests() {
+        let path =
+            std::env::temp_dir().join(format!("wayggle-bg-control-{}.sock", std::process::id()));
+        let socket = ControlSocket::bind_at(path.clone()).unwrap();
+        assert!(socket.accept().is_none());
+
+        let client = std::thread::spawn({
+            let path = path.clone();
+            move || send_to(&path, ControlCommand::Status).unwrap()
+        });
+        let request = loop {
+            if let Some(request) = socket.accept() {
+                break request;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        };
+        assert_eq!(request.command, ControlCommand::Status);
+        request.reply("showing the shader\n");
+        assert_eq!(client.join().unwrap(), "showing the shader\n");
+
+        drop(socket);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn silent_clients_dont_block() {
+        let path = std::env::temp_dir().join(format!(
+            "wayggle-bg-control-silent-{}.sock",
+            std::process::id()
+        ));
+        let socket = ControlSocket::bind_at(path.clone()).unwrap();
+        let silent = UnixStream::connect(&path).unwrap();
+        let started = std::time::Instant::now();
+        assert!(socket.accept().is_none());
+        assert!(started.elapsed() < TIMEOUT / 2);
+
+        // Hanging up is an empty request, after which the next one is read.
+        drop(silent);
+        let client = std::thread::spawn({
+            let path = path.clone();
+            move || send_to(&path, ControlCommand::Reload).unwrap()
+        });
+        let request = loop {
+            if let Some(request) = socket.accept() {
+                break request;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        };
+        assert_eq!(request.command, ControlCommand::Reload);
+        request.reply("reloaded\n");
+        assert_eq!(client.join().unwrap(), "reloaded\n");
+    }
+}
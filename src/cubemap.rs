@@ -0,0 +1,315 @@
+//! Cubemaps, e.g. environment maps for reflections and skies, as `samplerCube` channels.
+//!
+//! A cubemap is made of six square face images in the order +X, -X, +Y, -Y, +Z, -Z, each
+//! with up at the top as seen from the centre, or of one equirectangular panorama that
+//! is converted into faces.
+
+use std::f32::consts::PI;
+use std::path::PathBuf;
+
+use glow::HasContext;
+use image::RgbaImage;
+
+use crate::channels::{ChannelKind, ChannelSource};
+use crate::error::{Error, Result};
+use crate::image::read_image;
+
+/// A cubemap as a channel texture.
+pub struct CubemapChannel {
+    /// Square faces of the same size, in the order of the `TEXTURE_CUBE_MAP_POSITIVE_X`
+    /// and following targets.
+    faces: Vec<RgbaImage>,
+}
+
+impl CubemapChannel {
+    /// Read six face images, or a single equirectangular image with the -Z direction in
+    /// its centre.
+    pub fn open(paths: &[PathBuf]) -> Result<Self> {
+        let faces = match paths {
+            [path] => {
+                let panorama = read_image(path)?;
+                let size = (panorama.width() / 4).max(1);
+                tracing::info!(
+                    "Converting the {}x{} panorama {} into {}x{} cubemap faces",
+                    panorama.width(),
+                    panorama.height(),
+                    path.display(),
+                    size,
+                    size
+                );
+                (0..6)
+                    .map(|face| equirectangular_face(&panorama, face, size))
+                    .collect()
+            }
+            [_, _, _, _, _, _] => {
+                let faces = paths
+                    .iter()
+                    .map(|path| read_image(path))
+                    .collect::<Result<Vec<_>>>()?;
+                check_faces(paths, &faces)?;
+                faces
+            }
+            _ => {
+                return Err(Error::Configuration(format!(
+                    "a cubemap needs six face images or one equirectangular image, got {}",
+                    paths.len()
+                )));
+            }
+        };
+        Ok(CubemapChannel { faces })
+    }
+}
+
+/// Make sure the faces are square and of the same size.
+fn check_faces(paths: &[PathBuf], faces: &[RgbaImage]) -> Result<()> {
+    let size = faces[0].width();
+    for (path, face) in paths.iter().zip(faces) {
+        if face.dimensions() != (size, size) {
+            return Err(Error::Configuration(format!(
+                "cubemap face {} is {}x{}, expected {}x{} like {}",
+                path.display(),
+                face.width(),
+                face.height(),
+                size,
+                size,
+                paths[0].display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Direction from the centre of the cube through the point `(sc, tc)` of `face`, both
+/// from -1 to 1, as laid out by the `TEXTURE_CUBE_MAP` targets.
+fn face_direction(face: usize, sc: f32, tc: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -tc, -sc],
+        1 => [-1.0, -tc, sc],
+        2 => [sc, 1.0, tc],
+        3 => [sc, -1.0, -tc],
+        4 => [sc, -tc, 1.0],
+        _ => [-sc, -tc, -1.0],
+    }
+}
+
+/// Render one face of the cube seen from the centre of the `panorama`.
+fn equirectangular_face(panorama: &RgbaImage, face: usize, size: u32) -> RgbaImage {
+    RgbaImage::from_fn(size, size, |x, y| {
+        let side = |i: u32| 2.0 * (i as f32 + 0.5) / size as f32 - 1.0;
+        let [dx, dy, dz] = face_direction(face, side(x), side(y));
+        let length = (dx * dx + dy * dy + dz * dz).sqrt();
+        let u = 0.5 + dx.atan2(-dz) / (2.0 * PI);
+        let v = (dy / length).clamp(-1.0, 1.0).acos() / PI;
+        image::Rgba(sample_bilinear(panorama, u, v))
+    })
+}
+
+/// Sample `image` at `(u, v)` from 0 to 1, wrapping around horizontally.
+fn sample_bilinear(image: &RgbaImage, u: f32, v: f32) -> [u8; 4] {
+    let (width, height) = image.dimensions();
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let column = |x: f32| (x as i64).rem_euclid(width as i64) as u32;
+    let row = |y: f32| (y as u32).min(height - 1);
+    let corners = [
+        (
+            image.get_pixel(column(x0), row(y0)),
+            (1.0 - fx) * (1.0 - fy),
+        ),
+        (image.get_pixel(column(x0 + 1.0), row(y0)), fx * (1.0 - fy)),
+        (image.get_pixel(column(x0), row(y0 + 1.0)), (1.0 - fx) * fy),
+        (image.get_pixel(column(x0 + 1.0), row(y0 + 1.0)), fx * fy),
+    ];
+    std::array::from_fn(|channel| {
+        let value: f32 = corners
+            .iter()
+            .map(|(pixel, weight)| pixel[channel] as f32 * weight)
+            .sum();
+        value.round().clamp(0.0, 255.0) as u8
+    })
+}
+
+impl ChannelSource for CubemapChannel {
+    fn init_texture(&mut self, gl: &glow::Context, _texture: glow::Texture) {
+        let max_size =
+            unsafe { gl.get_parameter_i32(glow::MAX_CUBE_MAP_TEXTURE_SIZE) }.max(1) as u32;
+        let size = self.faces[0].width();
+        if size > max_size {
+            tracing::warn!(
+                "Scaling the {}x{} cubemap faces down to the maximum size of {}",
+                size,
+                size,
+                max_size
+            );
+            for face in &mut self.faces {
+                *face = image::imageops::resize(
+                    face,
+                    max_size,
+                    max_size,
+                    image::imageops::FilterType::Triangle,
+                );
+            }
+        }
+
+        unsafe {
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+            for (target, face) in (glow::TEXTURE_CUBE_MAP_POSITIVE_X..).zip(&self.faces) {
+                gl.tex_image_2d(
+                    target,
+                    0,
+                    glow::RGBA8 as i32,
+                    face.width() as i32,
+                    face.height() as i32,
+                    0,
+                    glow::RGBA,
+                    glow::UNSIGNED_BYTE,
+                    glow::PixelUnpackData::Slice(Some(face.as_raw())),
+                );
+            }
+            gl.generate_mipmap(glow::TEXTURE_CUBE_MAP);
+            gl.tex_parameter_i32(
+                glow::TEXTURE_CUBE_MAP,
+                glow::TEXTURE_MIN_FILTER,
+                glow::LINEAR_MIPMAP_LINEAR as i32,
+            );
+        }
+    }
+
+    fn kind(&self) -> ChannelKind {
+        ChannelKind::Cube
+    }
+
+    fn resolution(&self) -> [f32; 3] {
+        let size = self.faces[0].width() as f32;
+        [size, size, 1.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The face and face coordinates GL samples for `direction`, following the table of
+    /// cube map face selection in the OpenGL ES 3.0 specification.
+    fn gl_face([rx, ry, rz]: [f32; 3]) -> (usize, f32, f32) {
+        let (face, sc, tc, ma) = if rx.abs() >= ry.abs() && rx.abs() >= rz.abs() {
+            if rx > 0.0 {
+                (0, -rz, -ry, rx)
+            } else {
+                (1, rz, -ry, rx)
+            }
+        } else if ry.abs() >= rz.abs() {
+            if ry > 0.0 {
+                (2, rx, rz, ry)
+            } else {
+                (3, rx, -rz, ry)
+            }
+        } else if rz > 0.0 {
+            (4, rx, -ry, rz)
+        } else {
+            (5, -rx, -ry, rz)
+        };
+        (face, sc / ma.abs(), tc / ma.abs())
+    }
+
+    #[test]
+    fn face_centres_point_along_the_axes() {
+        let axes = [
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+        ];
+        for (face, axis) in axes.iter().enumerate() {
+            assert_eq!(face_direction(face, 0.0, 0.0).map(|c| c + 0.0), *axis);
+        }
+    }
+
+    #[test]
+    fn face_directions_match_gl() {
+        for face in 0..6 {
+            for (sc, tc) in [(0.5, 0.0), (0.0, 0.5), (-0.75, 0.25), (0.9, -0.9)] {
+                assert_eq!(
+                    gl_face(face_direction(face, sc, tc)),
+                    (face, sc, tc),
+                    "face {}",
+                    face
+                );
+            }
+        }
+    }
+
+    const RED: image::Rgba<u8> = image::Rgba([255, 0, 0, 255]);
+    const GREEN: image::Rgba<u8> = image::Rgba([0, 255, 0, 255]);
+    const BLUE: image::Rgba<u8> = image::Rgba([0, 0, 255, 255]);
+    const WHITE: image::Rgba<u8> = image::Rgba([255, 255, 255, 255]);
+    const GREY: image::Rgba<u8> = image::Rgba([64, 64, 64, 255]);
+
+    /// A 16x8 panorama with a white top row, a grey bottom row, red and blue left and
+    /// right edges and green in the middle.
+    fn panorama() -> RgbaImage {
+        RgbaImage::from_fn(16, 8, |x, y| match (x, y) {
+            (_, 0) => WHITE,
+            (_, 7) => GREY,
+            (0, _) => RED,
+            (15, _) => BLUE,
+            (7 | 8, _) => GREEN,
+            _ => image::Rgba([0, 0, 0, 255]),
+        })
+    }
+
+    #[test]
+    fn panoramas_face_minus_z_in_their_centre() {
+        // One pixel faces sample exactly the centre direction of each face.
+        let centre = |face| *equirectangular_face(&panorama(), face, 1).get_pixel(0, 0);
+        assert_eq!(centre(5), GREEN);
+        // +Z looks at the seam, where both edges blend.
+        assert_eq!(centre(4), image::Rgba([128, 0, 128, 255]));
+        assert_eq!(centre(0), image::Rgba([0, 0, 0, 255]));
+        assert_eq!(centre(1), image::Rgba([0, 0, 0, 255]));
+        assert_eq!(centre(2), WHITE);
+        assert_eq!(centre(3), GREY);
+    }
+
+    #[test]
+    fn sampling_wraps_around_horizontally() {
+        let row = RgbaImage::from_fn(4, 1, |x, _| if x == 0 { RED } else { BLUE });
+        let edge = image::Rgba([128, 0, 128, 255]).0;
+        assert_eq!(sample_bilinear(&row, 0.0, 0.5), edge);
+        assert_eq!(sample_bilinear(&row, 1.0, 0.5), edge);
+        assert_eq!(sample_bilinear(&row, 0.125, 0.5), RED.0);
+        assert_eq!(sample_bilinear(&row, 0.625, 0.5), BLUE.0);
+        // Rows don't wrap: past the top and bottom is the edge row.
+        let column = RgbaImage::from_fn(1, 2, |_, y| if y == 0 { RED } else { BLUE });
+        assert_eq!(sample_bilinear(&column, 0.5, 0.0), RED.0);
+        assert_eq!(sample_bilinear(&column, 0.5, 1.0), BLUE.0);
+    }
+
+    #[test]
+    fn faces_must_be_square_and_alike() {
+        let paths: Vec<PathBuf> = (0..6)
+            .map(|i| PathBuf::from(format!("{}.png", i)))
+            .collect();
+        let faces = |odd: Option<(u32, u32)>| -> Vec<RgbaImage> {
+            (0..6)
+                .map(|i| match odd {
+                    Some((width, height)) if i == 3 => RgbaImage::new(width, height),
+                    _ => RgbaImage::new(4, 4),
+                })
+                .collect()
+        };
+        assert!(check_faces(&paths, &faces(None)).is_ok());
+        for odd in [(4, 2), (8, 8)] {
+            match check_faces(&paths, &faces(Some(odd))) {
+                Err(Error::Configuration(message)) => {
+                    assert!(message.contains("3.png"), "{}", message)
+                }
+                result => panic!("{:?} for {:?}", result, odd),
+            }
+        }
+    }
+}
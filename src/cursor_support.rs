@@ -1,25 +1,442 @@
-pub fn hyprland_get_cursor() -> (f32, f32) {
-    // Get the cursor position from the Hyprland API
-    let output = std::process::Command::new("hyprctl")
-        .arg("cursorpos")
-        .output()
-        .expect("Failed to execute hyprctl command");
-    if !output.status.success() {
-        tracing::error!(
-            "Failed to get cursor position: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
-        std::process::exit(1);
-    }
-
-    // format is: "x, y"
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let parts: Vec<&str> = stdout.trim().split(", ").collect();
-    if parts.len() != 2 {
-        tracing::error!("Unexpected output format from hyprctl: {}", stdout);
-        std::process::exit(1);
-    }
-    let x: f32 = parts[0].parse().expect("Failed to parse x coordinate");
-    let y: f32 = parts[1].parse().expect("Failed to parse y coordinate");
-    (x, y)
+mod hyprland;
+mod pointer;
+mod smoothing;
+
+pub use hyprland::HyprlandCursor;
+pub use pointer::PointerCursor;
+pub use smoothing::{CursorFilter, Smoothing};
+
+use std::ffi::OsString;
+use std::rc::Rc;
+
+use wayland_client::protocol::wl_pointer;
+
+use crate::error::Result;
+
+/// Where the cursor position comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorSupportKind {
+    /// Detect the compositor from the environment.
+    Auto,
+    Hyprland,
+    /// Track the pointer while it is over the wallpaper. Works on any compositor, and is
+    /// what sway, niri and river get, as their IPC doesn't report the cursor position.
+    Pointer,
+    Disabled,
+}
+
+/// A cursor position in logical coordinates with the origin at the top left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CursorPosition {
+    /// Relative to the global compositor layout spanning all outputs.
+    Global(f32, f32),
+    /// Relative to the wallpaper surface.
+    Surface(f32, f32),
+}
+
+/// Logical position and size of an output in the global compositor layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OutputGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Map a cursor position to the pixel coordinates of a surface, with the origin at the
+/// bottom left as in `gl_FragCoord`.
+///
+/// `output` is where the surface is shown. `surface_size` is the logical size of the
+/// surface and `resolution` the size of its buffer in pixels. Returns `None` when the
+/// cursor is outside of the surface, e.g. on another monitor.
+///
+/// The wallpaper doesn't handle `PreferredBufferScale` yet, so its buffer has the
+/// logical size and the scale is 1 even on HiDPI outputs; positions are then in logical
+/// pixels, like `u_resolution`.
+pub fn to_surface_pixels(
+    position: CursorPosition,
+    output: Option<&OutputGeometry>,
+    surface_size: (u32, u32),
+    resolution: (u32, u32),
+) -> Option<(f32, f32)> {
+    let (x, y) = match position {
+        CursorPosition::Global(x, y) => match output {
+            Some(output) => (x - output.x as f32, y - output.y as f32),
+            // Without output geometry the surface is assumed to be at the layout origin.
+            None => (x, y),
+        },
+        CursorPosition::Surface(x, y) => (x, y),
+    };
+    let (width, height) = (surface_size.0 as f32, surface_size.1 as f32);
+    if !(0.0..width).contains(&x) || !(0.0..height).contains(&y) {
+        return None;
+    }
+    let scale_x = resolution.0 as f32 / width;
+    let scale_y = resolution.1 as f32 / height;
+    Some((x * scale_x, (height - y) * scale_y))
+}
+
+/// Mouse state following the semantics of Shadertoy's `iMouse`.
+///
+/// `xy` is the position while a button is held down and stays at the last drag position
+/// afterwards. `zw` is the position of the last click; `z` is positive while the button is
+/// held down and `w` is positive only during the frame of the click.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShadertoyMouse {
+    position: (f32, f32),
+    click: (f32, f32),
+    down: bool,
+    clicked: bool,
+}
+
+impl ShadertoyMouse {
+    /// Advance the state by one frame. `cursor` is in surface pixels, `None` while outside of
+    /// the surface. When the button state is unknown, `xy` follows the cursor as if dragging.
+    pub fn update(&mut self, cursor: Option<(f32, f32)>, pressed: Option<bool>) {
+        self.clicked = false;
+        match pressed {
+            None => {
+                if let Some(cursor) = cursor {
+                    self.position = cursor;
+                }
+            }
+            Some(true) => {
+                let Some(cursor) = cursor else {
+                    return;
+                };
+                if !self.down {
+                    self.down = true;
+                    self.clicked = true;
+                    self.click = cursor;
+                }
+                self.position = cursor;
+            }
+            Some(false) => {
+                self.down = false;
+            }
+        }
+    }
+
+    /// The value of `iMouse`.
+    pub fn uniform(&self) -> [f32; 4] {
+        let z = if self.down {
+            self.click.0
+        } else {
+            -self.click.0
+        };
+        let w = if self.clicked {
+            self.click.1
+        } else {
+            -self.click.1
+        };
+        [self.position.0, self.position.1, z, w]
+    }
+}
+
+/// Source of the cursor position fed to `u_mouse`.
+pub trait CursorProvider {
+    /// The current cursor position, or `None` while it is unknown.
+    fn position(&self) -> Option<CursorPosition>;
+
+    /// Whether a mouse button is held down, or `None` if the provider can't tell.
+    fn button_pressed(&self) -> Option<bool> {
+        None
+    }
+
+    /// Whether the provider needs the `wl_pointer` events of the wallpaper surface.
+    fn uses_pointer_events(&self) -> bool {
+        false
+    }
+
+    /// Receives the `wl_pointer` events of the wallpaper surface if `uses_pointer_events`.
+    fn handle_pointer_event(&self, _event: &wl_pointer::Event) {}
+
+    /// Called when the wallpaper stops rendering, e.g. while the session is idle, and
+    /// when it starts again. Providers polling in the background should stop meanwhile.
+    fn set_paused(&self, _paused: bool) {}
+}
+
+/// The compositor a wallpaper runs under, as far as the environment tells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compositor {
+    Hyprland,
+    Sway,
+    Niri,
+    River,
+    Unknown,
+}
+
+impl Compositor {
+    /// Detect the running compositor from the environment it sets for its clients.
+    pub fn detect() -> Self {
+        Self::detect_with(|variable| std::env::var_os(variable))
+    }
+
+    /// [`Compositor::detect`] with the environment variables read through `var`.
+    fn detect_with(var: impl Fn(&str) -> Option<OsString>) -> Self {
+        let desktop = var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+        // XDG_CURRENT_DESKTOP is a colon separated list, e.g. `sway:wlroots`.
+        let desktop_is = |name: &str| {
+            desktop
+                .to_string_lossy()
+                .split(':')
+                .any(|entry| entry.eq_ignore_ascii_case(name))
+        };
+        if var("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            Self::Hyprland
+        } else if var("SWAYSOCK").is_some() || desktop_is("sway") {
+            Self::Sway
+        } else if var("NIRI_SOCKET").is_some() || desktop_is("niri") {
+            Self::Niri
+        } else if desktop_is("river") {
+            Self::River
+        } else {
+            Self::Unknown
+        }
+    }
+
+    /// The best cursor support for this compositor. Sway's and niri's IPC have no request
+    /// reporting the pointer position and river has no IPC for it at all, so they, like
+    /// unknown compositors, get `Pointer`.
+    pub fn cursor_support(self) -> CursorSupportKind {
+        match self {
+            Self::Hyprland => CursorSupportKind::Hyprland,
+            Self::Sway | Self::Niri | Self::River | Self::Unknown => CursorSupportKind::Pointer,
+        }
+    }
+}
+
+/// Create the cursor provider for `kind`, `None` when cursor support is disabled.
+///
+/// A compositor that was asked for explicitly must be reachable; an auto-detected one
+/// falls back to `wl_pointer` events.
+pub fn create_provider(kind: CursorSupportKind) -> Result<Option<Rc<dyn CursorProvider>>> {
+    match kind {
+        CursorSupportKind::Auto => {
+            let compositor = Compositor::detect();
+            let detected = compositor.cursor_support();
+            if matches!(
+                compositor,
+                Compositor::Sway | Compositor::Niri | Compositor::River
+            ) {
+                tracing::info!(
+                    "Detected {:?}, whose IPC doesn't report the cursor position; \
+                     the cursor is only known while it is over the wallpaper",
+                    compositor
+                );
+            }
+            tracing::info!("Detected cursor support: {:?}", detected);
+            create_provider(detected).or_else(|e| {
+                tracing::warn!("{}; falling back to wl_pointer cursor support", e);
+                Ok(Some(Rc::new(PointerCursor::new())))
+            })
+        }
+        CursorSupportKind::Hyprland => Ok(Some(Rc::new(HyprlandCursor::from_env()?))),
+        CursorSupportKind::Pointer => Ok(Some(Rc::new(PointerCursor::new()))),
+        CursorSupportKind::Disabled => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_compositor_from_the_environment() {
+        let detect = |set: &[(&str, &str)]| {
+            Compositor::detect_with(|variable| {
+                set.iter()
+                    .find(|(name, _)| *name == variable)
+                    .map(|(_, value)| OsString::from(value))
+            })
+        };
+
+        assert_eq!(
+            detect(&[("HYPRLAND_INSTANCE_SIGNATURE", "abc_123")]),
+            Compositor::Hyprland
+        );
+        assert_eq!(
+            detect(&[
+                ("HYPRLAND_INSTANCE_SIGNATURE", "abc_123"),
+                ("XDG_CURRENT_DESKTOP", "sway"),
+            ]),
+            Compositor::Hyprland
+        );
+        assert_eq!(
+            detect(&[("SWAYSOCK", "/run/user/1000/sway-ipc.sock")]),
+            Compositor::Sway
+        );
+        assert_eq!(
+            detect(&[("XDG_CURRENT_DESKTOP", "sway:wlroots")]),
+            Compositor::Sway
+        );
+        assert_eq!(
+            detect(&[("NIRI_SOCKET", "/run/user/1000/niri.sock")]),
+            Compositor::Niri
+        );
+        assert_eq!(
+            detect(&[("XDG_CURRENT_DESKTOP", "river")]),
+            Compositor::River
+        );
+        assert_eq!(
+            detect(&[("XDG_CURRENT_DESKTOP", "GNOME")]),
+            Compositor::Unknown
+        );
+        assert_eq!(detect(&[]), Compositor::Unknown);
+    }
+
+    #[test]
+    fn only_hyprland_reports_the_cursor_over_ipc() {
+        assert_eq!(
+            Compositor::Hyprland.cursor_support(),
+            CursorSupportKind::Hyprland
+        );
+        for compositor in [
+            Compositor::Sway,
+            Compositor::Niri,
+            Compositor::River,
+            Compositor::Unknown,
+        ] {
+            assert_eq!(compositor.cursor_support(), CursorSupportKind::Pointer);
+        }
+    }
+
+    #[test]
+    fn shadertoy_mouse_follows_a_drag() {
+        let mut mouse = ShadertoyMouse::default();
+        mouse.update(Some((10.0, 20.0)), Some(false));
+        assert_eq!(mouse.uniform(), [0.0, 0.0, 0.0, 0.0]);
+
+        // The frame of the click: both z and w positive.
+        mouse.update(Some((10.0, 20.0)), Some(true));
+        assert_eq!(mouse.uniform(), [10.0, 20.0, 10.0, 20.0]);
+        // Held: xy follows, z stays positive, w turns negative.
+        mouse.update(Some((15.0, 25.0)), Some(true));
+        assert_eq!(mouse.uniform(), [15.0, 25.0, 10.0, -20.0]);
+        mouse.update(Some((15.0, 25.0)), Some(true));
+        assert_eq!(mouse.uniform(), [15.0, 25.0, 10.0, -20.0]);
+        // Released: xy stays at the last drag position, z turns negative.
+        mouse.update(Some((30.0, 40.0)), Some(false));
+        assert_eq!(mouse.uniform(), [15.0, 25.0, -10.0, -20.0]);
+
+        // The next click starts over.
+        mouse.update(Some((30.0, 40.0)), Some(true));
+        assert_eq!(mouse.uniform(), [30.0, 40.0, 30.0, 40.0]);
+    }
+
+    #[test]
+    fn shadertoy_mouse_without_buttons_hovers() {
+        let mut mouse = ShadertoyMouse::default();
+        mouse.update(Some((10.0, 20.0)), None);
+        assert_eq!(mouse.uniform(), [10.0, 20.0, 0.0, 0.0]);
+        // Leaving the surface keeps the last position.
+        mouse.update(None, None);
+        assert_eq!(mouse.uniform(), [10.0, 20.0, 0.0, 0.0]);
+
+        // Clicks made before keep their negative zw.
+        mouse.update(Some((5.0, 6.0)), Some(true));
+        mouse.update(Some((5.0, 6.0)), Some(false));
+        mouse.update(Some((50.0, 60.0)), None);
+        assert_eq!(mouse.uniform(), [50.0, 60.0, -5.0, -6.0]);
+    }
+
+    #[test]
+    fn shadertoy_mouse_ignores_presses_outside_the_surface() {
+        let mut mouse = ShadertoyMouse::default();
+        mouse.update(None, Some(true));
+        assert_eq!(mouse.uniform(), [0.0, 0.0, 0.0, 0.0]);
+
+        // A drag leaving the surface stays held where it left.
+        mouse.update(Some((10.0, 20.0)), Some(true));
+        mouse.update(None, Some(true));
+        assert_eq!(mouse.uniform(), [10.0, 20.0, 10.0, -20.0]);
+        mouse.update(None, Some(false));
+        assert_eq!(mouse.uniform(), [10.0, 20.0, -10.0, -20.0]);
+    }
+
+    const OUTPUT: OutputGeometry = OutputGeometry {
+        x: 1920,
+        y: 0,
+        width: 1280,
+        height: 720,
+    };
+
+    #[test]
+    fn flips_y_to_the_bottom_left() {
+        let size = (1280, 720);
+        let pixels = |position| to_surface_pixels(position, None, size, size);
+        assert_eq!(
+            pixels(CursorPosition::Surface(0.0, 0.0)),
+            Some((0.0, 720.0))
+        );
+        assert_eq!(
+            pixels(CursorPosition::Surface(100.0, 700.0)),
+            Some((100.0, 20.0))
+        );
+    }
+
+    #[test]
+    fn places_global_positions_on_the_output() {
+        let pixels = |x, y| {
+            to_surface_pixels(
+                CursorPosition::Global(x, y),
+                Some(&OUTPUT),
+                (1280, 720),
+                (1280, 720),
+            )
+        };
+        assert_eq!(pixels(1920.0, 0.0), Some((0.0, 720.0)));
+        assert_eq!(pixels(2000.0, 220.0), Some((80.0, 500.0)));
+        // Without the geometry of the output, it is taken to be at the origin.
+        assert_eq!(
+            to_surface_pixels(
+                CursorPosition::Global(80.0, 220.0),
+                None,
+                (1280, 720),
+                (1280, 720)
+            ),
+            Some((80.0, 500.0))
+        );
+    }
+
+    #[test]
+    fn scales_to_the_buffer_size() {
+        let pixels = |position| to_surface_pixels(position, None, (1280, 720), (2560, 1440));
+        assert_eq!(
+            pixels(CursorPosition::Surface(100.0, 200.0)),
+            Some((200.0, 1040.0))
+        );
+        let pixels = |position| to_surface_pixels(position, None, (1280, 720), (640, 360));
+        assert_eq!(
+            pixels(CursorPosition::Surface(100.0, 200.0)),
+            Some((50.0, 260.0))
+        );
+    }
+
+    #[test]
+    fn positions_outside_the_output_are_none() {
+        let pixels = |x, y| {
+            to_surface_pixels(
+                CursorPosition::Global(x, y),
+                Some(&OUTPUT),
+                (1280, 720),
+                (1280, 720),
+            )
+        };
+        // On the monitor to the left, below, and just past the edges.
+        assert_eq!(pixels(100.0, 100.0), None);
+        assert_eq!(pixels(2000.0, 800.0), None);
+        assert_eq!(pixels(1920.0 + 1280.0, 100.0), None);
+        assert_eq!(pixels(2000.0, 720.0), None);
+        assert_eq!(pixels(1919.5, 100.0), None);
+        assert_eq!(
+            to_surface_pixels(
+                CursorPosition::Surface(-1.0, 10.0),
+                None,
+                (1280, 720),
+                (1280, 720)
+            ),
+            None
+        );
+    }
 }
@@ -0,0 +1,257 @@
+use std::io::{Read as _, Write as _};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread::Thread;
+use std::time::Duration;
+
+use super::{CursorPosition, CursorProvider};
+use crate::error::{Error, Result};
+
+/// Delay between two cursor position requests to Hyprland.
+const HYPRLAND_POLL_INTERVAL: Duration = Duration::from_millis(8);
+/// Delay before retrying after Hyprland failed to answer, and between checks whether the
+/// cursor was dropped while paused.
+const HYPRLAND_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+const HYPRLAND_SOCKET_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Cursor position polled from the Hyprland IPC socket on a background thread.
+///
+/// The thread keeps the last known position in a cache, so reading it never blocks the
+/// render loop. It stops polling while paused, and for good once the `HyprlandCursor` is
+/// dropped.
+pub struct HyprlandCursor {
+    // Both coordinates as f32 bits, x in the upper half.
+    position: Arc<AtomicU64>,
+    paused: Arc<AtomicBool>,
+    thread: Thread,
+}
+
+impl HyprlandCursor {
+    /// Start polling the socket of the Hyprland instance this process runs in.
+    pub fn from_env() -> Result<Self> {
+        let socket_path = hyprland_socket_path()?;
+        tracing::info!("Using Hyprland socket {}", socket_path.display());
+        Self::spawn(socket_path)
+    }
+
+    pub fn spawn(socket_path: PathBuf) -> Result<Self> {
+        // NaN marks a position that hasn't been received yet.
+        let position = Arc::new(AtomicU64::new(pack(f32::NAN, f32::NAN)));
+        let paused = Arc::new(AtomicBool::new(false));
+        let weak_position = Arc::downgrade(&position);
+        let thread_paused = paused.clone();
+        let thread = std::thread::Builder::new()
+            .name("hyprland-cursor".to_string())
+            .spawn(move || poll_hyprland(&socket_path, weak_position, &thread_paused))
+            .map_err(|e| Error::io("failed to spawn the Hyprland cursor thread", e))?
+            .thread()
+            .clone();
+        Ok(HyprlandCursor {
+            position,
+            paused,
+            thread,
+        })
+    }
+}
+
+impl Drop for HyprlandCursor {
+    fn drop(&mut self) {
+        // Let a paused thread notice it is no longer needed.
+        self.thread.unpark();
+    }
+}
+
+impl CursorProvider for HyprlandCursor {
+    fn position(&self) -> Option<CursorPosition> {
+        let (x, y) = unpack(self.position.load(Ordering::Relaxed));
+        (!x.is_nan()).then_some(CursorPosition::Global(x, y))
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+        if !paused {
+            self.thread.unpark();
+        }
+    }
+}
+
+fn poll_hyprland(socket_path: &Path, position: Weak<AtomicU64>, paused: &AtomicBool) {
+    let mut failing = false;
+    loop {
+        let Some(position) = position.upgrade() else {
+            tracing::debug!("Hyprland cursor dropped, stopping to poll");
+            return;
+        };
+        if paused.load(Ordering::Relaxed) {
+            drop(position);
+            std::thread::park_timeout(HYPRLAND_RETRY_INTERVAL);
+            continue;
+        }
+        match query_cursor_pos(socket_path) {
+            Ok((x, y)) => {
+                if failing {
+                    tracing::info!("Hyprland socket is answering again");
+                    failing = false;
+                }
+                position.store(pack(x, y), Ordering::Relaxed);
+                drop(position);
+                std::thread::sleep(HYPRLAND_POLL_INTERVAL);
+            }
+            Err(e) => {
+                if !failing {
+                    tracing::warn!("Failed to get cursor position from Hyprland: {}", e);
+                    failing = true;
+                }
+                drop(position);
+                std::thread::sleep(HYPRLAND_RETRY_INTERVAL);
+            }
+        }
+    }
+}
+
+/// Locate the command socket of the running Hyprland instance.
+///
+/// Hyprland >= 0.40 keeps its sockets in `$XDG_RUNTIME_DIR/hypr`, older versions in `/tmp/hypr`.
+pub fn hyprland_socket_path() -> Result<PathBuf> {
+    let signature = std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").ok_or_else(|| {
+        Error::CursorProvider(
+            "HYPRLAND_INSTANCE_SIGNATURE is not set; is Hyprland running?".to_string(),
+        )
+    })?;
+    let mut candidates = Vec::new();
+    if let Some(runtime_dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        candidates.push(PathBuf::from(runtime_dir).join("hypr"));
+    }
+    candidates.push(PathBuf::from("/tmp/hypr"));
+    candidates
+        .into_iter()
+        .map(|dir| dir.join(&signature).join(".socket.sock"))
+        .find(|path| path.exists())
+        .ok_or_else(|| {
+            Error::CursorProvider(format!(
+                "Hyprland socket not found for instance {:?}",
+                signature
+            ))
+        })
+}
+
+/// Ask Hyprland for the cursor position in global layout coordinates.
+pub fn query_cursor_pos(socket_path: &Path) -> std::io::Result<(f32, f32)> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    stream.set_read_timeout(Some(HYPRLAND_SOCKET_TIMEOUT))?;
+    stream.set_write_timeout(Some(HYPRLAND_SOCKET_TIMEOUT))?;
+    stream.write_all(b"cursorpos")?;
+    // Hyprland closes the connection once the reply is written.
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    parse_cursor_pos(&response).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("unexpected cursorpos reply: {:?}", response),
+        )
+    })
+}
+
+/// Parse the reply to `cursorpos`, formatted as `"x, y"`.
+pub fn parse_cursor_pos(response: &str) -> Option<(f32, f32)> {
+    let (x, y) = response.trim().split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+fn pack(x: f32, y: f32) -> u64 {
+    ((x.to_bits() as u64) << 32) | y.to_bits() as u64
+}
+
+fn unpack(bits: u64) -> (f32, f32) {
+    (
+        f32::from_bits((bits >> 32) as u32),
+        f32::from_bits(bits as u32),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::time::Instant;
+
+    /// A fake Hyprland socket answering every request with `reply`, in a fresh directory.
+    fn serve(name: &str, reply: &'static str) -> (PathBuf, Arc<AtomicU64>) {
+        let directory = std::env::temp_dir().join(format!(
+            "wayggle-bg-hyprland-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let socket_path = directory.join(".socket.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let requests = Arc::new(AtomicU64::new(0));
+        let counter = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 64];
+                let length = stream.read(&mut request).unwrap();
+                assert_eq!(&request[..length], b"cursorpos");
+                stream.write_all(reply.as_bytes()).unwrap();
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        (socket_path, requests)
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if condition() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    #[test]
+    fn parses_replies() {
+        assert_eq!(parse_cursor_pos("1280, 720"), Some((1280.0, 720.0)));
+        assert_eq!(parse_cursor_pos(" 12.5,3 \n"), Some((12.5, 3.0)));
+        assert_eq!(parse_cursor_pos("-1920, -40"), Some((-1920.0, -40.0)));
+        assert_eq!(parse_cursor_pos("unknown request"), None);
+        assert_eq!(parse_cursor_pos("12, twelve"), None);
+        assert_eq!(parse_cursor_pos(""), None);
+    }
+
+    #[test]
+    fn queries_the_socket() {
+        let (socket_path, _) = serve("query", "1024, -16\n");
+        assert_eq!(query_cursor_pos(&socket_path).unwrap(), (1024.0, -16.0));
+    }
+
+    #[test]
+    fn garbage_replies_are_errors() {
+        let (socket_path, _) = serve("garbage", "error: no such command");
+        let error = query_cursor_pos(&socket_path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn polls_until_paused() {
+        let (socket_path, requests) = serve("poll", "10, 20");
+        let cursor = HyprlandCursor::spawn(socket_path).unwrap();
+        assert!(wait_for(|| cursor.position().is_some()));
+        assert_eq!(cursor.position(), Some(CursorPosition::Global(10.0, 20.0)));
+
+        cursor.set_paused(true);
+        // A request may have been in flight when pausing.
+        std::thread::sleep(HYPRLAND_POLL_INTERVAL * 4);
+        let paused_at = requests.load(Ordering::Relaxed);
+        std::thread::sleep(HYPRLAND_POLL_INTERVAL * 10);
+        assert_eq!(requests.load(Ordering::Relaxed), paused_at);
+
+        cursor.set_paused(false);
+        assert!(wait_for(|| requests.load(Ordering::Relaxed) > paused_at));
+    }
+}
@@ -0,0 +1,68 @@
+use std::cell::Cell;
+
+use wayland_client::{WEnum, protocol::wl_pointer};
+
+use super::{CursorPosition, CursorProvider};
+
+/// Cursor position taken from the `wl_pointer` events of the wallpaper surface.
+///
+/// Works on every compositor, but the position is only known while the pointer is
+/// directly over the wallpaper, not while it hovers a window.
+#[derive(Default)]
+pub struct PointerCursor {
+    position: Cell<Option<CursorPosition>>,
+    pressed_buttons: Cell<u32>,
+}
+
+impl PointerCursor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CursorProvider for PointerCursor {
+    fn position(&self) -> Option<CursorPosition> {
+        self.position.get()
+    }
+
+    fn button_pressed(&self) -> Option<bool> {
+        Some(self.pressed_buttons.get() > 0)
+    }
+
+    fn uses_pointer_events(&self) -> bool {
+        true
+    }
+
+    fn handle_pointer_event(&self, event: &wl_pointer::Event) {
+        match event {
+            wl_pointer::Event::Enter {
+                surface_x,
+                surface_y,
+                ..
+            }
+            | wl_pointer::Event::Motion {
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                self.position.set(Some(CursorPosition::Surface(
+                    *surface_x as f32,
+                    *surface_y as f32,
+                )));
+            }
+            wl_pointer::Event::Leave { .. } => {
+                // Buttons released elsewhere are never reported to this surface.
+                self.position.set(None);
+                self.pressed_buttons.set(0);
+            }
+            wl_pointer::Event::Button { state, .. } => {
+                let pressed_buttons = self.pressed_buttons.get();
+                self.pressed_buttons.set(match state {
+                    WEnum::Value(wl_pointer::ButtonState::Pressed) => pressed_buttons + 1,
+                    _ => pressed_buttons.saturating_sub(1),
+                });
+            }
+            _ => {}
+        }
+    }
+}
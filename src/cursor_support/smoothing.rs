@@ -0,0 +1,232 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smoothing {
+    None,
+    /// Exponential moving average reaching ~63% of a move after `time_constant` seconds.
+    Exponential {
+        time_constant: f32,
+    },
+    /// Critically damped spring settling in about `smooth_time` seconds.
+    Spring {
+        smooth_time: f32,
+    },
+}
+
+/// Smooths raw cursor samples and tracks how the cursor moves.
+pub struct CursorFilter {
+    smoothing: Smoothing,
+    raw: Option<(f32, f32)>,
+    position: Option<(f32, f32)>,
+    spring_velocity: (f32, f32),
+    velocity: (f32, f32),
+    idle_time: f32,
+}
+
+impl CursorFilter {
+    pub fn new(smoothing: Smoothing) -> Self {
+        CursorFilter {
+            smoothing,
+            raw: None,
+            position: None,
+            spring_velocity: (0.0, 0.0),
+            velocity: (0.0, 0.0),
+            idle_time: 0.0,
+        }
+    }
+
+    /// Feed the raw cursor sample of a frame `dt` seconds after the previous one.
+    /// Returns the smoothed position, `None` while the cursor is outside of the surface.
+    pub fn update(&mut self, raw: Option<(f32, f32)>, dt: f32) -> Option<(f32, f32)> {
+        // A cursor on another monitor counts as a still one.
+        if raw == self.raw {
+            self.idle_time += dt;
+        } else {
+            self.idle_time = 0.0;
+        }
+        self.raw = raw;
+
+        let Some(target) = raw else {
+            self.velocity = (0.0, 0.0);
+            self.spring_velocity = (0.0, 0.0);
+            return None;
+        };
+        let previous = self.position.unwrap_or(target);
+        let position = match self.smoothing {
+            Smoothing::None => target,
+            Smoothing::Exponential { time_constant } => {
+                let alpha = if time_constant > 0.0 {
+                    1.0 - (-dt / time_constant).exp()
+                } else {
+                    1.0
+                };
+                (
+                    previous.0 + (target.0 - previous.0) * alpha,
+                    previous.1 + (target.1 - previous.1) * alpha,
+                )
+            }
+            Smoothing::Spring { smooth_time } => {
+                let (x, vx) = smooth_damp(
+                    previous.0,
+                    target.0,
+                    self.spring_velocity.0,
+                    smooth_time,
+                    dt,
+                );
+                let (y, vy) = smooth_damp(
+                    previous.1,
+                    target.1,
+                    self.spring_velocity.1,
+                    smooth_time,
+                    dt,
+                );
+                self.spring_velocity = (vx, vy);
+                (x, y)
+            }
+        };
+        self.velocity = if dt > 0.0 {
+            (
+                (position.0 - previous.0) / dt,
+                (position.1 - previous.1) / dt,
+            )
+        } else {
+            self.velocity
+        };
+        self.position = Some(position);
+        Some(position)
+    }
+
+    /// Velocity of the smoothed position in pixels per second.
+    pub fn velocity(&self) -> (f32, f32) {
+        self.velocity
+    }
+
+    /// Seconds since the cursor last moved.
+    pub fn idle_time(&self) -> f32 {
+        self.idle_time
+    }
+}
+
+/// One step of a critically damped spring, stable for any `dt`.
+/// See "Critically Damped Ease-In/Ease-Out Smoothing", Game Programming Gems 4.
+fn smooth_damp(current: f32, target: f32, velocity: f32, smooth_time: f32, dt: f32) -> (f32, f32) {
+    if smooth_time <= 0.0 {
+        return (target, 0.0);
+    }
+    let omega = 2.0 / smooth_time;
+    let x = omega * dt;
+    let decay = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (velocity + omega * change) * dt;
+    let velocity = (velocity - omega * temp) * decay;
+    (target + (change + temp) * decay, velocity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: f32 = 1.0 / 60.0;
+
+    /// The x of the smoothed position after each of `frames` frames moving to x = 100.
+    fn approach(smoothing: Smoothing, frames: usize, dt: f32) -> Vec<f32> {
+        let mut filter = CursorFilter::new(smoothing);
+        filter.update(Some((0.0, 0.0)), dt);
+        (0..frames)
+            .map(|_| filter.update(Some((100.0, 0.0)), dt).unwrap().0)
+            .collect()
+    }
+
+    #[test]
+    fn without_smoothing_follows_the_cursor() {
+        assert_eq!(approach(Smoothing::None, 2, FRAME), [100.0, 100.0]);
+    }
+
+    #[test]
+    fn exponential_closes_the_distance_by_a_constant_factor() {
+        let smoothing = Smoothing::Exponential { time_constant: 0.1 };
+        let path = approach(smoothing, 3, 0.1);
+        // Each time constant leaves 1/e of the distance.
+        for (frame, x) in path.iter().enumerate() {
+            let left = 100.0 * (-(frame as f32 + 1.0)).exp();
+            assert!((100.0 - x - left).abs() < 1e-3, "{} {}", frame, x);
+        }
+        assert!(approach(smoothing, 120, FRAME)[119] > 99.99);
+        // A zero time constant doesn't smooth.
+        let instant = Smoothing::Exponential { time_constant: 0.0 };
+        assert_eq!(approach(instant, 1, FRAME), [100.0]);
+    }
+
+    #[test]
+    fn spring_settles_without_overshooting() {
+        let smoothing = Smoothing::Spring { smooth_time: 0.1 };
+        for dt in [1.0 / 144.0, FRAME, 0.25, 2.0] {
+            let path = approach(smoothing, (2.0 / dt) as usize + 1, dt);
+            assert!(
+                path.windows(2).all(|pair| pair[0] <= pair[1]),
+                "{} {:?}",
+                dt,
+                path
+            );
+            assert!(path.iter().all(|x| *x <= 100.0), "{} {:?}", dt, path);
+            assert!(path.last().unwrap() > &99.9, "{} {:?}", dt, path);
+        }
+        // Slower than exponential smoothing at first: it has to speed up.
+        let exponential = Smoothing::Exponential { time_constant: 0.1 };
+        assert!(approach(smoothing, 1, FRAME)[0] < approach(exponential, 1, FRAME)[0]);
+    }
+
+    #[test]
+    fn smooth_damp_without_time_snaps() {
+        assert_eq!(smooth_damp(0.0, 100.0, 5.0, 0.0, FRAME), (100.0, 0.0));
+    }
+
+    #[test]
+    fn velocity_follows_the_smoothed_motion() {
+        let mut filter = CursorFilter::new(Smoothing::None);
+        filter.update(Some((0.0, 0.0)), 0.5);
+        assert_eq!(filter.velocity(), (0.0, 0.0));
+        filter.update(Some((10.0, -5.0)), 0.5);
+        assert_eq!(filter.velocity(), (20.0, -10.0));
+        filter.update(Some((10.0, -5.0)), 0.5);
+        assert_eq!(filter.velocity(), (0.0, 0.0));
+
+        // Smoothed motion to the left is slower than the raw jump, but still leftwards.
+        let mut filter = CursorFilter::new(Smoothing::Spring { smooth_time: 0.1 });
+        filter.update(Some((100.0, 50.0)), FRAME);
+        filter.update(Some((0.0, 50.0)), FRAME);
+        let (vx, vy) = filter.velocity();
+        assert!(vx < 0.0 && vx > -100.0 / FRAME, "{}", vx);
+        assert_eq!(vy, 0.0);
+    }
+
+    #[test]
+    fn idle_time_counts_while_the_cursor_is_still() {
+        let mut filter = CursorFilter::new(Smoothing::None);
+        filter.update(Some((10.0, 10.0)), 0.25);
+        assert_eq!(filter.idle_time(), 0.0);
+        filter.update(Some((10.0, 10.0)), 0.25);
+        filter.update(Some((10.0, 10.0)), 0.25);
+        assert_eq!(filter.idle_time(), 0.5);
+        filter.update(Some((11.0, 10.0)), 0.25);
+        assert_eq!(filter.idle_time(), 0.0);
+    }
+
+    #[test]
+    fn leaving_the_surface_stops_the_cursor() {
+        let mut filter = CursorFilter::new(Smoothing::Spring { smooth_time: 0.1 });
+        filter.update(Some((0.0, 0.0)), FRAME);
+        filter.update(Some((100.0, 0.0)), FRAME);
+        assert_ne!(filter.velocity(), (0.0, 0.0));
+
+        assert_eq!(filter.update(None, FRAME), None);
+        assert_eq!(filter.velocity(), (0.0, 0.0));
+        assert_eq!(filter.idle_time(), 0.0);
+        // Being elsewhere counts as being still.
+        assert_eq!(filter.update(None, 0.5), None);
+        assert_eq!(filter.idle_time(), 0.5);
+        // Coming back is a move, and the spring starts at rest.
+        let back = filter.update(Some((100.0, 0.0)), FRAME).unwrap();
+        assert_eq!(filter.idle_time(), 0.0);
+        assert!(back.0 < 100.0);
+        assert!(filter.velocity().0 > 0.0);
+    }
+}
@@ -0,0 +1,300 @@
+//! Local time of day and the position of the sun, for time-of-day schedules and the
+//! `u_daytime` and `u_sun_elevation` uniforms.
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Timelike as _};
+
+use crate::error::{Error, Result};
+use crate::headless::parse_duration;
+
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// Elevation of the sun's centre at sunrise and sunset, allowing for refraction and
+/// the size of its disc.
+const HORIZON_ELEVATION: f64 = -0.833;
+
+/// Where on earth the wallpaper is shown, in degrees. North and east are positive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    /// Fails for coordinates off the globe.
+    pub fn new(latitude: f64, longitude: f64) -> Result<Self> {
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err(Error::Configuration(format!(
+                "invalid location {}, {}: latitude must be within ±90° and longitude within ±180°",
+                latitude, longitude
+            )));
+        }
+        Ok(Location {
+            latitude,
+            longitude,
+        })
+    }
+}
+
+/// A time of the day, either on the clock or relative to the sun.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeOfDay {
+    /// Seconds since local midnight.
+    Clock(u32),
+    /// Sunrise plus an offset in seconds, which may be negative.
+    Sunrise(i64),
+    Sunset(i64),
+}
+
+impl std::str::FromStr for TimeOfDay {
+    type Err = String;
+
+    /// Parse `HH:MM`, `HH:MM:SS`, `sunrise` or `sunset`, the latter two optionally
+    /// followed by an offset such as `+30m` or `-1h`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        for (event, make) in [
+            ("sunrise", TimeOfDay::Sunrise as fn(i64) -> TimeOfDay),
+            ("sunset", TimeOfDay::Sunset),
+        ] {
+            let Some(offset) = s.strip_prefix(event) else {
+                continue;
+            };
+            let offset = offset.trim();
+            let (sign, offset) = if let Some(offset) = offset.strip_prefix('-') {
+                (-1, offset)
+            } else {
+                (1, offset.strip_prefix('+').unwrap_or(offset))
+            };
+            if offset.is_empty() {
+                return Ok(make(0));
+            }
+            let offset = parse_duration(offset)?;
+            return Ok(make(sign * offset.as_secs() as i64));
+        }
+
+        let fields = s
+            .split(':')
+            .map(|field| field.parse::<u32>().ok())
+            .collect::<Option<Vec<_>>>();
+        match fields.as_deref() {
+            Some(&[hours, minutes]) if hours < 24 && minutes < 60 => {
+                Ok(TimeOfDay::Clock(hours * 3600 + minutes * 60))
+            }
+            Some(&[hours, minutes, seconds]) if hours < 24 && minutes < 60 && seconds < 60 => {
+                Ok(TimeOfDay::Clock(hours * 3600 + minutes * 60 + seconds))
+            }
+            _ => Err(format!(
+                "invalid time of day '{}', expected e.g. '07:30', 'sunrise' or 'sunset-30m'",
+                s
+            )),
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn depends_on_sun(&self) -> bool {
+        !matches!(self, TimeOfDay::Clock(_))
+    }
+
+    /// Seconds since midnight on the clock of `zone` this time falls on at `date`. `None`
+    /// for sunrise and sunset without a location, or on days the sun doesn't rise or set.
+    pub fn seconds_on<Tz: TimeZone>(
+        &self,
+        date: NaiveDate,
+        location: Option<Location>,
+        zone: &Tz,
+    ) -> Option<u32> {
+        let (rising, offset) = match *self {
+            TimeOfDay::Clock(seconds) => return Some(seconds),
+            TimeOfDay::Sunrise(offset) => (true, offset),
+            TimeOfDay::Sunset(offset) => (false, offset),
+        };
+        let crossing = horizon_crossing(location?, date, rising, zone)? as i64 + offset;
+        Some(crossing.clamp(0, SECONDS_PER_DAY as i64 - 1) as u32)
+    }
+}
+
+/// How far through the local day `time` is, from 0 at midnight towards 1.
+pub fn daytime(time: &DateTime<Local>) -> f32 {
+    ((time.num_seconds_from_midnight() as f64 + time.nanosecond() as f64 * 1e-9)
+        / SECONDS_PER_DAY as f64) as f32
+}
+
+/// The values of `u_daytime` and `u_sun_elevation` at `time`. Without a location, the
+/// sun is taken to stand at the equator on an equinox, culminating at noon.
+pub fn uniforms(time: &DateTime<Local>, location: Option<Location>) -> (f32, f32) {
+    let daytime = daytime(time);
+    let elevation = match location {
+        Some(location) => sun_elevation(location, time.timestamp_millis() as f64 / 1000.0),
+        None => -90.0 * (daytime as f64 * std::f64::consts::TAU).cos(),
+    };
+    (daytime, elevation as f32)
+}
+
+/// Elevation of the sun above the horizon in degrees, at `unix_time` seconds.
+pub fn sun_elevation(location: Location, unix_time: f64) -> f64 {
+    // Low precision formulas of the Astronomical Almanac, good to about a degree.
+    let days = unix_time / SECONDS_PER_DAY as f64 - 10957.5;
+    let mean_anomaly = (357.529 + 0.98560028 * days).to_radians();
+    let mean_longitude = 280.459 + 0.98564736 * days;
+    let ecliptic_longitude =
+        (mean_longitude + 1.915 * mean_anomaly.sin() + 0.020 * (2.0 * mean_anomaly).sin())
+            .to_radians();
+    let obliquity = (23.439 - 0.00000036 * days).to_radians();
+    let right_ascension =
+        (obliquity.cos() * ecliptic_longitude.sin()).atan2(ecliptic_longitude.cos());
+    let declination = (obliquity.sin() * ecliptic_longitude.sin()).asin();
+    let sidereal_time = (280.46061837 + 360.98564736629 * days + location.longitude).to_radians();
+    let hour_angle = sidereal_time - right_ascension;
+    let latitude = location.latitude.to_radians();
+    (latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos())
+        .asin()
+        .to_degrees()
+}
+
+/// Unix time of the first instant of `date` in `zone`.
+fn start_of_day<Tz: TimeZone>(date: NaiveDate, zone: &Tz) -> Option<i64> {
+    Some(
+        zone.from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest()?
+            .timestamp(),
+    )
+}
+
+/// Time on the clock of `zone`, in seconds since midnight, the sun rises or sets at
+/// `date` if it does. The search runs over the actual length of the day, so the clock
+/// time is right on days daylight saving time starts or ends.
+fn horizon_crossing<Tz: TimeZone>(
+    location: Location,
+    date: NaiveDate,
+    rising: bool,
+    zone: &Tz,
+) -> Option<u32> {
+    let midnight = start_of_day(date, zone)?;
+    let length = (start_of_day(date.succ_opt()?, zone)? - midnight) as u32;
+    let above = |seconds: u32| {
+        sun_elevation(location, (midnight + seconds as i64) as f64) > HORIZON_ELEVATION
+    };
+
+    // Find the ten minute step the sun crosses the horizon in, then bisect it.
+    const STEP: u32 = 600;
+    let (mut low, mut high) = (0..length.div_ceil(STEP))
+        .map(|step| (step * STEP, ((step + 1) * STEP).min(length)))
+        .find(|&(start, end)| above(start) != rising && above(end) == rising)?;
+    while high - low > 1 {
+        let middle = (low + high) / 2;
+        if above(middle) == rising {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    let crossing = zone.timestamp_opt(midnight + high as i64, 0).single()?;
+    Some(crossing.num_seconds_from_midnight())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    const LONDON: Location = Location {
+        latitude: 51.5074,
+        longitude: -0.1278,
+    };
+    const TROMSO: Location = Location {
+        latitude: 69.6492,
+        longitude: 18.9553,
+    };
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn unix_time(date: NaiveDate, hours: u32, minutes: u32) -> f64 {
+        date.and_hms_opt(hours, minutes, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp() as f64
+    }
+
+    #[test]
+    fn parses_times_of_day() {
+        assert_eq!("07:30".parse(), Ok(TimeOfDay::Clock(7 * 3600 + 30 * 60)));
+        assert_eq!("23:59:59".parse(), Ok(TimeOfDay::Clock(86399)));
+        assert_eq!("sunrise".parse(), Ok(TimeOfDay::Sunrise(0)));
+        assert_eq!("sunrise+1h".parse(), Ok(TimeOfDay::Sunrise(3600)));
+        assert_eq!("sunset-30m".parse(), Ok(TimeOfDay::Sunset(-1800)));
+        assert_eq!(" sunset - 30m ".parse(), Ok(TimeOfDay::Sunset(-1800)));
+        for invalid in ["24:00", "07:60", "07:30:60", "7", "", "noon", "sunset-soon"] {
+            assert!(invalid.parse::<TimeOfDay>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn sun_stands_overhead_at_the_equator_on_an_equinox() {
+        let equinox = date(2024, 3, 20);
+        let equator = Location::new(0.0, 0.0).unwrap();
+        // Solar noon at Greenwich falls about 7 minutes after 12:00 in March.
+        assert!(sun_elevation(equator, unix_time(equinox, 12, 7)) > 89.0);
+        assert!(sun_elevation(equator, unix_time(equinox, 0, 7)) < -89.0);
+        assert!(sun_elevation(equator, unix_time(equinox, 6, 7)).abs() < 1.0);
+    }
+
+    #[test]
+    fn finds_sunrise_and_sunset() {
+        let midsummer = date(2024, 6, 21);
+        let minutes = |seconds: Option<u32>| seconds.unwrap() as f64 / 60.0;
+        // 04:43 and 21:21 British Summer Time.
+        let sunrise = TimeOfDay::Sunrise(0).seconds_on(midsummer, Some(LONDON), &Utc);
+        assert!((minutes(sunrise) - (3.0 * 60.0 + 43.0)).abs() < 3.0);
+        let sunset = TimeOfDay::Sunset(0).seconds_on(midsummer, Some(LONDON), &Utc);
+        assert!((minutes(sunset) - (20.0 * 60.0 + 21.0)).abs() < 3.0);
+
+        let offset = TimeOfDay::Sunset(-1800).seconds_on(midsummer, Some(LONDON), &Utc);
+        assert_eq!(offset, sunset.map(|sunset| sunset - 1800));
+        let clamped = TimeOfDay::Sunset(6 * 3600).seconds_on(midsummer, Some(LONDON), &Utc);
+        assert_eq!(clamped, Some(SECONDS_PER_DAY - 1));
+    }
+
+    #[test]
+    fn clock_times_are_in_the_zone() {
+        let zone = chrono::FixedOffset::east_opt(2 * 3600).unwrap();
+        let midsummer = date(2024, 6, 21);
+        let sunrise = TimeOfDay::Sunrise(0).seconds_on(midsummer, Some(LONDON), &Utc);
+        let shifted = TimeOfDay::Sunrise(0).seconds_on(midsummer, Some(LONDON), &zone);
+        assert_eq!(shifted, sunrise.map(|sunrise| sunrise + 2 * 3600));
+        assert_eq!(
+            TimeOfDay::Clock(3600).seconds_on(midsummer, None, &zone),
+            Some(3600)
+        );
+    }
+
+    #[test]
+    fn polar_days_and_nights_have_no_crossing() {
+        let sunset = TimeOfDay::Sunset(0);
+        let sunrise = TimeOfDay::Sunrise(0);
+        assert_eq!(
+            sunset.seconds_on(date(2024, 6, 21), Some(TROMSO), &Utc),
+            None
+        );
+        assert_eq!(
+            sunrise.seconds_on(date(2024, 12, 21), Some(TROMSO), &Utc),
+            None
+        );
+        assert!(
+            sunset
+                .seconds_on(date(2024, 3, 20), Some(TROMSO), &Utc)
+                .is_some()
+        );
+        assert_eq!(sunset.seconds_on(date(2024, 3, 20), None, &Utc), None);
+    }
+
+    #[test]
+    fn rejects_locations_off_the_globe() {
+        assert!(Location::new(90.0, -180.0).is_ok());
+        assert!(Location::new(90.1, 0.0).is_err());
+        assert!(Location::new(0.0, 180.5).is_err());
+    }
+}
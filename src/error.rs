@@ -0,0 +1,90 @@
+use khronos_egl as egl;
+
+/// Everything that can stop the wallpaper from starting or running.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{context}: {source}")]
+    Egl {
+        context: &'static str,
+        source: egl::Error,
+    },
+    #[error("no EGL display available")]
+    NoEglDisplay,
+    #[error("no EGL config with {0}")]
+    NoEglConfig(&'static str),
+    #[error("{0}")]
+    Gl(String),
+    #[error("{stage} shader compilation failed:\n{log}")]
+    ShaderCompile { stage: ShaderStage, log: String },
+    #[error("shader program linking failed:\n{0}")]
+    ShaderLink(String),
+    #[error("failed to connect to the Wayland compositor: {0}")]
+    WaylandConnect(#[from] wayland_client::ConnectError),
+    #[error("Wayland protocol error: {0}")]
+    WaylandDispatch(#[from] wayland_client::DispatchError),
+    #[error("Wayland connection failed: {0}")]
+    WaylandBackend(#[from] wayland_client::backend::WaylandError),
+    #[error("the compositor doesn't support {0}")]
+    MissingGlobal(&'static str),
+    #[error("cursor support: {0}")]
+    CursorProvider(String),
+    #[error("{0}")]
+    Configuration(String),
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        source: std::io::Error,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+impl std::fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShaderStage::Vertex => write!(f, "vertex"),
+            ShaderStage::Fragment => write!(f, "fragment"),
+        }
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Process exit code for the class of the failure. 2 is left to command line errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Configuration(_) => 3,
+            Error::Io { .. } => 4,
+            Error::WaylandConnect(_)
+            | Error::WaylandDispatch(_)
+            | Error::WaylandBackend(_)
+            | Error::MissingGlobal(_) => 5,
+            Error::Egl { .. } | Error::NoEglDisplay | Error::NoEglConfig(_) | Error::Gl(_) => 6,
+            Error::ShaderCompile { .. } | Error::ShaderLink(_) => 7,
+            Error::CursorProvider(_) => 8,
+        }
+    }
+
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        Error::Io {
+            context: context.into(),
+            source,
+        }
+    }
+}
+
+/// Attach what was being done to EGL errors.
+pub(crate) trait EglResultExt<T> {
+    fn context(self, context: &'static str) -> Result<T>;
+}
+
+impl<T> EglResultExt<T> for std::result::Result<T, egl::Error> {
+    fn context(self, context: &'static str) -> Result<T> {
+        self.map_err(|source| Error::Egl { context, source })
+    }
+}
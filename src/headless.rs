@@ -0,0 +1,474 @@
+use glow::HasContext;
+
+use khronos_egl as egl;
+
+use crate::channels::{ChannelBinding, check_bindings};
+use crate::error::{EglResultExt as _, Error, Result};
+use crate::pipeline::feedback::FeedbackPass;
+use crate::pipeline::{FrameInput, Pipeline};
+use crate::renderer::Renderer;
+use crate::scene::{ShaderChoice, Textures};
+use crate::shader::ShaderSource;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// `EGL_PLATFORM_SURFACELESS_MESA`, a display that needs neither a window system nor a GPU.
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+/// Renders frames into an offscreen framebuffer, without a Wayland session.
+///
+/// The context is surfaceless, so this works with Mesa's llvmpipe on machines without
+/// a GPU.
+pub struct HeadlessRenderer {
+    egl_instance: egl::Instance<egl::Static>,
+    egl_display: egl::Display,
+    egl_context: egl::Context,
+    width: u32,
+    height: u32,
+
+    gl: glow::Context,
+    framebuffer: glow::Framebuffer,
+    renderbuffer: glow::Renderbuffer,
+    pipeline: Pipeline,
+    feedback: Option<FeedbackPass>,
+}
+
+impl HeadlessRenderer {
+    /// Read the last rendered frame back as RGBA rows, top row first.
+    pub fn read_pixels(&self) -> Vec<u8> {
+        let mut pixels = vec![0u8; self.width as usize * self.height as usize * 4];
+        unsafe {
+            self.gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+            self.gl.read_pixels(
+                0,
+                0,
+                self.width as i32,
+                self.height as i32,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(Some(&mut pixels)),
+            );
+        }
+        // GL rows start at the bottom.
+        let row = self.width as usize * 4;
+        pixels.chunks_exact(row).rev().flatten().copied().collect()
+    }
+
+    /// Fails like [`AppConfigurationBuilder::build`](crate::AppConfigurationBuilder::build)
+    /// if two channels are bound to the same `iChannel`.
+    pub fn new(
+        width: u32,
+        height: u32,
+        shader: &ShaderSource,
+        channels: &[ChannelBinding],
+    ) -> Result<Self> {
+        check_bindings(channels)?;
+        let egl_instance = egl::Instance::<egl::Static>::new(egl::Static);
+
+        let egl_display = unsafe {
+            egl_instance.get_platform_display(
+                PLATFORM_SURFACELESS_MESA,
+                egl::DEFAULT_DISPLAY,
+                &[egl::ATTRIB_NONE],
+            )
+        }
+        .inspect_err(|e| {
+            tracing::debug!(
+                "No surfaceless EGL platform ({}), using the default display",
+                e
+            );
+        })
+        .ok()
+        .or_else(|| unsafe { egl_instance.get_display(egl::DEFAULT_DISPLAY) })
+        .ok_or(Error::NoEglDisplay)?;
+
+        egl_instance
+            .initialize(egl_display)
+            .context("failed to initialize EGL display")?;
+        egl_instance
+            .bind_api(egl::OPENGL_ES_API)
+            .context("failed to bind EGL API")?;
+
+        let attributes = [
+            egl::RED_SIZE,
+            8,
+            egl::GREEN_SIZE,
+            8,
+            egl::BLUE_SIZE,
+            8,
+            // The default is WINDOW_BIT, which surfaceless displays don't offer.
+            egl::SURFACE_TYPE,
+            egl::PBUFFER_BIT,
+            egl::RENDERABLE_TYPE,
+            egl::OPENGL_ES2_BIT,
+            egl::NONE,
+        ];
+        let config = egl_instance
+            .choose_first_config(egl_display, &attributes)
+            .context("failed to choose EGL config")?
+            .ok_or(Error::NoEglConfig("pbuffer surfaces and OpenGL ES 2"))?;
+
+        let context_attributes = [egl::CONTEXT_CLIENT_VERSION, 2, egl::NONE];
+        let egl_context = egl_instance
+            .create_context(egl_display, config, None, &context_attributes)
+            .context("failed to create EGL context")?;
+
+        // Needs EGL_KHR_surfaceless_context, which Mesa always provides.
+        egl_instance
+            .make_current(egl_display, None, None, Some(egl_context))
+            .context("failed to make EGL context current")?;
+
+        let gl = unsafe {
+            glow::Context::from_loader_function(|s| {
+                egl_instance
+                    .get_proc_address(s)
+                    .map_or(std::ptr::null(), |f| f as *const _)
+            })
+        };
+
+        let (framebuffer, renderbuffer) = unsafe {
+            let renderbuffer = gl.create_renderbuffer().map_err(Error::Gl)?;
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(renderbuffer));
+            gl.renderbuffer_storage(glow::RENDERBUFFER, glow::RGBA8, width as i32, height as i32);
+
+            let framebuffer = gl.create_framebuffer().map_err(Error::Gl)?;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::RENDERBUFFER,
+                Some(renderbuffer),
+            );
+            let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+            if status != glow::FRAMEBUFFER_COMPLETE {
+                return Err(Error::Gl(format!(
+                    "offscreen framebuffer is incomplete: 0x{:x}",
+                    status
+                )));
+            }
+            (framebuffer, renderbuffer)
+        };
+
+        let (vertex_shader, fragment_shader) = shader.to_glsl(channels);
+        let pipeline = Pipeline::new(&gl, &vertex_shader, &fragment_shader, channels)?;
+
+        Ok(HeadlessRenderer {
+            egl_instance,
+            egl_display,
+            egl_context,
+            width,
+            height,
+            gl,
+            framebuffer,
+            renderbuffer,
+            pipeline,
+            feedback: None,
+        })
+    }
+
+    /// A renderer showing `choice` with its channels and `textures`, and with `feedback`
+    /// the previous frame.
+    pub fn from_choice(
+        width: u32,
+        height: u32,
+        choice: &ShaderChoice,
+        textures: &Textures,
+        feedback: bool,
+    ) -> Result<Self> {
+        let mut loaded = choice.load()?;
+        let textures = textures.bind(&loaded.channels, feedback)?;
+        loaded.channels.extend(textures);
+        let renderer = HeadlessRenderer::new(width, height, &loaded.source, &loaded.channels)?;
+        if feedback {
+            renderer.with_feedback()
+        } else {
+            Ok(renderer)
+        }
+    }
+
+    /// Give the shader the previous frame, see [`FeedbackPass`].
+    pub fn with_feedback(mut self) -> Result<Self> {
+        self.feedback = Some(FeedbackPass::new(&self.gl, self.width, self.height)?);
+        Ok(self)
+    }
+}
+
+impl Renderer for HeadlessRenderer {
+    fn render(&self, input: &FrameInput) -> Result<()> {
+        self.pipeline.advance_channels();
+        let output = match self.feedback.as_ref() {
+            Some(feedback) => feedback.begin(&self.gl, &[&self.pipeline], self.width, self.height),
+            None => self.framebuffer,
+        };
+        unsafe {
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, Some(output));
+        }
+        self.pipeline.draw(&self.gl, self.width, self.height, input);
+        if let Some(feedback) = self.feedback.as_ref() {
+            feedback.finish(&self.gl, Some(self.framebuffer), self.width, self.height);
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl Drop for HeadlessRenderer {
+    fn drop(&mut self) {
+        // 1. glow (OpenGL) resources cleanup
+        self.pipeline.delete(&self.gl);
+        if let Some(feedback) = self.feedback.as_ref() {
+            feedback.delete(&self.gl);
+        }
+        unsafe {
+            self.gl.delete_framebuffer(self.framebuffer);
+            self.gl.delete_renderbuffer(self.renderbuffer);
+        }
+
+        // 2. Unbind EGL context
+        self.egl_instance
+            .make_current(self.egl_display, None, None, None)
+            .inspect_err(|e| {
+                tracing::error!("Failed to unbind EGL context: {}", e);
+            })
+            .ok();
+
+        // 3. Destroy EGL context
+        self.egl_instance
+            .destroy_context(self.egl_display, self.egl_context)
+            .inspect_err(|e| {
+                tracing::error!("Failed to destroy EGL context: {}", e);
+            })
+            .ok();
+
+        // 4. Terminate connection to EGL display
+        self.egl_instance
+            .terminate(self.egl_display)
+            .inspect_err(|e| {
+                tracing::error!("Failed to terminate EGL display: {}", e);
+            })
+            .ok();
+    }
+}
+
+/// Where recorded frames go.
+pub struct RecordOutput<'a> {
+    /// Directory numbered PNG files are written to.
+    pub directory: Option<&'a Path>,
+    /// Shell command raw RGBA frames are piped to.
+    pub pipe: Option<&'a str>,
+}
+
+/// Render `frames` frames `1 / fps` seconds of shader time apart, starting at `start`.
+///
+/// Time steps by the frame number rather than the clock, so a recording comes out the
+/// same however long each frame takes to render.
+pub fn record(
+    renderer: &HeadlessRenderer,
+    start: f32,
+    fps: u32,
+    frames: u32,
+    output: &RecordOutput,
+) -> Result<()> {
+    if let Some(directory) = output.directory {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| Error::io(format!("failed to create {}", directory.display()), e))?;
+    }
+    let mut encoder = output
+        .pipe
+        .map(|command| Encoder::spawn(command, renderer.width, renderer.height, fps))
+        .transpose()?;
+
+    for frame in 0..frames {
+        // Accumulating `1 / fps` would drift, so derive the time from the frame number.
+        let time = start + (frame as f64 / fps as f64) as f32;
+        renderer.render(&FrameInput {
+            time,
+            ..Default::default()
+        })?;
+        let pixels = renderer.read_pixels();
+        if let Some(directory) = output.directory {
+            let path = directory.join(format!("frame_{:05}.png", frame));
+            write_png(&path, renderer.width, renderer.height, &pixels)
+                .map_err(|e| Error::io(format!("failed to write {}", path.display()), e))?;
+        }
+        if let Some(encoder) = encoder.as_mut() {
+            encoder.write(&pixels)?;
+        }
+        tracing::debug!("Recorded frame {}/{} at {:.3}s", frame + 1, frames, time);
+    }
+
+    match encoder {
+        Some(encoder) => encoder.finish(),
+        None => Ok(()),
+    }
+}
+
+/// A shell command raw frames are piped to. Dropping it before
+/// [`finish`](Encoder::finish), e.g. when recording fails, kills the command.
+struct Encoder {
+    child: std::process::Child,
+    stdin: Option<std::process::ChildStdin>,
+}
+
+impl Encoder {
+    fn spawn(command: &str, width: u32, height: u32, fps: u32) -> Result<Self> {
+        let mut child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("WAYGGLE_WIDTH", width.to_string())
+            .env("WAYGGLE_HEIGHT", height.to_string())
+            .env("WAYGGLE_FPS", fps.to_string())
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::io(format!("failed to run '{}'", command), e))?;
+        let stdin = child.stdin.take();
+        let encoder = Encoder { child, stdin };
+        if encoder.stdin.is_none() {
+            return Err(Error::io(
+                format!("failed to run '{}'", command),
+                std::io::Error::other("its standard input isn't piped"),
+            ));
+        }
+        Ok(encoder)
+    }
+
+    fn write(&mut self, pixels: &[u8]) -> Result<()> {
+        let result = match self.stdin.as_mut() {
+            Some(stdin) => stdin.write_all(pixels),
+            None => Err(std::io::ErrorKind::BrokenPipe.into()),
+        };
+        result.map_err(|e| Error::io("failed to pipe a frame to the encoder", e))
+    }
+
+    /// Wait for the command to encode the frames written so far.
+    fn finish(mut self) -> Result<()> {
+        // Closing stdin tells the encoder the stream ended.
+        drop(self.stdin.take());
+        let status = self
+            .child
+            .wait()
+            .map_err(|e| Error::io("failed to wait for the encoder", e))?;
+        if !status.success() {
+            return Err(Error::io(
+                "encoder failed",
+                std::io::Error::other(format!("it exited with {}", status)),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        drop(self.stdin.take());
+        // Still running unless it was waited for in `finish`.
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Write RGBA rows, top row first, as an 8 bit PNG.
+pub fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> std::io::Result<()> {
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(std::io::Error::other)?;
+    writer
+        .write_image_data(pixels)
+        .map_err(std::io::Error::other)?;
+    writer.finish().map_err(std::io::Error::other)
+}
+
+/// Parse a `WIDTHxHEIGHT` size such as `1920x1080`.
+pub fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let parse = |v: &str| v.trim().parse::<u32>().ok().filter(|&v| v > 0);
+    s.split_once(['x', 'X'])
+        .and_then(|(width, height)| Some((parse(width)?, parse(height)?)))
+        .ok_or_else(|| format!("invalid size '{}', expected WIDTHxHEIGHT", s))
+}
+
+/// Parse a duration such as `10s`, `500ms`, `2m` or `1h`. A bare number is in seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let scale = match unit.trim() {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("unknown unit in duration '{}'", s)),
+    };
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|value| Duration::try_from_secs_f64(value * scale).ok())
+        .ok_or_else(|| format!("invalid duration '{}', expected e.g. '10s'", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1920x1080"), Ok((1920, 1080)));
+        assert_eq!(parse_size("64X32"), Ok((64, 32)));
+        assert_eq!(parse_size(" 8 x 4 "), Ok((8, 4)));
+        for invalid in ["1920", "0x1080", "1920x0", "x", "-1x1", "axb", "1x2x3"] {
+            assert!(parse_size(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("10"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_duration("10s"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("2 min"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("0s"), Ok(Duration::ZERO));
+        for invalid in ["", "s", "10d", "-1s", "1..5s", "soon"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn encoders_get_the_frames_and_their_format() {
+        let path =
+            std::env::temp_dir().join(format!("wayggle-bg-encoder-{}.raw", std::process::id()));
+        let command = format!(
+            "echo $WAYGGLE_WIDTH $WAYGGLE_HEIGHT $WAYGGLE_FPS > '{0}' && cat >> '{0}'",
+            path.display()
+        );
+        let mut encoder = Encoder::spawn(&command, 2, 1, 30).unwrap();
+        encoder.write(b"frame").unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"2 1 30\nframe");
+        std::fs::remove_file(path).unwrap();
+
+        let encoder = Encoder::spawn("cat > /dev/null; exit 3", 2, 1, 30).unwrap();
+        assert!(matches!(encoder.finish(), Err(Error::Io { .. })));
+    }
+
+    #[test]
+    fn dropped_encoders_are_killed() {
+        let encoder = Encoder::spawn("sleep 30", 2, 1, 30).unwrap();
+        let pid = encoder.child.id();
+        let started = std::time::Instant::now();
+        drop(encoder);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!Path::new(&format!("/proc/{}", pid)).exists());
+    }
+}
@@ -0,0 +1,192 @@
+//! Photo wallpapers: an image bound to an `iChannel`, drawn by an effect shader.
+//!
+//! Effects are Shadertoy `mainImage` functions. They read the wallpaper through
+//! `vec4 wallpaper(vec2 fragCoord)`, which places the image on the surface according
+//! to the [`Fit`] and fills the rest with the background colour.
+
+use std::path::Path;
+
+use glow::HasContext;
+
+use crate::channels::ChannelSource;
+use crate::error::{Error, Result};
+use crate::shader::ShaderSource;
+
+/// Effect showing the image as it is.
+pub const DEFAULT_EFFECT: &str = include_str!("../shaders/image-default-effect.glsl");
+
+/// How the image is placed on the surface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fit {
+    /// Scale to cover the whole surface, cropping what sticks out.
+    Fill,
+    /// Scale to fit inside the surface, with bars of the background colour.
+    Fit,
+    /// Centre at its own size.
+    Center,
+    /// Repeat at its own size from the top left corner.
+    Tile,
+    /// Scale to the surface size, ignoring the aspect ratio.
+    Stretch,
+}
+
+/// The effect shader as a Shadertoy shader, with the `wallpaper` function reading the
+/// image from `iChannel<channel>` prepended.
+pub fn effect_shader(effect: &str, channel: u32, fit: Fit, background: [f32; 3]) -> ShaderSource {
+    let fit = match fit {
+        Fit::Fill => 0,
+        Fit::Fit => 1,
+        Fit::Center => 2,
+        Fit::Tile => 3,
+        Fit::Stretch => 4,
+    };
+    let [red, green, blue] = background;
+    ShaderSource::shader_toy(format!(
+        "
+        const int WALLPAPER_FIT = {fit};
+        const vec3 WALLPAPER_BACKGROUND = vec3({red:?}, {green:?}, {blue:?});
+
+        vec4 wallpaper(vec2 fragCoord) {{
+            vec2 size = iChannelResolution[{channel}].xy;
+            // Images are stored top row first.
+            vec2 p = vec2(fragCoord.x, iResolution.y - fragCoord.y);
+            vec2 uv;
+            if (WALLPAPER_FIT == 4) {{
+                uv = p / iResolution.xy;
+            }} else if (WALLPAPER_FIT == 3) {{
+                uv = fract(p / size);
+            }} else {{
+                float scale = 1.0;
+                if (WALLPAPER_FIT == 0) {{
+                    scale = max(iResolution.x / size.x, iResolution.y / size.y);
+                }} else if (WALLPAPER_FIT == 1) {{
+                    scale = min(iResolution.x / size.x, iResolution.y / size.y);
+                }}
+                uv = (p - 0.5 * iResolution.xy) / (size * scale) + 0.5;
+            }}
+            if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {{
+                return vec4(WALLPAPER_BACKGROUND, 1.0);
+            }}
+            return texture(iChannel{channel}, uv);
+        }}
+
+        {effect}
+        "
+    ))
+}
+
+/// Parse a colour such as `#1e1e2e` or `1e1e2e` into components from 0 to 1.
+pub fn parse_colour(s: &str) -> Result<[f32; 3], String> {
+    let hex = s.trim();
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    // from_str_radix alone would take a sign, as in `+f+f+f`.
+    if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(format!("invalid colour '{}', expected e.g. '#1e1e2e'", s));
+    }
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap() as f32 / 255.0;
+    Ok([component(0), component(2), component(4)])
+}
+
+/// A still image as a channel texture.
+pub struct ImageChannel {
+    image: ::image::RgbaImage,
+}
+
+impl ImageChannel {
+    /// Decode a PNG, JPEG or WebP file.
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(ImageChannel {
+            image: read_image(path)?,
+        })
+    }
+}
+
+/// Decode a PNG, JPEG or WebP file into RGBA pixels.
+pub(crate) fn read_image(path: &Path) -> Result<::image::RgbaImage> {
+    let image = ::image::open(path).map_err(|e| {
+        let e = match e {
+            ::image::ImageError::IoError(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        };
+        Error::io(format!("failed to read image {}", path.display()), e)
+    })?;
+    Ok(image.into_rgba8())
+}
+
+impl ChannelSource for ImageChannel {
+    fn init_texture(&mut self, gl: &glow::Context, _texture: glow::Texture) {
+        let max_size = unsafe { gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE) }.max(1) as u32;
+        let (width, height) = self.image.dimensions();
+        let scaled;
+        let image = if width > max_size || height > max_size {
+            let scale = max_size as f32 / width.max(height) as f32;
+            let size = |side: u32| ((side as f32 * scale) as u32).clamp(1, max_size);
+            tracing::warn!(
+                "Scaling the {}x{} image down to the maximum texture size of {}",
+                width,
+                height,
+                max_size
+            );
+            scaled = ::image::imageops::resize(
+                &self.image,
+                size(width),
+                size(height),
+                ::image::imageops::FilterType::Triangle,
+            );
+            &scaled
+        } else {
+            &self.image
+        };
+
+        unsafe {
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA8 as i32,
+                image.width() as i32,
+                image.height() as i32,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelUnpackData::Slice(Some(image.as_raw())),
+            );
+            // Photos are usually much larger than the surface; mipmaps keep them from
+            // shimmering when scaled down.
+            gl.generate_mipmap(glow::TEXTURE_2D);
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::LINEAR_MIPMAP_LINEAR as i32,
+            );
+        }
+    }
+
+    fn resolution(&self) -> [f32; 3] {
+        let (width, height) = self.image.dimensions();
+        [width as f32, height as f32, 1.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colours() {
+        assert_eq!(parse_colour("#ff0080"), Ok([1.0, 0.0, 128.0 / 255.0]));
+        assert_eq!(parse_colour("FF0080"), parse_colour("#ff0080"));
+        assert_eq!(parse_colour(" #000000 "), Ok([0.0; 3]));
+    }
+
+    #[test]
+    fn rejects_invalid_colours() {
+        for invalid in [
+            "", "#", "#fff", "#ff00800", "ff00 80", "##ff0080", "#gg0000", "+f+f+f", "-1-1-1",
+            // Six bytes, but not six digits.
+            "#ff€0", "ééé",
+        ] {
+            assert!(parse_colour(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use crate::channels::{ChannelSource, upload_red_texture};
+use crate::error::{Error, Result};
+
+/// Width of the keyboard texture, one column per JavaScript key code.
+const TEXTURE_WIDTH: usize = 256;
+const EV_KEY: u16 = 0x01;
+/// Size of `struct input_event`: a `struct timeval`, then type, code and value.
+const INPUT_EVENT_SIZE: usize = 2 * std::mem::size_of::<std::ffi::c_long>() + 8;
+
+/// Key states read from an evdev device, laid out like Shadertoy's keyboard input.
+///
+/// A 256x3 texture indexed by JavaScript key code: the first row is 1 while the key is
+/// down, the second is 1 only during the frame it was pressed and the third toggles on
+/// every press.
+pub struct KeyboardInput {
+    events: mpsc::Receiver<(u8, bool)>,
+    texture: Vec<u8>,
+}
+
+impl KeyboardInput {
+    /// Read key events of the evdev `device`. Fails if it can't be opened, e.g. without
+    /// permission to read it.
+    pub fn spawn(device: PathBuf) -> Result<Self> {
+        tracing::info!("Reading keyboard input from {}", device.display());
+        let file = std::fs::File::open(&device)
+            .map_err(|e| Error::io(format!("failed to open keyboard {}", device.display()), e))?;
+        let (sender, events) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("keyboard-input".to_string())
+            .spawn(move || read_device(&device, file, sender))
+            .map_err(|e| Error::io("failed to spawn the keyboard input thread", e))?;
+        Ok(KeyboardInput {
+            events,
+            texture: vec![0; TEXTURE_WIDTH * 3],
+        })
+    }
+
+    fn apply_events(&mut self) {
+        let (down, rest) = self.texture.split_at_mut(TEXTURE_WIDTH);
+        let (pressed, toggled) = rest.split_at_mut(TEXTURE_WIDTH);
+        pressed.fill(0);
+        for (key, is_down) in self.events.try_iter() {
+            let key = key as usize;
+            if is_down && down[key] == 0 {
+                pressed[key] = 255;
+                toggled[key] ^= 255;
+            }
+            down[key] = if is_down { 255 } else { 0 };
+        }
+    }
+}
+
+impl ChannelSource for KeyboardInput {
+    fn init_texture(&mut self, gl: &glow::Context, _texture: glow::Texture) {
+        upload_red_texture(gl, TEXTURE_WIDTH, 3, &self.texture, true);
+    }
+
+    fn advance(&mut self) {
+        self.apply_events();
+    }
+
+    fn update(&mut self, gl: &glow::Context, _texture: glow::Texture) {
+        upload_red_texture(gl, TEXTURE_WIDTH, 3, &self.texture, false);
+    }
+
+    fn resolution(&self) -> [f32; 3] {
+        [TEXTURE_WIDTH as f32, 3.0, 1.0]
+    }
+}
+
+fn read_device(device: &Path, mut file: impl Read, events: mpsc::Sender<(u8, bool)>) {
+    let mut event = [0u8; INPUT_EVENT_SIZE];
+    loop {
+        if let Err(e) = file.read_exact(&mut event) {
+            tracing::error!("Failed to read keyboard {}: {}", device.display(), e);
+            return;
+        }
+        let header = &event[INPUT_EVENT_SIZE - 8..];
+        let kind = u16::from_ne_bytes([header[0], header[1]]);
+        let code = u16::from_ne_bytes([header[2], header[3]]);
+        let value = i32::from_ne_bytes([header[4], header[5], header[6], header[7]]);
+        // Value 2 is autorepeat, which doesn't change the state.
+        if kind != EV_KEY || value == 2 {
+            continue;
+        }
+        let Some(key) = javascript_key_code(code) else {
+            continue;
+        };
+        if events.send((key, value == 1)).is_err() {
+            tracing::debug!("Keyboard input dropped, stopping to read");
+            return;
+        }
+    }
+}
+
+/// Map a Linux evdev key code to the JavaScript `keyCode` Shadertoy indexes by.
+fn javascript_key_code(code: u16) -> Option<u8> {
+    let key = match code {
+        1 => 27,                            // Esc
+        2..=10 => 49 + (code - 2) as u8,    // 1-9
+        11 => 48,                           // 0
+        12 => 189,                          // -
+        13 => 187,                          // =
+        14 => 8,                            // Backspace
+        15 => 9,                            // Tab
+        16 => 81,                           // Q
+        17 => 87,                           // W
+        18 => 69,                           // E
+        19 => 82,                           // R
+        20 => 84,                           // T
+        21 => 89,                           // Y
+        22 => 85,                           // U
+        23 => 73,                           // I
+        24 => 79,                           // O
+        25 => 80,                           // P
+        26 => 219,                          // [
+        27 => 221,                          // ]
+        28 | 96 => 13,                      // Enter, keypad Enter
+        29 | 97 => 17,                      // Ctrl
+        30 => 65,                           // A
+        31 => 83,                           // S
+        32 => 68,                           // D
+        33 => 70,                           // F
+        34 => 71,                           // G
+        35 => 72,                           // H
+        36 => 74,                           // J
+        37 => 75,                           // K
+        38 => 76,                           // L
+        39 => 186,                          // ;
+        40 => 222,                          // '
+        41 => 192,                          // `
+        42 | 54 => 16,                      // Shift
+        43 => 220,                          // \
+        44 => 90,                           // Z
+        45 => 88,                           // X
+        46 => 67,                           // C
+        47 => 86,                           // V
+        48 => 66,                           // B
+        49 => 78,                           // N
+        50 => 77,                           // M
+        51 => 188,                          // ,
+        52 => 190,                          // .
+        53 => 191,                          // /
+        55 => 106,                          // Keypad *
+        56 | 100 => 18,                     // Alt
+        57 => 32,                           // Space
+        58 => 20,                           // Caps Lock
+        59..=68 => 112 + (code - 59) as u8, // F1-F10
+        69 => 144,                          // Num Lock
+        70 => 145,                          // Scroll Lock
+        71 => 103,                          // Keypad 7
+        72 => 104,                          // Keypad 8
+        73 => 105,                          // Keypad 9
+        74 => 109,                          // Keypad -
+        75 => 100,                          // Keypad 4
+        76 => 101,                          // Keypad 5
+        77 => 102,                          // Keypad 6
+        78 => 107,                          // Keypad +
+        79 => 97,                           // Keypad 1
+        80 => 98,                           // Keypad 2
+        81 => 99,                           // Keypad 3
+        82 => 96,                           // Keypad 0
+        83 => 110,                          // Keypad .
+        87 => 122,                          // F11
+        88 => 123,                          // F12
+        98 => 111,                          // Keypad /
+        102 => 36,                          // Home
+        103 => 38,                          // Up
+        104 => 33,                          // Page Up
+        105 => 37,                          // Left
+        106 => 39,                          // Right
+        107 => 35,                          // End
+        108 => 40,                          // Down
+        109 => 34,                          // Page Down
+        110 => 45,                          // Insert
+        111 => 46,                          // Delete
+        119 => 19,                          // Pause
+        125 => 91,                          // Left Meta
+        126 => 92,                          // Right Meta
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An input fed through the returned sender instead of a device.
+    fn input() -> (KeyboardInput, mpsc::Sender<(u8, bool)>) {
+        let (sender, events) = mpsc::channel();
+        let input = KeyboardInput {
+            events,
+            texture: vec![0; TEXTURE_WIDTH * 3],
+        };
+        (input, sender)
+    }
+
+    /// The down, pressed and toggled values of `key`.
+    fn rows(input: &KeyboardInput, key: u8) -> [u8; 3] {
+        std::array::from_fn(|row| input.texture[row * TEXTURE_WIDTH + key as usize])
+    }
+
+    #[test]
+    fn maps_key_codes() {
+        assert_eq!(javascript_key_code(2), Some(b'1'));
+        assert_eq!(javascript_key_code(10), Some(b'9'));
+        assert_eq!(javascript_key_code(11), Some(b'0'));
+        assert_eq!(javascript_key_code(30), Some(b'A'));
+        assert_eq!(javascript_key_code(16), Some(b'Q'));
+        assert_eq!(javascript_key_code(44), Some(b'Z'));
+        assert_eq!(javascript_key_code(12), Some(189));
+        assert_eq!(javascript_key_code(39), Some(186));
+        assert_eq!(javascript_key_code(53), Some(191));
+        assert_eq!(javascript_key_code(57), Some(32));
+        assert_eq!(javascript_key_code(28), javascript_key_code(96));
+        assert_eq!(javascript_key_code(59), Some(112));
+        assert_eq!(javascript_key_code(88), Some(123));
+        for unmapped in [0, 84, 240, u16::MAX] {
+            assert_eq!(javascript_key_code(unmapped), None, "{}", unmapped);
+        }
+    }
+
+    #[test]
+    fn presses_last_one_frame() {
+        let (mut input, sender) = input();
+        sender.send((b'A', true)).unwrap();
+        input.apply_events();
+        assert_eq!(rows(&input, b'A'), [255, 255, 255]);
+
+        // Held down: no longer pressed this frame.
+        input.apply_events();
+        assert_eq!(rows(&input, b'A'), [255, 0, 255]);
+
+        sender.send((b'A', false)).unwrap();
+        input.apply_events();
+        assert_eq!(rows(&input, b'A'), [0, 0, 255]);
+        assert_eq!(rows(&input, b'B'), [0, 0, 0]);
+    }
+
+    #[test]
+    fn every_press_toggles() {
+        let (mut input, sender) = input();
+        for (is_down, toggled) in [(true, 255), (false, 255), (true, 0), (false, 0)] {
+            sender.send((b' ', is_down)).unwrap();
+            input.apply_events();
+            assert_eq!(rows(&input, b' ')[2], toggled);
+        }
+    }
+
+    #[test]
+    fn repeated_downs_are_one_press() {
+        let (mut input, sender) = input();
+        sender.send((b'W', true)).unwrap();
+        input.apply_events();
+        // A second down without an up in between, as if a release got lost.
+        sender.send((b'W', true)).unwrap();
+        input.apply_events();
+        assert_eq!(rows(&input, b'W'), [255, 0, 255]);
+    }
+
+    #[test]
+    fn quick_taps_within_a_frame_still_press() {
+        let (mut input, sender) = input();
+        sender.send((b'E', true)).unwrap();
+        sender.send((b'E', false)).unwrap();
+        input.apply_events();
+        assert_eq!(rows(&input, b'E'), [0, 255, 255]);
+        input.apply_events();
+        assert_eq!(rows(&input, b'E'), [0, 0, 255]);
+    }
+
+    /// A `struct input_event` with a zero timestamp.
+    fn event(kind: u16, code: u16, value: i32) -> Vec<u8> {
+        let mut event = vec![0; INPUT_EVENT_SIZE - 8];
+        event.extend(kind.to_ne_bytes());
+        event.extend(code.to_ne_bytes());
+        event.extend(value.to_ne_bytes());
+        event
+    }
+
+    #[test]
+    fn reads_key_events_and_skips_autorepeat() {
+        const EV_SYN: u16 = 0x00;
+        let device: Vec<u8> = [
+            event(EV_KEY, 30, 1),
+            event(EV_SYN, 0, 0),
+            event(EV_KEY, 30, 2),
+            event(EV_KEY, 30, 2),
+            event(EV_KEY, 240, 1),
+            event(EV_KEY, 30, 0),
+        ]
+        .concat();
+        let (sender, events) = mpsc::channel();
+        read_device(Path::new("fake"), &device[..], sender);
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [(b'A', true), (b'A', false)]
+        );
+    }
+}
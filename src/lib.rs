@@ -0,0 +1,39 @@
+//! Shader wallpapers for Wayland, rendered on a wlr-layer-shell background surface or
+//! offscreen.
+//!
+//! Describe the shader with a [`ShaderSource`], or pick a file, package or picture with a
+//! [`ShaderChoice`], then either configure a wallpaper with [`AppConfiguration::builder`]
+//! or [`AppConfiguration::from_choice`] and hand it to [`wayland_app::run`], or draw frames
+//! yourself with a [`Renderer`] such as [`HeadlessRenderer`].
+
+pub mod adaptors;
+pub mod audio;
+pub mod channels;
+pub mod clock;
+pub mod control;
+pub mod cubemap;
+pub mod cursor_support;
+pub mod daytime;
+pub mod error;
+pub mod headless;
+pub mod image;
+pub mod keyboard;
+pub mod pipeline;
+pub mod playlist;
+pub mod power_supply;
+pub mod renderer;
+pub mod scene;
+pub mod screencopy;
+pub mod shader;
+#[cfg(feature = "video")]
+pub mod video;
+pub mod volume;
+pub mod wayland_app;
+
+pub use error::{Error, Result};
+pub use headless::HeadlessRenderer;
+pub use pipeline::FrameInput;
+pub use renderer::Renderer;
+pub use scene::ShaderChoice;
+pub use shader::{ShaderMode, ShaderSource};
+pub use wayland_app::{AppConfiguration, AppConfigurationBuilder};
@@ -1,9 +1,17 @@
-mod adaptors;
 mod cli;
-mod cursor_support;
-mod wayland_app;
 use clap::Parser as _;
-use std::rc::Rc;
+use wayggle_bg::{
+    AppConfiguration, Error, HeadlessRenderer, Renderer, ShaderMode,
+    clock::ShaderClock,
+    control::{self, ControlCommand},
+    cursor_support,
+    daytime::Location,
+    headless,
+    playlist::Playlist,
+    power_supply, scene,
+    shader::{BUILTIN_SHADERS, package},
+    wayland_app,
+};
 
 fn main() {
     tracing_subscriber::fmt()
@@ -14,53 +22,201 @@ fn main() {
         .with_span_events(tracing_subscriber::fmt::format::FmtSpan::FULL)
         .init();
 
-    let default_vertex_shader = include_str!("../shaders/default-vert.glsl").to_string();
-
     let cli_configuration = cli::Cli::parse();
 
-    let default_shaders = vec![(
-        "box".to_string(),
-        include_str!("../shaders/box.glsl").to_string(),
-    )]
-    .into_iter()
-    .collect::<std::collections::HashMap<String, String>>();
+    if let Err(e) = run(cli_configuration) {
+        eprintln!("wayggle-bg: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
 
-    let (vertex_shader, fragment_shader) = match cli_configuration.command {
-        cli::Command::ShaderToy { fragment_shader } => {
-            let fragment_shader = adaptors::shader_toy_adaptor(fragment_shader);
-            (default_vertex_shader.clone(), fragment_shader)
+fn run(cli_configuration: cli::Cli) -> wayggle_bg::Result<()> {
+    let mut builder = match cli_configuration.command {
+        cli::Command::Shader(shader_command) => {
+            AppConfiguration::from_choice(shader_command.into())?
+        }
+        cli::Command::Playlist { file } => {
+            let playlist = Playlist::from_file(&file)?;
+            AppConfiguration::builder(&playlist.entries[0].shader).playlist(playlist)
         }
-        cli::Command::TheBookOfShaders {
-            fragment_shader,
-            vertex_shader,
+        cli::Command::Status => return send_control(ControlCommand::Status),
+        cli::Command::Reload => return send_control(ControlCommand::Reload),
+        cli::Command::List => {
+            let mode_name = |mode| match mode {
+                ShaderMode::ShaderToy => "shadertoy",
+                ShaderMode::TheBookOfShaders => "the_book_of_shaders",
+            };
+            // Packages take precedence over the built-in shaders of the same name.
+            let packages = package::discover();
+            let overridden = |name: &str| packages.iter().any(|package| package.name == name);
+            for shader in BUILTIN_SHADERS {
+                if overridden(shader.name) {
+                    continue;
+                }
+                println!("{:<12} {}", shader.name, shader.description);
+                println!(
+                    "{:<12} {}, {} licence, by {}",
+                    "",
+                    mode_name(shader.mode),
+                    shader.licence,
+                    shader.author
+                );
+                if let Some(url) = shader.url {
+                    println!("{:<12} {}", "", url);
+                }
+            }
+            for package in &packages {
+                let manifest = &package.manifest;
+                println!("{:<12} {}", package.name, manifest.description);
+                println!(
+                    "{:<12} {}, {} licence, by {}",
+                    "",
+                    mode_name(manifest.mode),
+                    manifest.licence,
+                    manifest.author
+                );
+                if let Some(url) = &manifest.url {
+                    println!("{:<12} {}", "", url);
+                }
+                println!("{:<12} installed in {}", "", package.directory.display());
+                if BUILTIN_SHADERS
+                    .iter()
+                    .any(|shader| shader.name == package.name)
+                {
+                    println!("{:<12} overrides the built-in shader of the same name", "");
+                }
+            }
+            return Ok(());
+        }
+        cli::Command::Render {
+            time,
+            size: (width, height),
+            out,
+            shader,
         } => {
-            let fragment_shader = fragment_shader;
-            let vertex_shader = vertex_shader.unwrap_or(default_vertex_shader);
-            (vertex_shader, fragment_shader)
+            let renderer = HeadlessRenderer::from_choice(
+                width,
+                height,
+                &shader.into(),
+                &cli_configuration.textures.into(),
+                cli_configuration.feedback,
+            )?;
+            renderer.render(&wayggle_bg::FrameInput {
+                time,
+                ..Default::default()
+            })?;
+            headless::write_png(&out, width, height, &renderer.read_pixels())
+                .map_err(|e| Error::io(format!("failed to write {}", out.display()), e))?;
+            tracing::info!("Rendered {}x{} frame to {}", width, height, out.display());
+            return Ok(());
         }
-        cli::Command::Default { name } => {
-            let fragment_shader = default_shaders
-                .get(&name)
-                .unwrap_or_else(|| {
-                    tracing::error!("Shader '{}' not found in default shaders", name);
-                    std::process::exit(1);
-                })
-                .clone();
-            let fragment_shader = adaptors::shader_toy_adaptor(fragment_shader);
-            (default_vertex_shader.clone(), fragment_shader)
+        cli::Command::Record {
+            duration,
+            fps,
+            start,
+            size: (width, height),
+            out,
+            pipe,
+            shader,
+        } => {
+            let renderer = HeadlessRenderer::from_choice(
+                width,
+                height,
+                &shader.into(),
+                &cli_configuration.textures.into(),
+                cli_configuration.feedback,
+            )?;
+            let frames = (duration.as_secs_f64() * fps as f64).round() as u32;
+            let output = headless::RecordOutput {
+                directory: out.as_deref(),
+                pipe: pipe.as_deref(),
+            };
+            headless::record(&renderer, start, fps, frames, &output)?;
+            tracing::info!("Recorded {} frames of {}x{}", frames, width, height);
+            return Ok(());
         }
     };
 
-    let get_cursor = match cli_configuration.cursor_support {
-        cli::CursorSupportKind::Hyprland => Some(Rc::new(
-            cursor_support::hyprland_get_cursor as fn() -> (f32, f32),
-        )),
-        cli::CursorSupportKind::Disabled => None,
-    };
-    let conf = wayland_app::AppConfiguration {
-        vertex_shader,
-        fragment_shader,
-        get_cursor,
-    };
-    wayland_app::run(conf);
+    if cli_configuration.feedback {
+        builder = builder.feedback();
+    }
+    builder = builder.clock(ShaderClock {
+        mode: cli_configuration.time_mode.into(),
+        speed: if cli_configuration.freeze {
+            0.0
+        } else {
+            cli_configuration.time_speed
+        },
+        offset: cli_configuration.time_offset,
+        wrap: cli_configuration
+            .time_wrap
+            .map(|period| period.as_secs_f64())
+            .filter(|period| *period > 0.0),
+    });
+    if let (Some(latitude), Some(longitude)) =
+        (cli_configuration.latitude, cli_configuration.longitude)
+    {
+        builder = builder.location(Location::new(latitude, longitude)?);
+    }
+    if let Some(path) = cli_configuration.fallback_shader.as_deref() {
+        builder = builder.fallback_shader_file(path)?;
+    }
+    if let Some(cursor_provider) =
+        cursor_support::create_provider(cli_configuration.cursor_support.into())?
+    {
+        builder = builder.cursor_provider(cursor_provider);
+    }
+    let smoothing_time = cli_configuration.cursor_smoothing_time;
+    builder = builder.cursor_smoothing(match cli_configuration.cursor_smoothing {
+        cli::CursorSmoothingKind::None => cursor_support::Smoothing::None,
+        cli::CursorSmoothingKind::Exponential => cursor_support::Smoothing::Exponential {
+            time_constant: smoothing_time,
+        },
+        cli::CursorSmoothingKind::Spring => cursor_support::Smoothing::Spring {
+            smooth_time: smoothing_time,
+        },
+    });
+    if cli_configuration.idle_timeout > 0 {
+        builder = builder.idle_timeout(std::time::Duration::from_secs(
+            cli_configuration.idle_timeout,
+        ));
+    }
+    if cli_configuration.battery_fps.is_some() || cli_configuration.low_battery_threshold.is_some()
+    {
+        builder = builder.power_policy(power_supply::PowerPolicy {
+            sysfs_root: cli_configuration.power_supply_root,
+            battery_fps: cli_configuration.battery_fps,
+            low_battery_threshold: cli_configuration.low_battery_threshold,
+        });
+    }
+    builder = builder
+        .inputs(scene::Inputs {
+            audio: cli_configuration.audio,
+            audio_channel: cli_configuration.audio_channel,
+            keyboard: cli_configuration.keyboard,
+            keyboard_channel: cli_configuration.keyboard_channel,
+            screencopy: cli_configuration.screencopy,
+            screencopy_channel: cli_configuration.screencopy_channel,
+            screencopy_interval: cli_configuration.screencopy_interval,
+        })
+        .textures(cli_configuration.textures.into());
+    wayland_app::run(builder.build()?)
+}
+
+/// Send `command` to the running wallpapers and print their replies.
+fn send_control(command: ControlCommand) -> wayggle_bg::Result<()> {
+    let replies = control::send(command)?;
+    if replies.is_empty() {
+        return Err(Error::Configuration(
+            "no running wallpaper found".to_string(),
+        ));
+    }
+    for (socket, reply) in replies {
+        let instance = socket.file_stem().unwrap_or_default().to_string_lossy();
+        println!("wallpaper {}:", instance);
+        for line in reply.lines() {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,481 @@
+pub mod feedback;
+pub mod transition;
+
+use glow::HasContext;
+
+use crate::channels::{ChannelBinding, ChannelSource};
+use crate::error::{Error, Result, ShaderStage};
+use crate::shader::ShaderSource;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Per-frame values fed to the shader uniforms.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameInput {
+    pub time: f32,
+    /// Cursor in surface pixels, `None` while it is outside of the surface.
+    pub cursor: Option<(f32, f32)>,
+    /// Cursor velocity in pixels per second.
+    pub cursor_velocity: (f32, f32),
+    /// Seconds since the cursor last moved.
+    pub cursor_idle_time: f32,
+    /// The value of Shadertoy's `iMouse`.
+    pub shadertoy_mouse: [f32; 4],
+    /// How far through the local day it is, from 0 at midnight towards 1.
+    pub daytime: f32,
+    /// Elevation of the sun above the horizon in degrees.
+    pub sun_elevation: f32,
+}
+
+/// A shader of a fallback chain that failed to build, and why.
+#[derive(Debug)]
+pub struct ShaderFailure {
+    /// Label of the shader in the chain, e.g. `"fallback shader"`.
+    pub shader: &'static str,
+    pub error: Error,
+}
+
+/// A channel source with the texture and uniforms it feeds.
+struct BoundChannel {
+    index: u32,
+    /// The target `texture` is bound to, after the kind of the source.
+    target: u32,
+    texture: glow::Texture,
+    source: Rc<RefCell<dyn ChannelSource>>,
+    resolution_uniform_location: Option<glow::UniformLocation>,
+    uniform_locations: Vec<Option<glow::UniformLocation>>,
+}
+
+/// The shader program drawing a full screen rectangle, with its uniforms and channels.
+///
+/// It is independent of where the GL context comes from, so the Wayland surface and the
+/// headless renderer share it. The GL context it was created with must be current for
+/// every call.
+pub struct Pipeline {
+    shader_program: glow::Program,
+    vbo: glow::Buffer,
+    position_attribute_location: u32,
+    time_uniform_location: Option<glow::UniformLocation>,
+    resolution_uniform_location: Option<glow::UniformLocation>,
+    cursor_uniform_location: Option<glow::UniformLocation>,
+    cursor_inside_uniform_location: Option<glow::UniformLocation>,
+    shadertoy_mouse_uniform_location: Option<glow::UniformLocation>,
+    cursor_velocity_uniform_location: Option<glow::UniformLocation>,
+    cursor_idle_time_uniform_location: Option<glow::UniformLocation>,
+    daytime_uniform_location: Option<glow::UniformLocation>,
+    sun_elevation_uniform_location: Option<glow::UniformLocation>,
+    /// Where [`FeedbackPass`](feedback::FeedbackPass) puts the previous frame: always in
+    /// `u_backbuffer`, and in `iChannel0` if no channel is bound to it.
+    backbuffer_uniform_location: Option<glow::UniformLocation>,
+    channel0_uniform_location: Option<glow::UniformLocation>,
+    channel0_resolution_uniform_location: Option<glow::UniformLocation>,
+    channels: Vec<BoundChannel>,
+}
+
+impl Pipeline {
+    /// Let the channel sources take in new input. Call once per frame before drawing;
+    /// pipelines of the same channels share their sources, so only one of them needs to.
+    pub fn advance_channels(&self) {
+        for channel in &self.channels {
+            channel.source.borrow_mut().advance();
+        }
+    }
+
+    /// Draw a frame into the bound framebuffer.
+    pub fn draw(&self, gl: &glow::Context, width: u32, height: u32, input: &FrameInput) {
+        // glow functions must be called inside an unsafe block
+        unsafe {
+            gl.viewport(0, 0, width as i32, height as i32);
+            // Pass the program handle wrapped in Option
+            gl.use_program(Some(self.shader_program));
+
+            // Pass a reference to the UniformLocation
+            if let Some(location) = self.time_uniform_location.as_ref() {
+                gl.uniform_1_f32(Some(location), input.time);
+            }
+            if let Some(location) = self.resolution_uniform_location.as_ref() {
+                gl.uniform_2_f32(Some(location), width as f32, height as f32);
+            }
+            // A cursor outside of the surface keeps the last position it had inside.
+            if let Some(location) = self.cursor_uniform_location.as_ref()
+                && let Some((x, y)) = input.cursor
+            {
+                gl.uniform_2_f32(Some(location), x, y);
+            }
+            if let Some(location) = self.cursor_inside_uniform_location.as_ref() {
+                let inside = if input.cursor.is_some() { 1.0 } else { 0.0 };
+                gl.uniform_1_f32(Some(location), inside);
+            }
+            if let Some(location) = self.shadertoy_mouse_uniform_location.as_ref() {
+                let [x, y, z, w] = input.shadertoy_mouse;
+                gl.uniform_4_f32(Some(location), x, y, z, w);
+            }
+            if let Some(location) = self.cursor_velocity_uniform_location.as_ref() {
+                let (x, y) = input.cursor_velocity;
+                gl.uniform_2_f32(Some(location), x, y);
+            }
+            if let Some(location) = self.cursor_idle_time_uniform_location.as_ref() {
+                gl.uniform_1_f32(Some(location), input.cursor_idle_time);
+            }
+            if let Some(location) = self.daytime_uniform_location.as_ref() {
+                gl.uniform_1_f32(Some(location), input.daytime);
+            }
+            if let Some(location) = self.sun_elevation_uniform_location.as_ref() {
+                gl.uniform_1_f32(Some(location), input.sun_elevation);
+            }
+
+            for channel in &self.channels {
+                gl.active_texture(glow::TEXTURE0 + channel.index);
+                gl.bind_texture(channel.target, Some(channel.texture));
+                let mut source = channel.source.borrow_mut();
+                source.update(gl, channel.texture);
+                if let Some(location) = channel.resolution_uniform_location.as_ref() {
+                    let [x, y, z] = source.resolution();
+                    gl.uniform_3_f32(Some(location), x, y, z);
+                }
+                for (location, (_, value)) in
+                    channel.uniform_locations.iter().zip(source.uniforms())
+                {
+                    if let Some(location) = location.as_ref() {
+                        gl.uniform_1_f32(Some(location), value);
+                    }
+                }
+            }
+
+            // Draw the rectangle
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
+            gl.enable_vertex_attrib_array(self.position_attribute_location);
+            gl.vertex_attrib_pointer_f32(
+                self.position_attribute_location,
+                2,
+                glow::FLOAT,
+                false,
+                0,
+                0,
+            );
+            gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+        }
+    }
+
+    /// Compile and link the shaders and set up the channels.
+    pub fn new(
+        gl: &glow::Context,
+        vertex_shader: &str,
+        fragment_shader: &str,
+        channels: &[ChannelBinding],
+    ) -> Result<Self> {
+        let shader_program = unsafe {
+            let program = gl.create_program().map_err(Error::Gl)?;
+            let vs = compile_shader(gl, ShaderStage::Vertex, vertex_shader)
+                .inspect_err(|_| gl.delete_program(program))?;
+            let fs =
+                compile_shader(gl, ShaderStage::Fragment, fragment_shader).inspect_err(|_| {
+                    gl.delete_shader(vs);
+                    gl.delete_program(program);
+                })?;
+            gl.attach_shader(program, vs);
+            gl.attach_shader(program, fs);
+
+            gl.link_program(program);
+            let linked = gl.get_program_link_status(program);
+
+            gl.detach_shader(program, fs);
+            gl.delete_shader(fs);
+            gl.detach_shader(program, vs);
+            gl.delete_shader(vs);
+
+            if !linked {
+                let log = gl.get_program_info_log(program);
+                gl.delete_program(program);
+                return Err(Error::ShaderLink(log));
+            }
+
+            gl.use_program(Some(program));
+            program
+        };
+        // Everything created from here on is freed again if a later step fails.
+        let delete = |channels: &[BoundChannel]| unsafe {
+            gl.delete_program(shader_program);
+            for channel in channels {
+                gl.delete_texture(channel.texture);
+            }
+        };
+
+        let Some(position_attribute_location) =
+            (unsafe { gl.get_attrib_location(shader_program, "a_position") })
+        else {
+            delete(&[]);
+            return Err(Error::ShaderLink(
+                "the vertex shader has no a_position attribute".to_string(),
+            ));
+        };
+
+        let time_uniform_location = unsafe { gl.get_uniform_location(shader_program, "u_time") };
+
+        let resolution_uniform_location =
+            unsafe { gl.get_uniform_location(shader_program, "u_resolution") };
+
+        let cursor_uniform_location = unsafe { gl.get_uniform_location(shader_program, "u_mouse") };
+
+        let cursor_inside_uniform_location =
+            unsafe { gl.get_uniform_location(shader_program, "u_mouse_inside") };
+
+        let shadertoy_mouse_uniform_location =
+            unsafe { gl.get_uniform_location(shader_program, "u_shadertoy_mouse") };
+
+        let cursor_velocity_uniform_location =
+            unsafe { gl.get_uniform_location(shader_program, "u_mouse_velocity") };
+
+        let cursor_idle_time_uniform_location =
+            unsafe { gl.get_uniform_location(shader_program, "u_mouse_idle_time") };
+
+        let daytime_uniform_location =
+            unsafe { gl.get_uniform_location(shader_program, "u_daytime") };
+
+        let sun_elevation_uniform_location =
+            unsafe { gl.get_uniform_location(shader_program, "u_sun_elevation") };
+
+        let backbuffer_uniform_location =
+            unsafe { gl.get_uniform_location(shader_program, "u_backbuffer") };
+
+        let channel0_uniform_location =
+            unsafe { gl.get_uniform_location(shader_program, "iChannel0") };
+
+        let channel0_resolution_uniform_location =
+            unsafe { gl.get_uniform_location(shader_program, "iChannelResolution[0]") };
+
+        let mut bound_channels: Vec<BoundChannel> = Vec::with_capacity(channels.len());
+        for binding in channels {
+            unsafe {
+                let target = binding.source.borrow().kind().target();
+                let texture = match gl.create_texture() {
+                    Ok(texture) => texture,
+                    Err(e) => {
+                        delete(&bound_channels);
+                        return Err(Error::Gl(e));
+                    }
+                };
+                gl.active_texture(glow::TEXTURE0 + binding.index);
+                gl.bind_texture(target, Some(texture));
+                for (parameter, value) in [
+                    (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+                    (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+                    (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                    (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+                    (glow::TEXTURE_WRAP_R, glow::CLAMP_TO_EDGE),
+                ] {
+                    gl.tex_parameter_i32(target, parameter, value as i32);
+                }
+                binding.source.borrow_mut().init_texture(gl, texture);
+                let sampler_location =
+                    gl.get_uniform_location(shader_program, &format!("iChannel{}", binding.index));
+                gl.uniform_1_i32(sampler_location.as_ref(), binding.index as i32);
+                let resolution_uniform_location = gl.get_uniform_location(
+                    shader_program,
+                    &format!("iChannelResolution[{}]", binding.index),
+                );
+                let uniform_locations = binding
+                    .source
+                    .borrow()
+                    .uniforms()
+                    .iter()
+                    .map(|(name, _)| gl.get_uniform_location(shader_program, name))
+                    .collect();
+                bound_channels.push(BoundChannel {
+                    index: binding.index,
+                    target,
+                    texture,
+                    source: binding.source.clone(),
+                    resolution_uniform_location,
+                    uniform_locations,
+                });
+            }
+        }
+
+        let vbo = unsafe {
+            let vertices: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+            let vertices_u8: &[u8] = core::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                vertices.len() * std::mem::size_of::<f32>(),
+            );
+
+            let vbo = gl
+                .create_buffer()
+                .map_err(Error::Gl)
+                .inspect_err(|_| delete(&bound_channels))?;
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, vertices_u8, glow::STATIC_DRAW);
+            vbo
+        };
+
+        Ok(Pipeline {
+            shader_program,
+            vbo,
+            position_attribute_location,
+            time_uniform_location,
+            resolution_uniform_location,
+            cursor_uniform_location,
+            cursor_inside_uniform_location,
+            shadertoy_mouse_uniform_location,
+            cursor_velocity_uniform_location,
+            cursor_idle_time_uniform_location,
+            daytime_uniform_location,
+            sun_elevation_uniform_location,
+            backbuffer_uniform_location,
+            channel0_uniform_location,
+            channel0_resolution_uniform_location,
+            channels: bound_channels,
+        })
+    }
+
+    /// Build the first shader of `chain` that compiles and links, as made by
+    /// [`ShaderSource::fallback_chain`]. Also returns the failures of the shaders before it.
+    ///
+    /// Only shader errors move on to the next shader; anything else is returned, as is the
+    /// error of the first shader when none of them builds.
+    pub fn with_fallbacks(
+        gl: &glow::Context,
+        chain: &[(&'static str, ShaderSource)],
+        channels: &[ChannelBinding],
+    ) -> Result<(Self, Vec<ShaderFailure>)> {
+        let mut failures = Vec::new();
+        for (label, shader) in chain {
+            let (vertex_shader, fragment_shader) = shader.to_glsl(channels);
+            match Pipeline::new(gl, &vertex_shader, &fragment_shader, channels) {
+                Ok(pipeline) => {
+                    if !failures.is_empty() {
+                        tracing::warn!("Showing the {} instead", label);
+                    }
+                    return Ok((pipeline, failures));
+                }
+                Err(error @ (Error::ShaderCompile { .. } | Error::ShaderLink(_))) => {
+                    tracing::warn!("The {} failed to build: {}", label, error);
+                    failures.push(ShaderFailure {
+                        shader: label,
+                        error,
+                    });
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(failures
+            .into_iter()
+            .next()
+            .map(|failure| failure.error)
+            .unwrap_or_else(|| Error::Configuration("no shader to build".to_string())))
+    }
+
+    /// Set float uniforms that keep their value for the lifetime of the pipeline. Names
+    /// the shader doesn't use are ignored.
+    pub fn set_uniforms(&self, gl: &glow::Context, uniforms: &[(String, f32)]) {
+        unsafe {
+            gl.use_program(Some(self.shader_program));
+            for (name, value) in uniforms {
+                let location = gl.get_uniform_location(self.shader_program, name);
+                gl.uniform_1_f32(location.as_ref(), *value);
+            }
+        }
+    }
+
+    /// Free the GL resources. The pipeline must not be used afterwards.
+    pub fn delete(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_program(self.shader_program);
+            gl.delete_buffer(self.vbo);
+            for channel in &self.channels {
+                gl.delete_texture(channel.texture);
+            }
+        }
+    }
+}
+
+/// A framebuffer rendering into a texture of the surface size, for passes sampling
+/// what was drawn.
+struct RenderTarget {
+    framebuffer: glow::Framebuffer,
+    texture: glow::Texture,
+}
+
+impl RenderTarget {
+    /// Create the target without storage; [`RenderTarget::resize`] allocates it.
+    fn new(gl: &glow::Context) -> Result<Self> {
+        unsafe {
+            let texture = gl.create_texture().map_err(Error::Gl)?;
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            for (parameter, value) in [
+                (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+                (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+                (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            let framebuffer = gl.create_framebuffer().map_err(Error::Gl)?;
+            Ok(RenderTarget {
+                framebuffer,
+                texture,
+            })
+        }
+    }
+
+    /// Reallocate the texture, leaving its contents undefined. `label` names the pass in
+    /// errors.
+    fn resize(&self, gl: &glow::Context, width: u32, height: u32, label: &str) -> Result<()> {
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA as i32,
+                width as i32,
+                height as i32,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelUnpackData::Slice(None),
+            );
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(self.texture),
+                0,
+            );
+            let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            if status != glow::FRAMEBUFFER_COMPLETE {
+                return Err(Error::Gl(format!(
+                    "{} framebuffer is incomplete: 0x{:x}",
+                    label, status
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn delete(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_texture(self.texture);
+        }
+    }
+}
+
+fn compile_shader(gl: &glow::Context, stage: ShaderStage, source: &str) -> Result<glow::Shader> {
+    let kind = match stage {
+        ShaderStage::Vertex => glow::VERTEX_SHADER,
+        ShaderStage::Fragment => glow::FRAGMENT_SHADER,
+    };
+    unsafe {
+        let shader = gl.create_shader(kind).map_err(Error::Gl)?;
+        gl.shader_source(shader, source);
+        gl.compile_shader(shader);
+        if !gl.get_shader_compile_status(shader) {
+            let log = gl.get_shader_info_log(shader);
+            gl.delete_shader(shader);
+            return Err(Error::ShaderCompile { stage, log });
+        }
+        Ok(shader)
+    }
+}
@@ -0,0 +1,125 @@
+use std::cell::Cell;
+
+use glow::HasContext;
+
+use super::{Pipeline, RenderTarget};
+use crate::channels::CHANNEL_COUNT;
+use crate::error::Result;
+
+/// Texture unit of the previous frame, after the ones of the channels and transitions.
+const FEEDBACK_TEXTURE_UNIT: u32 = CHANNEL_COUNT + 2;
+
+/// Renders every frame into one of two textures in turn, so the next frame can sample
+/// the previous one for trails and feedback effects.
+///
+/// Shaders read the previous frame as `u_backbuffer`, and as `iChannel0` unless an input
+/// is bound to it. Both start out black, and again after a resize.
+pub struct FeedbackPass {
+    targets: [RenderTarget; 2],
+    /// Index of the target holding the previous frame.
+    previous: Cell<usize>,
+}
+
+impl FeedbackPass {
+    pub fn new(gl: &glow::Context, width: u32, height: u32) -> Result<Self> {
+        let feedback = FeedbackPass {
+            targets: [RenderTarget::new(gl)?, RenderTarget::new(gl)?],
+            previous: Cell::new(0),
+        };
+        feedback.resize(gl, width, height)?;
+        Ok(feedback)
+    }
+
+    /// Reallocate the textures for a new surface size and clear them.
+    pub fn resize(&self, gl: &glow::Context, width: u32, height: u32) -> Result<()> {
+        for target in &self.targets {
+            target.resize(gl, width, height, "feedback")?;
+            unsafe {
+                gl.bind_framebuffer(glow::FRAMEBUFFER, Some(target.framebuffer));
+                gl.clear_color(0.0, 0.0, 0.0, 0.0);
+                gl.clear(glow::COLOR_BUFFER_BIT);
+            }
+        }
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        }
+        Ok(())
+    }
+
+    /// Bind the previous frame for `pipelines` and return the framebuffer the frame is to
+    /// be drawn into instead of the output.
+    pub fn begin(
+        &self,
+        gl: &glow::Context,
+        pipelines: &[&Pipeline],
+        width: u32,
+        height: u32,
+    ) -> glow::Framebuffer {
+        let previous = &self.targets[self.previous.get()];
+        unsafe {
+            gl.active_texture(glow::TEXTURE0 + FEEDBACK_TEXTURE_UNIT);
+            gl.bind_texture(glow::TEXTURE_2D, Some(previous.texture));
+            for pipeline in pipelines {
+                gl.use_program(Some(pipeline.shader_program));
+                gl.uniform_1_i32(
+                    pipeline.backbuffer_uniform_location.as_ref(),
+                    FEEDBACK_TEXTURE_UNIT as i32,
+                );
+                if pipeline.channels.iter().all(|channel| channel.index != 0) {
+                    gl.uniform_1_i32(
+                        pipeline.channel0_uniform_location.as_ref(),
+                        FEEDBACK_TEXTURE_UNIT as i32,
+                    );
+                    gl.uniform_3_f32(
+                        pipeline.channel0_resolution_uniform_location.as_ref(),
+                        width as f32,
+                        height as f32,
+                        1.0,
+                    );
+                }
+            }
+        }
+        self.targets[1 - self.previous.get()].framebuffer
+    }
+
+    /// Copy the frame drawn since [`FeedbackPass::begin`] into `output`, `None` being the
+    /// default framebuffer, and keep it for the next frame.
+    pub fn finish(
+        &self,
+        gl: &glow::Context,
+        output: Option<glow::Framebuffer>,
+        width: u32,
+        height: u32,
+    ) {
+        let current = 1 - self.previous.get();
+        let (width, height) = (width as i32, height as i32);
+        unsafe {
+            gl.bind_framebuffer(
+                glow::READ_FRAMEBUFFER,
+                Some(self.targets[current].framebuffer),
+            );
+            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, output);
+            gl.blit_framebuffer(
+                0,
+                0,
+                width,
+                height,
+                0,
+                0,
+                width,
+                height,
+                glow::COLOR_BUFFER_BIT,
+                glow::NEAREST,
+            );
+            gl.bind_framebuffer(glow::FRAMEBUFFER, output);
+        }
+        self.previous.set(current);
+    }
+
+    /// Free the GL resources. The pass must not be used afterwards.
+    pub fn delete(&self, gl: &glow::Context) {
+        for target in &self.targets {
+            target.delete(gl);
+        }
+    }
+}
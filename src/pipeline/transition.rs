@@ -0,0 +1,93 @@
+use glow::HasContext;
+
+use super::{FrameInput, Pipeline, RenderTarget};
+use crate::channels::CHANNEL_COUNT;
+use crate::error::Result;
+use crate::shader::DEFAULT_VERTEX_SHADER;
+
+/// Texture units of `u_from` and `u_to`, after the ones of the channels.
+const FROM_TEXTURE_UNIT: u32 = CHANNEL_COUNT;
+const TO_TEXTURE_UNIT: u32 = CHANNEL_COUNT + 1;
+
+/// Blends the frames of two pipelines with a transition shader.
+///
+/// Both pipelines are drawn into textures of the surface size, which the transition
+/// shader receives as `u_from` and `u_to` along with `u_progress`.
+pub struct TransitionPass {
+    pipeline: Pipeline,
+    progress_uniform_location: Option<glow::UniformLocation>,
+    targets: [RenderTarget; 2],
+}
+
+impl TransitionPass {
+    pub fn new(gl: &glow::Context, fragment_shader: &str, width: u32, height: u32) -> Result<Self> {
+        let pipeline = Pipeline::new(gl, DEFAULT_VERTEX_SHADER, fragment_shader, &[])?;
+        let progress_uniform_location = unsafe {
+            // Pipeline::new leaves the program in use.
+            let program = pipeline.shader_program;
+            let from_location = gl.get_uniform_location(program, "u_from");
+            gl.uniform_1_i32(from_location.as_ref(), FROM_TEXTURE_UNIT as i32);
+            let to_location = gl.get_uniform_location(program, "u_to");
+            gl.uniform_1_i32(to_location.as_ref(), TO_TEXTURE_UNIT as i32);
+            gl.get_uniform_location(program, "u_progress")
+        };
+        let transition = TransitionPass {
+            pipeline,
+            progress_uniform_location,
+            targets: [RenderTarget::new(gl)?, RenderTarget::new(gl)?],
+        };
+        transition.resize(gl, width, height)?;
+        Ok(transition)
+    }
+
+    /// Reallocate the textures for a new surface size.
+    pub fn resize(&self, gl: &glow::Context, width: u32, height: u32) -> Result<()> {
+        for target in &self.targets {
+            target.resize(gl, width, height, "transition")?;
+        }
+        Ok(())
+    }
+
+    /// Draw `from` blended into `to` into `output`, `None` being the default framebuffer.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        gl: &glow::Context,
+        output: Option<glow::Framebuffer>,
+        width: u32,
+        height: u32,
+        input: &FrameInput,
+        from: &Pipeline,
+        to: &Pipeline,
+        progress: f32,
+    ) {
+        unsafe {
+            for (target, pipeline) in self.targets.iter().zip([from, to]) {
+                gl.bind_framebuffer(glow::FRAMEBUFFER, Some(target.framebuffer));
+                pipeline.draw(gl, width, height, input);
+            }
+            gl.bind_framebuffer(glow::FRAMEBUFFER, output);
+
+            for (unit, target) in [FROM_TEXTURE_UNIT, TO_TEXTURE_UNIT]
+                .into_iter()
+                .zip(&self.targets)
+            {
+                gl.active_texture(glow::TEXTURE0 + unit);
+                gl.bind_texture(glow::TEXTURE_2D, Some(target.texture));
+            }
+            gl.use_program(Some(self.pipeline.shader_program));
+            if let Some(location) = self.progress_uniform_location.as_ref() {
+                gl.uniform_1_f32(Some(location), progress.clamp(0.0, 1.0));
+            }
+        }
+        self.pipeline.draw(gl, width, height, input);
+    }
+
+    /// Free the GL resources. The pass must not be used afterwards.
+    pub fn delete(&self, gl: &glow::Context) {
+        self.pipeline.delete(gl);
+        for target in &self.targets {
+            target.delete(gl);
+        }
+    }
+}
@@ -0,0 +1,626 @@
+//! Rotating through several shaders, with a transition shader blending each switch.
+//!
+//! A playlist file is TOML, with paths relative to the file:
+//!
+//! ```toml
+//! duration = "10m"  # how long each shader is shown, unless it says otherwise
+//! shuffle = true
+//!
+//! [transition]
+//! duration = "2s"
+//! shader = "wipe.glsl"  # the built-in crossfade when omitted
+//!
+//! [[shaders]]
+//! name = "box"  # a built-in shader or installed package
+//!
+//! [[shaders]]
+//! shadertoy = "tunnel.glsl"
+//! duration = "30m"
+//!
+//! [[shaders]]
+//! the_book_of_shaders = "waves.frag"
+//! vertex_shader = "waves.vert"
+//! uniforms = { u_speed = 0.5 }
+//! ```
+//!
+//! Instead of durations, every shader can be given the time of day it takes over at,
+//! e.g. `at = "07:00"`, `at = "sunrise"` or `at = "sunset-30m"`.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, TimeZone, Timelike as _};
+use serde::Deserialize;
+
+use crate::daytime::{Location, TimeOfDay};
+use crate::error::{Error, Result};
+use crate::headless::parse_duration;
+use crate::shader::{ShaderMode, ShaderSource, package};
+
+/// Transition shader used when the playlist doesn't name one.
+///
+/// Transition shaders are complete GLSL ES 3.0 fragment shaders receiving the outgoing
+/// and incoming frames as `sampler2D u_from` and `u_to`, `float u_progress` going from
+/// 0 to 1, and the usual `u_resolution` and `u_time`.
+pub const CROSSFADE_TRANSITION: &str = include_str!("../shaders/transition-crossfade.glsl");
+
+const DEFAULT_DURATION: Duration = Duration::from_secs(10 * 60);
+const DEFAULT_TRANSITION_DURATION: Duration = Duration::from_secs(2);
+/// How often a time-of-day playlist checks whether another entry is due.
+const TIME_OF_DAY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+pub struct PlaylistEntry {
+    pub shader: ShaderSource,
+    /// How long the shader is shown before switching to the next one.
+    pub duration: Duration,
+    /// When the shader is shown instead, in a playlist following the time of day.
+    pub at: Option<TimeOfDay>,
+    /// Values of float uniforms of the shader while this entry is shown.
+    pub uniforms: Vec<(String, f32)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Transition {
+    /// A duration of zero switches without blending.
+    pub duration: Duration,
+    pub fragment_shader: String,
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Transition {
+            duration: DEFAULT_TRANSITION_DURATION,
+            fragment_shader: CROSSFADE_TRANSITION.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Playlist {
+    /// Either all entries have a time of day, or none have.
+    pub entries: Vec<PlaylistEntry>,
+    /// Play the entries in a new random order on every round instead of in order.
+    pub shuffle: bool,
+    pub transition: Transition,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlaylistFile {
+    duration: Option<String>,
+    #[serde(default)]
+    shuffle: bool,
+    #[serde(default)]
+    transition: TransitionFile,
+    shaders: Vec<EntryFile>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransitionFile {
+    duration: Option<String>,
+    shader: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EntryFile {
+    name: Option<String>,
+    shadertoy: Option<PathBuf>,
+    the_book_of_shaders: Option<PathBuf>,
+    vertex_shader: Option<PathBuf>,
+    duration: Option<String>,
+    at: Option<String>,
+    #[serde(default)]
+    uniforms: std::collections::BTreeMap<String, f32>,
+}
+
+impl Playlist {
+    /// Read a playlist file, loading every shader it names.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::from_file_in(path, &package::search_path())
+    }
+
+    /// [`Playlist::from_file`] finding named packages in the directories `search_path`
+    /// instead of the XDG ones.
+    pub fn from_file_in(path: &Path, search_path: &[PathBuf]) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::io(format!("failed to read playlist {}", path.display()), e))?;
+        let invalid =
+            |message: String| Error::Configuration(format!("{}: {}", path.display(), message));
+        let file: PlaylistFile = toml::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        let duration = |value: Option<&String>, default: Duration| {
+            value
+                .map(|value| parse_duration(value).map_err(&invalid))
+                .unwrap_or(Ok(default))
+        };
+
+        let default_duration = duration(file.duration.as_ref(), DEFAULT_DURATION)?;
+        let entries = file
+            .shaders
+            .iter()
+            .map(|entry| {
+                let shader = match (&entry.name, &entry.shadertoy, &entry.the_book_of_shaders) {
+                    (Some(name), None, None) => match package::find_in(search_path, name)? {
+                        Some(package) => package.source()?,
+                        None => ShaderSource::builtin(name)
+                            .map_err(|_| package::unknown_name(search_path, name))?,
+                    },
+                    (None, Some(fragment), None) => {
+                        ShaderSource::from_files(ShaderMode::ShaderToy, &base.join(fragment), None)?
+                    }
+                    (None, None, Some(fragment)) => ShaderSource::from_files(
+                        ShaderMode::TheBookOfShaders,
+                        &base.join(fragment),
+                        entry
+                            .vertex_shader
+                            .as_ref()
+                            .map(|vertex| base.join(vertex))
+                            .as_deref(),
+                    )?,
+                    _ => {
+                        return Err(invalid(
+                            "each shader needs exactly one of name, shadertoy or the_book_of_shaders"
+                                .to_string(),
+                        ));
+                    }
+                };
+                Ok(PlaylistEntry {
+                    shader,
+                    duration: duration(entry.duration.as_ref(), default_duration)?,
+                    at: entry
+                        .at
+                        .as_ref()
+                        .map(|at| at.parse().map_err(&invalid))
+                        .transpose()?,
+                    uniforms: entry.uniforms.clone().into_iter().collect(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if entries.is_empty() {
+            return Err(invalid("no shaders".to_string()));
+        }
+        if entries.iter().any(|entry| entry.duration.is_zero()) {
+            return Err(invalid("shader durations must be positive".to_string()));
+        }
+        let timed = file
+            .shaders
+            .iter()
+            .filter(|entry| entry.at.is_some())
+            .count();
+        if timed > 0 {
+            if timed < entries.len() {
+                return Err(invalid(
+                    "either all shaders or none have a time of day ('at')".to_string(),
+                ));
+            }
+            if file.shuffle
+                || file.duration.is_some()
+                || file.shaders.iter().any(|entry| entry.duration.is_some())
+            {
+                return Err(invalid(
+                    "shaders with a time of day ('at') can't have a duration or be shuffled"
+                        .to_string(),
+                ));
+            }
+        }
+
+        let transition = Transition {
+            duration: duration(
+                file.transition.duration.as_ref(),
+                DEFAULT_TRANSITION_DURATION,
+            )?,
+            fragment_shader: match &file.transition.shader {
+                Some(shader) => {
+                    let shader = base.join(shader);
+                    std::fs::read_to_string(&shader).map_err(|e| {
+                        Error::io(format!("failed to read shader {}", shader.display()), e)
+                    })?
+                }
+                None => CROSSFADE_TRANSITION.to_string(),
+            },
+        };
+
+        Ok(Playlist {
+            entries,
+            shuffle: file.shuffle,
+            transition,
+        })
+    }
+
+    /// Whether entries are timed by sunrise or sunset, which need a location.
+    pub fn depends_on_sun(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.at.is_some_and(|at| at.depends_on_sun()))
+    }
+}
+
+/// Which playlist entry is shown, and when to look at the schedule again.
+pub struct Schedule {
+    mode: ScheduleMode,
+    current: usize,
+    next_switch: Instant,
+}
+
+enum ScheduleMode {
+    /// Every entry for its duration, in order or shuffled.
+    Rotation {
+        durations: Vec<Duration>,
+        shuffle: bool,
+        order: Vec<usize>,
+        position: usize,
+        rng: u64,
+    },
+    /// The entry whose time of day passed last.
+    TimeOfDay {
+        times: Vec<TimeOfDay>,
+        location: Option<Location>,
+    },
+}
+
+impl Schedule {
+    /// `location` places sunrise and sunset, which entries may be timed by.
+    pub fn new(playlist: &Playlist, location: Option<Location>, now: Instant) -> Self {
+        let times: Option<Vec<TimeOfDay>> = playlist.entries.iter().map(|entry| entry.at).collect();
+        if let Some(times) = times {
+            let current = active_entry(&times, location, &Local::now()).unwrap_or(0);
+            return Schedule {
+                mode: ScheduleMode::TimeOfDay { times, location },
+                current,
+                next_switch: now + TIME_OF_DAY_CHECK_INTERVAL,
+            };
+        }
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        // xorshift gets stuck on zero.
+        let mut rng = seed | 1;
+        let mut order: Vec<usize> = (0..playlist.entries.len()).collect();
+        if playlist.shuffle {
+            shuffle(&mut order, &mut rng);
+        }
+        let durations: Vec<Duration> = playlist
+            .entries
+            .iter()
+            .map(|entry| entry.duration)
+            .collect();
+        Schedule {
+            current: order[0],
+            next_switch: now + durations[order[0]],
+            mode: ScheduleMode::Rotation {
+                durations,
+                shuffle: playlist.shuffle,
+                order,
+                position: 0,
+                rng,
+            },
+        }
+    }
+
+    /// Index of the entry currently shown.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// When [`Schedule::advance`] has something to do.
+    pub fn next_switch(&self) -> Instant {
+        self.next_switch
+    }
+
+    /// Move on to the next entry if the current one is due, returning its index.
+    pub fn advance(&mut self, now: Instant) -> Option<usize> {
+        if now < self.next_switch {
+            return None;
+        }
+        let previous = self.current;
+        match &mut self.mode {
+            ScheduleMode::Rotation {
+                durations,
+                shuffle: shuffled,
+                order,
+                position,
+                rng,
+            } => {
+                *position += 1;
+                if *position == order.len() {
+                    *position = 0;
+                    if *shuffled {
+                        shuffle(order, rng);
+                        // Don't show the same shader twice in a row across rounds.
+                        if order.len() > 1 && order[0] == previous {
+                            order.swap(0, 1);
+                        }
+                    }
+                }
+                self.current = order[*position];
+                self.next_switch = now + durations[self.current];
+            }
+            ScheduleMode::TimeOfDay { times, location } => {
+                if let Some(active) = active_entry(times, *location, &Local::now()) {
+                    self.current = active;
+                }
+                self.next_switch = now + TIME_OF_DAY_CHECK_INTERVAL;
+            }
+        }
+        (self.current != previous).then_some(self.current)
+    }
+}
+
+/// The entry whose time passed last at `now`, or yesterday's last entry before the
+/// first one of the day. Times that don't happen today, such as sunset in polar day,
+/// are skipped.
+fn active_entry<Tz: TimeZone>(
+    times: &[TimeOfDay],
+    location: Option<Location>,
+    now: &DateTime<Tz>,
+) -> Option<usize> {
+    let today = now.date_naive();
+    let seconds = now.num_seconds_from_midnight();
+    let resolved: Vec<(usize, u32)> = times
+        .iter()
+        .enumerate()
+        .filter_map(|(index, time)| {
+            Some((index, time.seconds_on(today, location, &now.timezone())?))
+        })
+        .collect();
+    resolved
+        .iter()
+        .filter(|(_, time)| *time <= seconds)
+        .max_by_key(|(_, time)| *time)
+        .or_else(|| resolved.iter().max_by_key(|(_, time)| *time))
+        .map(|(index, _)| *index)
+}
+
+/// Fisher-Yates shuffle driven by a xorshift generator.
+fn shuffle(order: &mut [usize], rng: &mut u64) {
+    for i in (1..order.len()).rev() {
+        *rng ^= *rng << 13;
+        *rng ^= *rng >> 7;
+        *rng ^= *rng << 17;
+        let j = (*rng % (i as u64 + 1)) as usize;
+        order.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write `files` into a fresh directory and read the playlist `playlist.toml` there.
+    fn read(name: &str, playlist: &str, files: &[(&str, &str)]) -> Result<Playlist> {
+        let directory = std::env::temp_dir().join(format!(
+            "wayggle-bg-playlist-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("playlist.toml"), playlist).unwrap();
+        for (file, contents) in files {
+            std::fs::write(directory.join(file), contents).unwrap();
+        }
+        // Installed packages mustn't take the place of the built-in shaders.
+        let result = Playlist::from_file_in(&directory.join("playlist.toml"), &[]);
+        std::fs::remove_dir_all(directory).unwrap();
+        result
+    }
+
+    const SHADERS: &[(&str, &str)] = &[
+        ("a.glsl", "// a"),
+        ("b.frag", "// b"),
+        ("b.vert", "// b vertex"),
+        ("wipe.glsl", "// wipe"),
+    ];
+
+    #[test]
+    fn reads_entries_relative_to_the_file() {
+        let playlist = read(
+            "entries",
+            r#"
+                duration = "1m"
+                shuffle = true
+
+                [transition]
+                duration = "500ms"
+                shader = "wipe.glsl"
+
+                [[shaders]]
+                name = "box"
+
+                [[shaders]]
+                shadertoy = "a.glsl"
+                duration = "30s"
+
+                [[shaders]]
+                the_book_of_shaders = "b.frag"
+                vertex_shader = "b.vert"
+                uniforms = { u_speed = 0.5 }
+            "#,
+            SHADERS,
+        )
+        .unwrap();
+        assert!(playlist.shuffle);
+        assert_eq!(playlist.transition.duration, Duration::from_millis(500));
+        assert_eq!(playlist.transition.fragment_shader, "// wipe");
+
+        let [boxed, a, b] = &playlist.entries[..] else {
+            panic!("expected three entries");
+        };
+        assert_eq!(boxed.shader, ShaderSource::builtin("box").unwrap());
+        assert_eq!(boxed.duration, Duration::from_secs(60));
+        assert_eq!(a.shader.mode, ShaderMode::ShaderToy);
+        assert_eq!(a.shader.fragment_shader, "// a");
+        assert_eq!(a.duration, Duration::from_secs(30));
+        assert_eq!(b.shader.mode, ShaderMode::TheBookOfShaders);
+        assert_eq!(b.shader.vertex_shader.as_deref(), Some("// b vertex"));
+        assert_eq!(b.uniforms, [("u_speed".to_string(), 0.5)]);
+        assert!(!playlist.depends_on_sun());
+    }
+
+    #[test]
+    fn durations_default() {
+        let playlist = read("defaults", "[[shaders]]\nname = \"box\"\n", &[]).unwrap();
+        assert_eq!(playlist.entries[0].duration, DEFAULT_DURATION);
+        assert_eq!(playlist.transition.duration, DEFAULT_TRANSITION_DURATION);
+        assert_eq!(playlist.transition.fragment_shader, CROSSFADE_TRANSITION);
+        assert!(!playlist.shuffle);
+    }
+
+    #[test]
+    fn entries_name_exactly_one_shader() {
+        for (name, entry) in [
+            ("none", "duration = \"1m\""),
+            ("name-shadertoy", "name = \"box\"\nshadertoy = \"a.glsl\""),
+            (
+                "shadertoy-book",
+                "shadertoy = \"a.glsl\"\nthe_book_of_shaders = \"b.frag\"",
+            ),
+        ] {
+            let playlist = format!("[[shaders]]\n{}\n", entry);
+            assert!(
+                matches!(
+                    read(name, &playlist, SHADERS),
+                    Err(Error::Configuration(message)) if message.contains("exactly one")
+                ),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_playlists() {
+        for (name, playlist) in [
+            ("empty", "shaders = []"),
+            ("unknown-field", "[[shaders]]\nname = \"box\"\ncolour = 1"),
+            (
+                "zero-duration",
+                "[[shaders]]\nname = \"box\"\nduration = \"0s\"",
+            ),
+            (
+                "bad-duration",
+                "[[shaders]]\nname = \"box\"\nduration = \"soon\"",
+            ),
+            (
+                "partly-timed",
+                "[[shaders]]\nname = \"box\"\nat = \"07:00\"\n[[shaders]]\nname = \"box\"",
+            ),
+            (
+                "timed-shuffle",
+                "shuffle = true\n[[shaders]]\nname = \"box\"\nat = \"07:00\"",
+            ),
+        ] {
+            assert!(
+                matches!(read(name, playlist, SHADERS), Err(Error::Configuration(_))),
+                "{}",
+                name
+            );
+        }
+        assert!(matches!(
+            read(
+                "missing-file",
+                "[[shaders]]\nshadertoy = \"gone.glsl\"",
+                &[]
+            ),
+            Err(Error::Io { .. })
+        ));
+    }
+
+    fn rotation(durations: &[u64], shuffle: bool) -> Playlist {
+        Playlist {
+            entries: durations
+                .iter()
+                .map(|seconds| PlaylistEntry {
+                    shader: ShaderSource::shader_toy(""),
+                    duration: Duration::from_secs(*seconds),
+                    at: None,
+                    uniforms: Vec::new(),
+                })
+                .collect(),
+            shuffle,
+            transition: Transition::default(),
+        }
+    }
+
+    #[test]
+    fn advances_in_order_and_wraps() {
+        let start = Instant::now();
+        let mut schedule = Schedule::new(&rotation(&[10, 20, 5], false), None, start);
+        assert_eq!(schedule.current(), 0);
+        assert_eq!(schedule.next_switch(), start + Duration::from_secs(10));
+
+        assert_eq!(schedule.advance(start + Duration::from_secs(9)), None);
+        let mut now = start + Duration::from_secs(10);
+        assert_eq!(schedule.advance(now), Some(1));
+        assert_eq!(schedule.next_switch(), now + Duration::from_secs(20));
+        assert_eq!(schedule.advance(now + Duration::from_secs(19)), None);
+
+        now += Duration::from_secs(20);
+        assert_eq!(schedule.advance(now), Some(2));
+        now += Duration::from_secs(5);
+        assert_eq!(schedule.advance(now), Some(0));
+        assert_eq!(schedule.next_switch(), now + Duration::from_secs(10));
+    }
+
+    #[test]
+    fn a_single_entry_stays() {
+        let start = Instant::now();
+        let mut schedule = Schedule::new(&rotation(&[10], true), None, start);
+        assert_eq!(schedule.advance(start + Duration::from_secs(10)), None);
+        assert_eq!(
+            schedule.next_switch(),
+            start + Duration::from_secs(20),
+            "the entry gets another full duration"
+        );
+    }
+
+    #[test]
+    fn shuffles_every_entry_once_per_round_without_repeats() {
+        let entries = 5;
+        let mut now = Instant::now();
+        let mut schedule = Schedule::new(&rotation(&[1; 5], true), None, now);
+        let mut shown = vec![schedule.current()];
+        for _ in 1..entries * 40 {
+            now += Duration::from_secs(1);
+            let next = schedule.advance(now).expect("a different entry every time");
+            assert_ne!(Some(&next), shown.last());
+            shown.push(next);
+        }
+        for round in shown.chunks(entries) {
+            let mut round = round.to_vec();
+            round.sort();
+            assert_eq!(round, [0, 1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn the_last_passed_time_is_active() {
+        let at = |hours: u32, minutes: u32| {
+            chrono::NaiveDate::from_ymd_opt(2024, 6, 21)
+                .unwrap()
+                .and_hms_opt(hours, minutes, 0)
+                .unwrap()
+                .and_utc()
+        };
+        let times = [TimeOfDay::Clock(7 * 3600), TimeOfDay::Clock(19 * 3600)];
+        assert_eq!(active_entry(&times, None, &at(7, 0)), Some(0));
+        assert_eq!(active_entry(&times, None, &at(18, 59)), Some(0));
+        assert_eq!(active_entry(&times, None, &at(19, 0)), Some(1));
+        // Before the first time of the day, yesterday's last one is still active.
+        assert_eq!(active_entry(&times, None, &at(3, 0)), Some(1));
+
+        // Sunset without a location, or in polar day, never comes.
+        let tromso = Location::new(69.6492, 18.9553).unwrap();
+        let times = [TimeOfDay::Clock(7 * 3600), TimeOfDay::Sunset(0)];
+        assert_eq!(active_entry(&times, None, &at(23, 0)), Some(0));
+        assert_eq!(active_entry(&times, Some(tromso), &at(23, 0)), Some(0));
+        assert_eq!(
+            active_entry(&[TimeOfDay::Sunrise(0)], None, &at(12, 0)),
+            None
+        );
+    }
+}
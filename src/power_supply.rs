@@ -0,0 +1,311 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::wayland_app::FramePacing;
+
+pub const DEFAULT_SYSFS_ROOT: &str = "/sys/class/power_supply";
+
+/// How often the power supply state is read again.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerState {
+    pub on_battery: bool,
+    /// Mean charge of the system batteries in percent, if any reports one.
+    pub capacity: Option<u8>,
+}
+
+/// Read the power state from a sysfs `power_supply` class directory.
+///
+/// Batteries with `scope` set to `Device` (mice, keyboards, ...) are ignored. When the
+/// machine has a mains adapter its `online` flag decides whether we are on battery,
+/// otherwise the battery `status` does.
+pub fn read_power_state(root: &Path) -> std::io::Result<PowerState> {
+    let mut mains_seen = false;
+    let mut mains_online = false;
+    let mut battery_seen = false;
+    let mut discharging = false;
+    let mut capacities = Vec::new();
+
+    for entry in std::fs::read_dir(root)? {
+        let path = entry?.path();
+        let Some(kind) = read_attribute(&path, "type") else {
+            continue;
+        };
+        match kind.as_str() {
+            "Mains" | "USB" | "USB_C" | "USB_PD" => {
+                mains_seen = true;
+                if read_attribute(&path, "online").as_deref() == Some("1") {
+                    mains_online = true;
+                }
+            }
+            "Battery" => {
+                if read_attribute(&path, "scope").as_deref() == Some("Device") {
+                    continue;
+                }
+                battery_seen = true;
+                if read_attribute(&path, "status").as_deref() == Some("Discharging") {
+                    discharging = true;
+                }
+                if let Some(capacity) =
+                    read_attribute(&path, "capacity").and_then(|c| c.parse::<u32>().ok())
+                {
+                    capacities.push(capacity.min(100));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let on_battery = if mains_seen {
+        battery_seen && !mains_online
+    } else {
+        discharging
+    };
+    let capacity = match capacities.len() {
+        0 => None,
+        n => Some((capacities.iter().sum::<u32>() / n as u32) as u8),
+    };
+    Ok(PowerState {
+        on_battery,
+        capacity,
+    })
+}
+
+fn read_attribute(supply: &Path, attribute: &str) -> Option<String> {
+    std::fs::read_to_string(supply.join(attribute))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+#[derive(Clone, Debug)]
+pub struct PowerPolicy {
+    pub sysfs_root: PathBuf,
+    /// Frame rate limit while on battery. `Some(0)` freezes the last frame.
+    pub battery_fps: Option<u32>,
+    /// Freeze the last frame while on battery and below this charge in percent.
+    pub low_battery_threshold: Option<u8>,
+}
+
+impl PowerPolicy {
+    pub fn pacing(&self, state: &PowerState) -> FramePacing {
+        if !state.on_battery {
+            return FramePacing::Unlimited;
+        }
+        if let (Some(threshold), Some(capacity)) = (self.low_battery_threshold, state.capacity)
+            && capacity < threshold
+        {
+            return FramePacing::Frozen;
+        }
+        match self.battery_fps {
+            None => FramePacing::Unlimited,
+            Some(0) => FramePacing::Frozen,
+            Some(fps) => FramePacing::Limited(Duration::from_secs_f64(1.0 / fps as f64)),
+        }
+    }
+}
+
+/// Periodically re-reads the power state and maps it to a frame pacing.
+pub struct PowerMonitor {
+    policy: PowerPolicy,
+    state: Option<PowerState>,
+    next_poll: Instant,
+}
+
+impl PowerMonitor {
+    pub fn new(policy: PowerPolicy) -> Self {
+        PowerMonitor {
+            policy,
+            state: None,
+            next_poll: Instant::now(),
+        }
+    }
+
+    pub fn next_poll(&self) -> Instant {
+        self.next_poll
+    }
+
+    /// Read the power state if it is due. Returns the new pacing when the state changed.
+    pub fn poll(&mut self, now: Instant) -> Option<FramePacing> {
+        if now < self.next_poll {
+            return None;
+        }
+        self.next_poll = now + POLL_INTERVAL;
+
+        let state = match read_power_state(&self.policy.sysfs_root) {
+            Ok(state) => state,
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to read power supply state from {}: {}",
+                    self.policy.sysfs_root.display(),
+                    e
+                );
+                return None;
+            }
+        };
+        if self.state == Some(state) {
+            return None;
+        }
+        tracing::info!(
+            "Power state changed: on_battery={}, capacity={:?}",
+            state.on_battery,
+            state.capacity
+        );
+        self.state = Some(state);
+        Some(self.policy.pacing(&state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fake `power_supply` class directory with a supply per `(name, attributes)`.
+    fn fake_tree(name: &str, supplies: &[(&str, &[(&str, &str)])]) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("wayggle-bg-power-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        for (supply, attributes) in supplies {
+            let directory = root.join(supply);
+            std::fs::create_dir_all(&directory).unwrap();
+            for (attribute, value) in *attributes {
+                std::fs::write(directory.join(attribute), format!("{}\n", value)).unwrap();
+            }
+        }
+        root
+    }
+
+    fn read(name: &str, supplies: &[(&str, &[(&str, &str)])]) -> PowerState {
+        let root = fake_tree(name, supplies);
+        let state = read_power_state(&root).unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+        state
+    }
+
+    const BATTERY: (&str, &[(&str, &str)]) = (
+        "BAT0",
+        &[
+            ("type", "Battery"),
+            ("status", "Discharging"),
+            ("capacity", "40"),
+        ],
+    );
+
+    #[test]
+    fn mains_online_is_not_on_battery() {
+        let state = read(
+            "online",
+            &[("AC", &[("type", "Mains"), ("online", "1")]), BATTERY],
+        );
+        assert_eq!(
+            state,
+            PowerState {
+                on_battery: false,
+                capacity: Some(40),
+            }
+        );
+    }
+
+    #[test]
+    fn mains_offline_is_on_battery() {
+        let state = read(
+            "offline",
+            &[("AC", &[("type", "Mains"), ("online", "0")]), BATTERY],
+        );
+        assert!(state.on_battery);
+    }
+
+    #[test]
+    fn without_mains_the_battery_status_decides() {
+        assert!(read("no-mains", &[BATTERY]).on_battery);
+        let charging = read(
+            "charging",
+            &[("BAT0", &[("type", "Battery"), ("status", "Charging")])],
+        );
+        assert!(!charging.on_battery);
+    }
+
+    #[test]
+    fn device_batteries_are_ignored() {
+        let mouse = (
+            "hidpp_battery_0",
+            &[
+                ("type", "Battery"),
+                ("scope", "Device"),
+                ("status", "Discharging"),
+                ("capacity", "5"),
+            ][..],
+        );
+        let state = read("device", &[mouse]);
+        assert_eq!(
+            state,
+            PowerState {
+                on_battery: false,
+                capacity: None,
+            }
+        );
+        // A desktop with a mouse battery and mains power isn't on battery either.
+        let state = read(
+            "desktop",
+            &[("AC", &[("type", "Mains"), ("online", "0")]), mouse],
+        );
+        assert!(!state.on_battery);
+    }
+
+    #[test]
+    fn missing_capacity_is_none() {
+        let state = read(
+            "no-capacity",
+            &[("BAT0", &[("type", "Battery"), ("status", "Discharging")])],
+        );
+        assert_eq!(
+            state,
+            PowerState {
+                on_battery: true,
+                capacity: None,
+            }
+        );
+    }
+
+    #[test]
+    fn capacity_is_the_mean_of_the_batteries() {
+        let state = read(
+            "two-batteries",
+            &[
+                BATTERY,
+                ("BAT1", &[("type", "Battery"), ("capacity", "81")]),
+            ],
+        );
+        assert_eq!(state.capacity, Some(60));
+    }
+
+    #[test]
+    fn low_battery_threshold_freezes() {
+        let policy = PowerPolicy {
+            sysfs_root: PathBuf::new(),
+            battery_fps: Some(10),
+            low_battery_threshold: Some(20),
+        };
+        let state = |on_battery, capacity| PowerState {
+            on_battery,
+            capacity,
+        };
+        let limited = FramePacing::Limited(Duration::from_millis(100));
+        assert_eq!(
+            policy.pacing(&state(false, Some(5))),
+            FramePacing::Unlimited
+        );
+        assert_eq!(policy.pacing(&state(true, Some(19))), FramePacing::Frozen);
+        assert_eq!(policy.pacing(&state(true, Some(20))), limited);
+        assert_eq!(policy.pacing(&state(true, None)), limited);
+        let freeze_on_battery = PowerPolicy {
+            battery_fps: Some(0),
+            low_battery_threshold: None,
+            ..policy
+        };
+        assert_eq!(
+            freeze_on_battery.pacing(&state(true, Some(90))),
+            FramePacing::Frozen
+        );
+    }
+}
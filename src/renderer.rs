@@ -0,0 +1,11 @@
+use crate::error::Result;
+use crate::pipeline::FrameInput;
+
+/// Something frames are drawn into: a Wayland surface or an offscreen framebuffer.
+pub trait Renderer {
+    /// Draw a frame and present it.
+    fn render(&self, input: &FrameInput) -> Result<()>;
+
+    /// Size of the buffer in pixels.
+    fn size(&self) -> (u32, u32);
+}
@@ -0,0 +1,373 @@
+//! What a wallpaper shows, described the way a user picks it: a shader file, a shader by
+//! name or a picture, plus optional inputs. [`ShaderChoice::load`] and [`Inputs::spawn`]
+//! resolve them into the shader sources and channels the renderers take.
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::audio::{AudioInput, AudioSource};
+use crate::channels::{CHANNEL_COUNT, ChannelBinding, ChannelSource};
+use crate::cubemap::CubemapChannel;
+use crate::error::{Error, Result};
+use crate::image::{self, Fit, ImageChannel};
+use crate::keyboard::KeyboardInput;
+use crate::screencopy::ScreencopyChannel;
+use crate::shader::{ShaderMode, ShaderSource, package};
+use crate::volume::VolumeChannel;
+
+/// Which shader to show.
+#[derive(Clone, Debug)]
+pub enum ShaderChoice {
+    ShaderToy {
+        fragment_shader: PathBuf,
+    },
+    TheBookOfShaders {
+        fragment_shader: PathBuf,
+        vertex_shader: Option<PathBuf>,
+    },
+    /// An installed shader package or built-in shader. Packages shadow built-in shaders of
+    /// the same name.
+    Named(String),
+    /// An image shown through an effect shader.
+    Image {
+        file: PathBuf,
+        placement: Placement,
+    },
+    /// A video shown through an effect shader, starting over at the end if `looping`.
+    #[cfg(feature = "video")]
+    Video {
+        file: PathBuf,
+        looping: bool,
+        placement: Placement,
+    },
+}
+
+/// How an image or video is shown.
+#[derive(Clone, Debug)]
+pub struct Placement {
+    /// Shadertoy style `mainImage` calling `wallpaper(fragCoord)`, [`image::DEFAULT_EFFECT`]
+    /// if `None`.
+    pub effect: Option<PathBuf>,
+    pub fit: Fit,
+    /// Colour around the picture with [`Fit::Fit`] or [`Fit::Center`].
+    pub background: [f32; 3],
+    /// The `iChannel` the picture is bound to.
+    pub channel: u32,
+}
+
+/// A [`ShaderChoice`] resolved into what the renderers need.
+pub struct LoadedShader {
+    pub source: ShaderSource,
+    /// Tried before the built-in fallbacks when `source` fails to build.
+    pub fallback: Option<ShaderSource>,
+    /// Inputs the shader is made for, besides the optional [`Inputs`] and [`Textures`].
+    pub channels: Vec<ChannelBinding>,
+    /// Where an installed package expects the optional inputs.
+    pub package_channels: package::Channels,
+}
+
+impl LoadedShader {
+    fn new(source: ShaderSource) -> Self {
+        LoadedShader {
+            source,
+            fallback: None,
+            channels: Vec::new(),
+            package_channels: Default::default(),
+        }
+    }
+}
+
+impl ShaderChoice {
+    /// Read the shader and open the picture it shows, if any.
+    pub fn load(&self) -> Result<LoadedShader> {
+        match self {
+            ShaderChoice::ShaderToy { fragment_shader } => Ok(LoadedShader::new(
+                ShaderSource::from_files(ShaderMode::ShaderToy, fragment_shader, None)?,
+            )),
+            ShaderChoice::TheBookOfShaders {
+                fragment_shader,
+                vertex_shader,
+            } => Ok(LoadedShader::new(ShaderSource::from_files(
+                ShaderMode::TheBookOfShaders,
+                fragment_shader,
+                vertex_shader.as_deref(),
+            )?)),
+            ShaderChoice::Named(name) => {
+                let search_path = package::search_path();
+                match package::find_in(&search_path, name)? {
+                    Some(package) => Ok(LoadedShader {
+                        package_channels: package.manifest.channels,
+                        ..LoadedShader::new(package.source()?)
+                    }),
+                    None => Ok(LoadedShader::new(
+                        ShaderSource::builtin(name)
+                            .map_err(|_| package::unknown_name(&search_path, name))?,
+                    )),
+                }
+            }
+            ShaderChoice::Image { file, placement } => {
+                load_picture(placement, Rc::new(RefCell::new(ImageChannel::open(file)?)))
+            }
+            #[cfg(feature = "video")]
+            ShaderChoice::Video {
+                file,
+                looping,
+                placement,
+            } => load_picture(
+                placement,
+                Rc::new(RefCell::new(crate::video::VideoChannel::open(
+                    file, *looping,
+                )?)),
+            ),
+        }
+    }
+
+    /// Read the shader from disk again. Inputs such as the picture of `Image` stay as
+    /// they are.
+    pub fn reload(&self) -> Result<ShaderSource> {
+        match self {
+            ShaderChoice::Image { placement, .. } => placement.effect_shader(),
+            #[cfg(feature = "video")]
+            ShaderChoice::Video { placement, .. } => placement.effect_shader(),
+            choice => Ok(choice.load()?.source),
+        }
+    }
+}
+
+impl Placement {
+    /// The effect shader, wrapped to read the picture.
+    fn effect_shader(&self) -> Result<ShaderSource> {
+        let effect = match self.effect.as_deref() {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| Error::io(format!("failed to read shader {}", path.display()), e))?,
+            None => image::DEFAULT_EFFECT.to_string(),
+        };
+        Ok(image::effect_shader(
+            &effect,
+            self.channel,
+            self.fit,
+            self.background,
+        ))
+    }
+}
+
+/// An image or video shown through an effect shader.
+fn load_picture(
+    placement: &Placement,
+    picture: Rc<RefCell<dyn ChannelSource>>,
+) -> Result<LoadedShader> {
+    Ok(LoadedShader {
+        source: placement.effect_shader()?,
+        // A broken effect still shows the picture.
+        fallback: Some(image::effect_shader(
+            image::DEFAULT_EFFECT,
+            placement.channel,
+            placement.fit,
+            placement.background,
+        )),
+        channels: vec![ChannelBinding {
+            index: placement.channel,
+            source: picture,
+        }],
+        package_channels: Default::default(),
+    })
+}
+
+/// Live inputs of a wallpaper. Without an explicit channel the audio and keyboard take
+/// the one their shader package expects, else 0 and 1.
+#[derive(Clone, Debug)]
+pub struct Inputs {
+    pub audio: Option<AudioSource>,
+    pub audio_channel: Option<u32>,
+    /// evdev keyboard feeding the Shadertoy keyboard texture.
+    pub keyboard: Option<PathBuf>,
+    pub keyboard_channel: Option<u32>,
+    /// Output captured with wlr-screencopy every `screencopy_interval` rendered frames.
+    pub screencopy: Option<String>,
+    pub screencopy_channel: u32,
+    pub screencopy_interval: u32,
+}
+
+impl Default for Inputs {
+    fn default() -> Self {
+        Inputs {
+            audio: None,
+            audio_channel: None,
+            keyboard: None,
+            keyboard_channel: None,
+            screencopy: None,
+            screencopy_channel: 3,
+            screencopy_interval: 30,
+        }
+    }
+}
+
+impl Inputs {
+    /// Start the inputs, bound where `package_channels` expects them unless told otherwise.
+    pub fn spawn(&self, package_channels: &package::Channels) -> Result<Vec<ChannelBinding>> {
+        let mut bindings = Vec::new();
+        if let Some(source) = self.audio.clone() {
+            bindings.push(ChannelBinding {
+                index: self.audio_channel.or(package_channels.audio).unwrap_or(0),
+                source: Rc::new(RefCell::new(AudioInput::spawn(source)?)),
+            });
+        }
+        if let Some(device) = self.keyboard.clone() {
+            bindings.push(ChannelBinding {
+                index: self
+                    .keyboard_channel
+                    .or(package_channels.keyboard)
+                    .unwrap_or(1),
+                source: Rc::new(RefCell::new(KeyboardInput::spawn(device)?)),
+            });
+        }
+        if let Some(output) = self.screencopy.clone() {
+            bindings.push(ChannelBinding {
+                index: self.screencopy_channel,
+                source: Rc::new(RefCell::new(ScreencopyChannel::spawn(
+                    output,
+                    self.screencopy_interval,
+                )?)),
+            });
+        }
+        Ok(bindings)
+    }
+}
+
+/// Cubemap and volume textures for the shader.
+#[derive(Clone, Debug, Default)]
+pub struct Textures {
+    /// Six faces in the order +X -X +Y -Y +Z -Z, or one equirectangular image.
+    pub cubemap: Vec<PathBuf>,
+    pub cubemap_channel: Option<u32>,
+    /// Volume in Shadertoy's binary format.
+    pub volume: Option<PathBuf>,
+    pub volume_channel: Option<u32>,
+}
+
+impl Textures {
+    /// Open the textures. Without an explicit channel they take their default one, or the
+    /// lowest one none of `inputs` is bound to, sparing iChannel0 for the previous frame
+    /// with `feedback`.
+    pub fn bind(&self, inputs: &[ChannelBinding], feedback: bool) -> Result<Vec<ChannelBinding>> {
+        let mut taken: Vec<u32> = inputs.iter().map(|binding| binding.index).collect();
+        let mut pick = |requested: Option<u32>, default: u32, input: &str| {
+            let index = match requested {
+                Some(index) => index,
+                None => std::iter::once(default)
+                    .chain(if feedback { 1 } else { 0 }..CHANNEL_COUNT)
+                    .find(|index| !taken.contains(index))
+                    .ok_or_else(|| {
+                        Error::Configuration(format!("no iChannel is left for the {}", input))
+                    })?,
+            };
+            taken.push(index);
+            Ok::<_, Error>(index)
+        };
+        let mut bindings = Vec::new();
+        if !self.cubemap.is_empty() {
+            bindings.push(ChannelBinding {
+                index: pick(self.cubemap_channel, 2, "cubemap")?,
+                source: Rc::new(RefCell::new(CubemapChannel::open(&self.cubemap)?)),
+            });
+        }
+        if let Some(path) = self.volume.as_deref() {
+            bindings.push(ChannelBinding {
+                index: pick(self.volume_channel, 3, "volume")?,
+                source: Rc::new(RefCell::new(VolumeChannel::open(path)?)),
+            });
+        }
+        Ok(bindings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Blank;
+
+    impl ChannelSource for Blank {
+        fn resolution(&self) -> [f32; 3] {
+            [1.0, 1.0, 1.0]
+        }
+    }
+
+    fn bindings(indices: &[u32]) -> Vec<ChannelBinding> {
+        indices
+            .iter()
+            .map(|&index| ChannelBinding {
+                index,
+                source: Rc::new(RefCell::new(Blank)),
+            })
+            .collect()
+    }
+
+    /// Textures with a one pixel panorama as the cubemap.
+    fn cubemap(name: &str, channel: Option<u32>) -> (Textures, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "wayggle-bg-scene-{}-{}.png",
+            std::process::id(),
+            name
+        ));
+        ::image::RgbaImage::new(2, 1).save(&path).unwrap();
+        let textures = Textures {
+            cubemap: vec![path.clone()],
+            cubemap_channel: channel,
+            ..Default::default()
+        };
+        (textures, path)
+    }
+
+    fn bound_index(textures: &Textures, inputs: &[u32], feedback: bool) -> Result<u32> {
+        let bound = textures.bind(&bindings(inputs), feedback)?;
+        assert_eq!(bound.len(), 1);
+        Ok(bound[0].index)
+    }
+
+    #[test]
+    fn textures_take_the_lowest_free_channel() {
+        let (textures, path) = cubemap("free", None);
+        assert_eq!(bound_index(&textures, &[], false).unwrap(), 2);
+        assert_eq!(bound_index(&textures, &[2], false).unwrap(), 0);
+        // The previous frame keeps iChannel0.
+        assert_eq!(bound_index(&textures, &[2], true).unwrap(), 1);
+        assert!(matches!(
+            bound_index(&textures, &[0, 1, 2, 3], false),
+            Err(Error::Configuration(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn textures_keep_an_explicit_channel() {
+        // Even a taken one; binding both is rejected later, naming the channel.
+        let (textures, path) = cubemap("explicit", Some(3));
+        assert_eq!(bound_index(&textures, &[], false).unwrap(), 3);
+        assert_eq!(bound_index(&textures, &[3], false).unwrap(), 3);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn pictures_are_bound_with_a_fallback() {
+        let (textures, path) = cubemap("picture", None);
+        let choice = ShaderChoice::Image {
+            file: path.clone(),
+            placement: Placement {
+                effect: None,
+                fit: Fit::Fit,
+                background: [0.0; 3],
+                channel: 1,
+            },
+        };
+        let loaded = choice.load().unwrap();
+        assert_eq!(loaded.channels.len(), 1);
+        assert_eq!(loaded.channels[0].index, 1);
+        assert_eq!(loaded.fallback.as_ref(), Some(&loaded.source));
+        assert_eq!(choice.reload().unwrap(), loaded.source);
+        // The cubemap steers clear of the picture.
+        assert_eq!(textures.bind(&loaded.channels, false).unwrap()[0].index, 2);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,591 @@
+//! The contents of an output as a channel texture, captured with wlr-screencopy.
+//!
+//! Captures run on a background thread with a Wayland connection of their own, into a
+//! shared memory buffer, and are requested by the render loop every few frames.
+
+use std::fs::File;
+use std::os::fd::AsFd as _;
+use std::os::unix::fs::FileExt as _;
+use std::sync::mpsc;
+
+use glow::HasContext;
+use wayland_client::globals::{GlobalError, GlobalListContents, registry_queue_init};
+use wayland_client::protocol::{wl_buffer, wl_output, wl_registry, wl_shm, wl_shm_pool};
+use wayland_client::{Connection, Dispatch, EventQueue, Proxy as _, QueueHandle, WEnum};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1, zwlr_screencopy_manager_v1,
+};
+
+use crate::channels::ChannelSource;
+use crate::error::{Error, Result};
+
+/// A captured output, RGBA with the top row first.
+struct Frame {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// An output captured every `interval` frames.
+///
+/// Capturing the output the wallpaper itself is shown on would feed every frame back
+/// into the next one, so captures stop while the wallpaper is on the captured output,
+/// and only start once it is known which output that is.
+pub struct ScreencopyChannel {
+    output: String,
+    interval: u32,
+    frames_until_capture: u32,
+    requests: mpsc::Sender<()>,
+    captures: mpsc::Receiver<Option<Frame>>,
+    capture_pending: bool,
+    /// Whether the wallpaper is shown on the captured output, `None` until the first
+    /// [`set_output`](ChannelSource::set_output).
+    on_captured_output: Option<bool>,
+    frame: Option<Frame>,
+    /// The textures `frame` was uploaded into, one per pipeline.
+    uploaded: Vec<glow::Texture>,
+    /// The textures with storage allocated for frames of the current size.
+    allocated: Vec<glow::Texture>,
+}
+
+impl ScreencopyChannel {
+    /// Capture the output with the connector name `output`, such as `DP-1`, every
+    /// `interval` rendered frames. Fails if the compositor doesn't support screencopy or
+    /// has no such output.
+    pub fn spawn(output: String, interval: u32) -> Result<Self> {
+        let interval = interval.max(1);
+        let conn = Connection::connect_to_env()?;
+        let (globals, mut queue) =
+            registry_queue_init::<CaptureState>(&conn).map_err(|e| match e {
+                GlobalError::Backend(e) => Error::WaylandBackend(e),
+                GlobalError::InvalidId(_) => Error::MissingGlobal("wl_registry"),
+            })?;
+        let qh = queue.handle();
+        let manager: zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1 = globals
+            .bind(&qh, 1..=3, ())
+            .map_err(|_| Error::MissingGlobal("zwlr_screencopy_manager_v1"))?;
+        let shm: wl_shm::WlShm = globals
+            .bind(&qh, 1..=1, ())
+            .map_err(|_| Error::MissingGlobal("wl_shm"))?;
+        for global in globals.contents().clone_list() {
+            if global.interface == "wl_output" {
+                let _: wl_output::WlOutput =
+                    globals
+                        .registry()
+                        .bind(global.name, global.version.min(4), &qh, ());
+            }
+        }
+
+        // The output names arrive with the first roundtrip.
+        let mut state = CaptureState::default();
+        queue.roundtrip(&mut state)?;
+        let Some(captured) = state
+            .outputs
+            .iter()
+            .find(|(_, name)| *name == output)
+            .map(|(captured, _)| captured.clone())
+        else {
+            let names: Vec<&str> = state
+                .outputs
+                .iter()
+                .map(|(_, name)| name.as_str())
+                .collect();
+            return Err(Error::Configuration(format!(
+                "no output named '{}' to capture, there are: {}",
+                output,
+                names.join(", ")
+            )));
+        };
+        tracing::info!("Capturing output {} every {} frames", output, interval);
+
+        let (requests, requested) = mpsc::channel();
+        let (captured_frames, captures) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("screencopy".to_string())
+            .spawn(move || {
+                let capturer = Capturer {
+                    queue,
+                    state,
+                    manager,
+                    shm,
+                    output: captured,
+                    buffer: None,
+                };
+                capturer.run(requested, captured_frames)
+            })
+            .map_err(|e| Error::io("failed to spawn the screencopy thread", e))?;
+
+        Ok(ScreencopyChannel {
+            output,
+            interval,
+            frames_until_capture: 0,
+            requests,
+            captures,
+            capture_pending: false,
+            on_captured_output: None,
+            frame: None,
+            uploaded: Vec::new(),
+            allocated: Vec::new(),
+        })
+    }
+}
+
+impl ChannelSource for ScreencopyChannel {
+    fn init_texture(&mut self, _gl: &glow::Context, texture: glow::Texture) {
+        // A texture of a dropped pipeline may have had the same name.
+        self.uploaded.retain(|uploaded| *uploaded != texture);
+        self.allocated.retain(|allocated| *allocated != texture);
+    }
+
+    fn advance(&mut self) {
+        for capture in self.captures.try_iter() {
+            self.capture_pending = false;
+            let Some(frame) = capture else {
+                continue;
+            };
+            if self
+                .frame
+                .as_ref()
+                .is_none_or(|old| (old.width, old.height) != (frame.width, frame.height))
+            {
+                self.allocated.clear();
+            }
+            self.frame = Some(frame);
+            self.uploaded.clear();
+        }
+
+        if self.on_captured_output == Some(false) && !self.capture_pending {
+            if self.frames_until_capture == 0 {
+                self.capture_pending = self.requests.send(()).is_ok();
+                self.frames_until_capture = self.interval - 1;
+            } else {
+                self.frames_until_capture -= 1;
+            }
+        }
+    }
+
+    fn update(&mut self, gl: &glow::Context, texture: glow::Texture) {
+        let Some(frame) = self.frame.as_ref() else {
+            return;
+        };
+        if self.uploaded.contains(&texture) {
+            return;
+        }
+        unsafe {
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+            let pixels = glow::PixelUnpackData::Slice(Some(&frame.pixels));
+            if self.allocated.contains(&texture) {
+                gl.tex_sub_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    0,
+                    0,
+                    frame.width as i32,
+                    frame.height as i32,
+                    glow::RGBA,
+                    glow::UNSIGNED_BYTE,
+                    pixels,
+                );
+            } else {
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    glow::RGBA8 as i32,
+                    frame.width as i32,
+                    frame.height as i32,
+                    0,
+                    glow::RGBA,
+                    glow::UNSIGNED_BYTE,
+                    pixels,
+                );
+                self.allocated.push(texture);
+            }
+        }
+        self.uploaded.push(texture);
+    }
+
+    fn resolution(&self) -> [f32; 3] {
+        self.frame.as_ref().map_or([1.0, 1.0, 1.0], |frame| {
+            [frame.width as f32, frame.height as f32, 1.0]
+        })
+    }
+
+    fn set_output(&mut self, name: Option<&str>) {
+        let on_captured_output = name == Some(self.output.as_str());
+        if on_captured_output && self.on_captured_output != Some(true) {
+            tracing::warn!(
+                "The wallpaper is shown on {}, the output it captures; keeping the last capture",
+                self.output
+            );
+        } else if !on_captured_output && self.on_captured_output == Some(true) {
+            tracing::info!("Capturing output {} again", self.output);
+        }
+        self.on_captured_output = Some(on_captured_output);
+    }
+}
+
+/// A shared memory buffer frames are copied into.
+struct ShmBuffer {
+    file: File,
+    buffer: wl_buffer::WlBuffer,
+    format: BufferFormat,
+}
+
+/// Layout of a frame as offered by the compositor.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct BufferFormat {
+    format: wl_shm::Format,
+    width: u32,
+    height: u32,
+    stride: u32,
+}
+
+/// Owns the capture connection on the screencopy thread.
+struct Capturer {
+    queue: EventQueue<CaptureState>,
+    state: CaptureState,
+    manager: zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+    shm: wl_shm::WlShm,
+    output: wl_output::WlOutput,
+    buffer: Option<ShmBuffer>,
+}
+
+impl Capturer {
+    /// Capture a frame per request until the channel is dropped. Failed captures are
+    /// reported as `None`.
+    fn run(mut self, requested: mpsc::Receiver<()>, captured: mpsc::Sender<Option<Frame>>) {
+        for () in requested {
+            let frame = self.capture().inspect_err(|e| {
+                tracing::warn!("Failed to capture the output: {}", e);
+            });
+            if captured.send(frame.ok()).is_err() {
+                break;
+            }
+        }
+        if let Some(buffer) = self.buffer.take() {
+            buffer.buffer.destroy();
+        }
+        self.manager.destroy();
+    }
+
+    fn capture(&mut self) -> Result<Frame> {
+        let qh = self.queue.handle();
+        self.state.frame = FrameState::default();
+        let frame = self.manager.capture_output(0, &self.output, &qh, ());
+        while !self.state.frame.buffer_done && !self.state.frame.failed {
+            self.queue.blocking_dispatch(&mut self.state)?;
+        }
+        let result = self.copy(&frame, &qh);
+        frame.destroy();
+        result
+    }
+
+    /// Copy the frame offered by `frame` into shared memory and read it back.
+    fn copy(
+        &mut self,
+        frame: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        qh: &QueueHandle<CaptureState>,
+    ) -> Result<Frame> {
+        let failed = || {
+            Error::io(
+                "screencopy",
+                std::io::Error::other("the compositor failed to copy the output"),
+            )
+        };
+        let Some(format) = self.state.frame.format.filter(|_| !self.state.frame.failed) else {
+            return Err(failed());
+        };
+        if self
+            .buffer
+            .as_ref()
+            .is_none_or(|buffer| buffer.format != format)
+        {
+            if let Some(old) = self.buffer.take() {
+                old.buffer.destroy();
+            }
+            self.buffer = Some(self.create_buffer(format, qh)?);
+        }
+        let Some(buffer) = self.buffer.as_ref() else {
+            return Err(failed());
+        };
+
+        frame.copy(&buffer.buffer);
+        while !self.state.frame.ready && !self.state.frame.failed {
+            self.queue.blocking_dispatch(&mut self.state)?;
+        }
+        if self.state.frame.failed {
+            return Err(failed());
+        }
+
+        let mut data = vec![0; (format.stride * format.height) as usize];
+        buffer
+            .file
+            .read_exact_at(&mut data, 0)
+            .map_err(|e| Error::io("failed to read the captured frame", e))?;
+        Ok(to_rgba(&data, format, self.state.frame.y_invert))
+    }
+
+    fn create_buffer(
+        &self,
+        format: BufferFormat,
+        qh: &QueueHandle<CaptureState>,
+    ) -> Result<ShmBuffer> {
+        let size = format.stride * format.height;
+        let fd = rustix::fs::memfd_create("wayggle-bg-screencopy", rustix::fs::MemfdFlags::CLOEXEC)
+            .map_err(|e| Error::io("failed to create the screencopy buffer", e.into()))?;
+        let file = File::from(fd);
+        file.set_len(size as u64)
+            .map_err(|e| Error::io("failed to create the screencopy buffer", e))?;
+        let pool = self.shm.create_pool(file.as_fd(), size as i32, qh, ());
+        let buffer = pool.create_buffer(
+            0,
+            format.width as i32,
+            format.height as i32,
+            format.stride as i32,
+            format.format,
+            qh,
+            (),
+        );
+        pool.destroy();
+        Ok(ShmBuffer {
+            file,
+            buffer,
+            format,
+        })
+    }
+}
+
+/// Convert a captured frame into RGBA with the top row first.
+fn to_rgba(data: &[u8], format: BufferFormat, y_invert: bool) -> Frame {
+    let (width, height, stride) = (
+        format.width as usize,
+        format.height as usize,
+        format.stride as usize,
+    );
+    // The formats are little endian, so ARGB is stored as BGRA.
+    let bgr = matches!(
+        format.format,
+        wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888
+    );
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        let row = if y_invert { height - 1 - y } else { y };
+        for pixel in data[row * stride..][..width * 4].chunks_exact(4) {
+            let (red, blue) = if bgr {
+                (pixel[2], pixel[0])
+            } else {
+                (pixel[0], pixel[2])
+            };
+            // Outputs are opaque; the X formats leave the alpha byte undefined.
+            pixels.extend_from_slice(&[red, pixel[1], blue, 255]);
+        }
+    }
+    Frame {
+        width: format.width,
+        height: format.height,
+        pixels,
+    }
+}
+
+#[derive(Default)]
+struct CaptureState {
+    /// Outputs with their connector names.
+    outputs: Vec<(wl_output::WlOutput, String)>,
+    frame: FrameState,
+}
+
+/// Events of the frame being captured.
+#[derive(Default)]
+struct FrameState {
+    /// The first buffer layout offered that can be converted to RGBA.
+    format: Option<BufferFormat>,
+    buffer_done: bool,
+    y_invert: bool,
+    ready: bool,
+    failed: bool,
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for CaptureState {
+    fn event(
+        _state: &mut Self,
+        _registry: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Do nothing: Outputs added later aren't captured.
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, ()> for CaptureState {
+    fn event(
+        state: &mut Self,
+        output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wl_output::Event::Name { name } = event {
+            state.outputs.push((output.clone(), name));
+        }
+    }
+}
+
+impl Dispatch<wl_shm::WlShm, ()> for CaptureState {
+    fn event(
+        _state: &mut Self,
+        _shm: &wl_shm::WlShm,
+        _event: wl_shm::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Do nothing: The screencopy frame tells which format to use.
+    }
+}
+
+impl Dispatch<wl_shm_pool::WlShmPool, ()> for CaptureState {
+    fn event(
+        _state: &mut Self,
+        _pool: &wl_shm_pool::WlShmPool,
+        _event: wl_shm_pool::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Do nothing: WlShmPool never dispatches events.
+    }
+}
+
+impl Dispatch<wl_buffer::WlBuffer, ()> for CaptureState {
+    fn event(
+        _state: &mut Self,
+        _buffer: &wl_buffer::WlBuffer,
+        _event: wl_buffer::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Do nothing: The buffer is only reused once the copy is ready.
+    }
+}
+
+impl Dispatch<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1, ()> for CaptureState {
+    fn event(
+        _state: &mut Self,
+        _manager: &zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+        _event: zwlr_screencopy_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Do nothing: ScreencopyManager never dispatches events.
+    }
+}
+
+impl Dispatch<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1, ()> for CaptureState {
+    fn event(
+        state: &mut Self,
+        frame: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let frame_state = &mut state.frame;
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format: WEnum::Value(format),
+                width,
+                height,
+                stride,
+            } => {
+                let supported = matches!(
+                    format,
+                    wl_shm::Format::Argb8888
+                        | wl_shm::Format::Xrgb8888
+                        | wl_shm::Format::Abgr8888
+                        | wl_shm::Format::Xbgr8888
+                );
+                if supported && frame_state.format.is_none() {
+                    frame_state.format = Some(BufferFormat {
+                        format,
+                        width,
+                        height,
+                        stride,
+                    });
+                }
+                // Before version 3, the only buffer event is all there is.
+                if frame.version() < 3 {
+                    frame_state.buffer_done = true;
+                }
+            }
+            zwlr_screencopy_frame_v1::Event::BufferDone => frame_state.buffer_done = true,
+            zwlr_screencopy_frame_v1::Event::Flags { flags } => {
+                frame_state.y_invert = matches!(
+                    flags,
+                    WEnum::Value(flags) if flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert)
+                );
+            }
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => frame_state.ready = true,
+            zwlr_screencopy_frame_v1::Event::Failed => frame_state.failed = true,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: wl_shm::Format, width: u32, height: u32, stride: u32) -> BufferFormat {
+        BufferFormat {
+            format,
+            width,
+            height,
+            stride,
+        }
+    }
+
+    #[test]
+    fn swaps_blue_and_red_of_argb() {
+        let data = [10, 20, 30, 0, 40, 50, 60, 0];
+        for shm_format in [wl_shm::Format::Argb8888, wl_shm::Format::Xrgb8888] {
+            let frame = to_rgba(&data, format(shm_format, 2, 1, 8), false);
+            assert_eq!(frame.pixels, [30, 20, 10, 255, 60, 50, 40, 255]);
+        }
+    }
+
+    #[test]
+    fn keeps_the_order_of_abgr() {
+        let data = [10, 20, 30, 0, 40, 50, 60, 7];
+        for shm_format in [wl_shm::Format::Abgr8888, wl_shm::Format::Xbgr8888] {
+            let frame = to_rgba(&data, format(shm_format, 2, 1, 8), false);
+            assert_eq!(frame.pixels, [10, 20, 30, 255, 40, 50, 60, 255]);
+        }
+    }
+
+    #[test]
+    fn flips_inverted_frames() {
+        let data = [1, 1, 1, 0, 2, 2, 2, 0];
+        let format = || format(wl_shm::Format::Abgr8888, 1, 2, 4);
+        assert_eq!(
+            to_rgba(&data, format(), false).pixels,
+            [1, 1, 1, 255, 2, 2, 2, 255]
+        );
+        assert_eq!(
+            to_rgba(&data, format(), true).pixels,
+            [2, 2, 2, 255, 1, 1, 1, 255]
+        );
+    }
+
+    #[test]
+    fn skips_the_stride_padding() {
+        let data = [1, 2, 3, 0, 9, 9, 9, 9, 4, 5, 6, 0, 9, 9, 9, 9];
+        let frame = to_rgba(&data, format(wl_shm::Format::Xbgr8888, 1, 2, 8), false);
+        assert_eq!((frame.width, frame.height), (1, 2));
+        assert_eq!(frame.pixels, [1, 2, 3, 255, 4, 5, 6, 255]);
+    }
+}
@@ -0,0 +1,148 @@
+mod builtin;
+pub mod package;
+
+pub use builtin::{BUILTIN_SHADERS, BuiltinShader};
+
+use std::path::Path;
+
+use crate::adaptors;
+use crate::channels::{ChannelBinding, channel_kinds};
+use crate::error::{Error, Result};
+
+/// The error for a shader `name` that isn't one of `candidates`, suggesting the closest.
+fn unknown_name<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Error {
+    let mut message = format!("no shader named '{}'", name);
+    if let Some(suggestion) = builtin::suggest(name, candidates) {
+        message.push_str(&format!("; did you mean '{}'?", suggestion));
+    }
+    Error::Configuration(message)
+}
+
+/// Vertex shader used when a shader doesn't bring its own.
+pub const DEFAULT_VERTEX_SHADER: &str = include_str!("../shaders/default-vert.glsl");
+
+/// Colour shown when no shader compiles at all.
+const FALLBACK_COLOUR: [f32; 3] = [0.1, 0.1, 0.12];
+
+/// The uniform conventions a fragment shader is written against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+pub enum ShaderMode {
+    /// A Shadertoy `mainImage`, wrapped by [`adaptors::shader_toy_adaptor`].
+    #[serde(rename = "shadertoy")]
+    ShaderToy,
+    /// A complete GLSL ES 3.0 shader using The Book of Shaders' `u_time`, `u_resolution`, ...
+    #[serde(rename = "the_book_of_shaders")]
+    TheBookOfShaders,
+}
+
+/// Shader sources as written by their author, before adaptation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderSource {
+    pub mode: ShaderMode,
+    pub fragment_shader: String,
+    /// Replaces [`DEFAULT_VERTEX_SHADER`] when set.
+    pub vertex_shader: Option<String>,
+}
+
+impl ShaderSource {
+    pub fn shader_toy(fragment_shader: impl Into<String>) -> Self {
+        ShaderSource {
+            mode: ShaderMode::ShaderToy,
+            fragment_shader: fragment_shader.into(),
+            vertex_shader: None,
+        }
+    }
+
+    pub fn the_book_of_shaders(
+        fragment_shader: impl Into<String>,
+        vertex_shader: Option<String>,
+    ) -> Self {
+        ShaderSource {
+            mode: ShaderMode::TheBookOfShaders,
+            fragment_shader: fragment_shader.into(),
+            vertex_shader,
+        }
+    }
+
+    /// Read the fragment shader, and the vertex shader if given, from files.
+    pub fn from_files(
+        mode: ShaderMode,
+        fragment_shader: &Path,
+        vertex_shader: Option<&Path>,
+    ) -> Result<Self> {
+        let read = |path: &Path| {
+            std::fs::read_to_string(path)
+                .map_err(|e| Error::io(format!("failed to read shader {}", path.display()), e))
+        };
+        Ok(ShaderSource {
+            mode,
+            fragment_shader: read(fragment_shader)?,
+            vertex_shader: vertex_shader.map(read).transpose()?,
+        })
+    }
+
+    /// One of the shaders compiled into the binary. An unknown name is a configuration
+    /// error suggesting the closest built-in name; see [`package::unknown_name`] for one
+    /// that also knows the installed packages.
+    pub fn builtin(name: &str) -> Result<Self> {
+        let Some(shader) = builtin::find(name) else {
+            return Err(unknown_name(
+                name,
+                BUILTIN_SHADERS.iter().map(|shader| shader.name),
+            ));
+        };
+        Ok(ShaderSource {
+            mode: shader.mode,
+            fragment_shader: shader.source.to_string(),
+            vertex_shader: None,
+        })
+    }
+
+    /// A shader filling the screen with one colour, which compiles on any driver.
+    pub fn solid_colour([red, green, blue]: [f32; 3]) -> Self {
+        ShaderSource::the_book_of_shaders(
+            format!(
+                "#version 300 es\n\
+                 precision mediump float;\n\
+                 out vec4 fragColor;\n\
+                 void main(){{fragColor=vec4({:?},{:?},{:?},1.);}}\n",
+                red, green, blue
+            ),
+            None,
+        )
+    }
+
+    /// Shaders to try in order until one compiles: this one, `fallback`, the built-in
+    /// Voronoi and finally a solid colour. Each comes with a label for diagnostics.
+    pub fn fallback_chain(
+        &self,
+        fallback: Option<&ShaderSource>,
+    ) -> Vec<(&'static str, ShaderSource)> {
+        let mut chain = vec![("shader", self.clone())];
+        if let Some(fallback) = fallback {
+            chain.push(("fallback shader", fallback.clone()));
+        }
+        if let Ok(voronoi) = ShaderSource::builtin("voronoi") {
+            chain.push(("built-in Voronoi shader", voronoi));
+        }
+        chain.push(("solid colour", ShaderSource::solid_colour(FALLBACK_COLOUR)));
+        chain
+    }
+
+    /// The vertex and fragment shader to compile, with the adaptor of the mode applied.
+    /// The Shadertoy adaptor declares the `iChannel` samplers to match `channels`; Book of
+    /// Shaders shaders declare their own.
+    pub fn to_glsl(&self, channels: &[ChannelBinding]) -> (String, String) {
+        let vertex_shader = self
+            .vertex_shader
+            .clone()
+            .unwrap_or_else(|| DEFAULT_VERTEX_SHADER.to_string());
+        let fragment_shader = match self.mode {
+            ShaderMode::ShaderToy => {
+                adaptors::shader_toy_adaptor(self.fragment_shader.clone(), &channel_kinds(channels))
+            }
+            ShaderMode::TheBookOfShaders => self.fragment_shader.clone(),
+        };
+        (vertex_shader, fragment_shader)
+    }
+}
@@ -0,0 +1,104 @@
+use super::ShaderMode;
+
+/// A shader compiled into the binary, with what `list` shows about it.
+#[derive(Clone, Copy, Debug)]
+pub struct BuiltinShader {
+    pub name: &'static str,
+    pub author: &'static str,
+    pub licence: &'static str,
+    pub mode: ShaderMode,
+    pub description: &'static str,
+    /// Where the shader was published, if elsewhere.
+    pub url: Option<&'static str>,
+    pub source: &'static str,
+}
+
+pub const BUILTIN_SHADERS: &[BuiltinShader] = &[
+    BuiltinShader {
+        name: "box",
+        author: "unattributed",
+        licence: "CC0",
+        mode: ShaderMode::ShaderToy,
+        description: "Raymarched glowing cube",
+        url: Some("https://www.shadertoy.com/view/3XdXRr"),
+        source: include_str!("../../shaders/box.glsl"),
+    },
+    BuiltinShader {
+        name: "voronoi",
+        author: "comavius",
+        licence: "MIT",
+        mode: ShaderMode::TheBookOfShaders,
+        description: "Drifting Voronoi cells, also the fallback when a shader fails to compile",
+        url: None,
+        source: include_str!("../../shaders/default-frag.glsl"),
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static BuiltinShader> {
+    BUILTIN_SHADERS.iter().find(|shader| shader.name == name)
+}
+
+/// How similar a name must be to a misspelt one to be suggested, from 0 to 1. Two typos
+/// in a five letter name are just close enough.
+const MIN_SIMILARITY: f64 = 0.6;
+
+/// The known name closest to a misspelt `name`, if any is close enough to be a typo.
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            // An abbreviation is as good a guess as a typo.
+            let similarity = if !name.is_empty() && candidate.starts_with(name) {
+                1.0
+            } else {
+                strsim::normalized_damerau_levenshtein(name, candidate)
+            };
+            (candidate, similarity)
+        })
+        .filter(|(_, similarity)| *similarity >= MIN_SIMILARITY)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(candidate, _)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 3] = ["box", "voronoi", "plasma"];
+
+    #[test]
+    fn suggests_names_a_prefix_abbreviates() {
+        assert_eq!(suggest("vor", NAMES), Some("voronoi"));
+        assert_eq!(suggest("p", NAMES), Some("plasma"));
+        assert_eq!(suggest("voronoi", NAMES), Some("voronoi"));
+    }
+
+    #[test]
+    fn suggests_names_with_typos() {
+        assert_eq!(suggest("boz", NAMES), Some("box"));
+        assert_eq!(suggest("vornooi", NAMES), Some("voronoi"));
+        assert_eq!(suggest("plsama", NAMES), Some("plasma"));
+        // The closest name wins, and a name the input abbreviates beats any typo.
+        assert_eq!(suggest("voronio", ["voronxyz", "voronoi"]), Some("voronoi"));
+        assert_eq!(suggest("boxes", ["boxed", "boxes2"]), Some("boxes2"));
+    }
+
+    #[test]
+    fn the_threshold_is_inclusive() {
+        // Two of five letters differ: a similarity of exactly 0.6.
+        assert_eq!(
+            strsim::normalized_damerau_levenshtein("abcxy", "abcde"),
+            MIN_SIMILARITY
+        );
+        assert_eq!(suggest("abcxy", ["abcde"]), Some("abcde"));
+        // Three of seven: below it.
+        assert_eq!(suggest("voxyzoi", NAMES), None);
+        assert_eq!(suggest("tunnel", NAMES), None);
+    }
+
+    #[test]
+    fn empty_names_get_no_suggestion() {
+        assert_eq!(suggest("", NAMES), None);
+        assert_eq!(suggest("box", []), None);
+    }
+}
@@ -0,0 +1,389 @@
+//! Shader packages installed under the XDG data directories.
+//!
+//! A package is a directory `wayggle-bg/shaders/<name>/` holding a `manifest.toml`:
+//!
+//! ```toml
+//! mode = "shadertoy"            # or "the_book_of_shaders"
+//! description = "Sound-reactive tunnel"
+//! author = "someone"
+//! licence = "CC-BY-NC-SA-3.0"
+//!
+//! [[passes]]
+//! fragment = "image.glsl"
+//! # vertex = "vertex.glsl"
+//!
+//! [channels]
+//! audio = 0
+//! keyboard = 1
+//! ```
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use super::{BUILTIN_SHADERS, ShaderMode, ShaderSource};
+use crate::channels::CHANNEL_COUNT;
+use crate::error::{Error, Result};
+
+pub const MANIFEST_FILE: &str = "manifest.toml";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub mode: ShaderMode,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub licence: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    pub passes: Vec<Pass>,
+    #[serde(default)]
+    pub channels: Channels,
+}
+
+/// Shader files of one render pass, relative to the package directory.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pass {
+    pub fragment: PathBuf,
+    pub vertex: Option<PathBuf>,
+}
+
+/// The iChannel each input is expected on. The command line still decides whether
+/// the input is enabled, and its `--*-channel` options take precedence.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Channels {
+    pub audio: Option<u32>,
+    pub keyboard: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderPackage {
+    pub name: String,
+    pub directory: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl ShaderPackage {
+    /// Read and validate the manifest of the package in `directory`.
+    pub fn load(directory: &Path) -> Result<Self> {
+        let manifest_path = directory.join(MANIFEST_FILE);
+        let text = std::fs::read_to_string(&manifest_path)
+            .map_err(|e| Error::io(format!("failed to read {}", manifest_path.display()), e))?;
+        let invalid = |message: String| {
+            Error::Configuration(format!("{}: {}", manifest_path.display(), message))
+        };
+        let manifest: Manifest = toml::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+        match manifest.passes.len() {
+            0 => return Err(invalid("no passes".to_string())),
+            1 => {}
+            // Only the image pass is rendered for now.
+            n => return Err(invalid(format!("{} passes, but only one is supported", n))),
+        }
+        for index in [manifest.channels.audio, manifest.channels.keyboard]
+            .into_iter()
+            .flatten()
+        {
+            if index >= CHANNEL_COUNT {
+                return Err(invalid(format!(
+                    "channel {} out of range 0..{}",
+                    index, CHANNEL_COUNT
+                )));
+            }
+        }
+        Ok(ShaderPackage {
+            name: directory
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            directory: directory.to_path_buf(),
+            manifest,
+        })
+    }
+
+    /// Read the shader files of the package.
+    pub fn source(&self) -> Result<ShaderSource> {
+        let pass = &self.manifest.passes[0];
+        ShaderSource::from_files(
+            self.manifest.mode,
+            &self.directory.join(&pass.fragment),
+            pass.vertex
+                .as_ref()
+                .map(|vertex| self.directory.join(vertex))
+                .as_deref(),
+        )
+    }
+}
+
+/// Directories searched for packages, most important first: `$XDG_DATA_HOME`, then
+/// each of `$XDG_DATA_DIRS`, with the defaults of the base directory specification.
+pub fn search_path() -> Vec<PathBuf> {
+    search_path_with(|name| std::env::var_os(name))
+}
+
+/// [`search_path`] with the environment variables read through `var`.
+fn search_path_with(var: impl Fn(&str) -> Option<OsString>) -> Vec<PathBuf> {
+    // Relative paths are invalid in the XDG variables and are ignored.
+    let absolute = |path: PathBuf| path.is_absolute().then_some(path);
+    let data_home = var("XDG_DATA_HOME")
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .and_then(absolute)
+        .or_else(|| {
+            var("HOME")
+                .map(|home| PathBuf::from(home).join(".local/share"))
+                .and_then(absolute)
+        });
+    let data_dirs = var("XDG_DATA_DIRS")
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "/usr/local/share/:/usr/share/".into());
+    data_home
+        .into_iter()
+        .chain(std::env::split_paths(&data_dirs).filter_map(absolute))
+        .map(|dir| dir.join("wayggle-bg/shaders"))
+        .collect()
+}
+
+/// Every installed package, in search path order. A name installed in several
+/// directories is only listed from the first. Broken packages are skipped with a warning.
+pub fn discover() -> Vec<ShaderPackage> {
+    discover_in(&search_path())
+}
+
+/// [`discover`] in the package directories `search_path` instead of the XDG ones.
+pub fn discover_in(search_path: &[PathBuf]) -> Vec<ShaderPackage> {
+    let mut packages: Vec<ShaderPackage> = Vec::new();
+    for dir in search_path {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        let mut directories: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.join(MANIFEST_FILE).is_file())
+            .collect();
+        directories.sort();
+        for directory in directories {
+            match ShaderPackage::load(&directory) {
+                Ok(package) if packages.iter().any(|known| known.name == package.name) => {}
+                Ok(package) => packages.push(package),
+                Err(e) => tracing::warn!("Skipping shader package: {}", e),
+            }
+        }
+    }
+    packages
+}
+
+/// The package called `name` from the first directory of the search path that has one.
+pub fn find(name: &str) -> Result<Option<ShaderPackage>> {
+    find_in(&search_path(), name)
+}
+
+/// [`find`] in the package directories `search_path` instead of the XDG ones.
+pub fn find_in(search_path: &[PathBuf], name: &str) -> Result<Option<ShaderPackage>> {
+    // Names are directory names; anything path-like can't be one.
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Ok(None);
+    }
+    search_path
+        .iter()
+        .map(|dir| dir.join(name))
+        .find(|directory| directory.join(MANIFEST_FILE).is_file())
+        .map(|directory| ShaderPackage::load(&directory))
+        .transpose()
+}
+
+/// The error for a shader `name` that is neither installed in `search_path` nor built in,
+/// suggesting the closest name of either.
+pub fn unknown_name(search_path: &[PathBuf], name: &str) -> Error {
+    let packages = discover_in(search_path);
+    super::unknown_name(
+        name,
+        BUILTIN_SHADERS
+            .iter()
+            .map(|shader| shader.name)
+            .chain(packages.iter().map(|package| package.name.as_str())),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for the test `name`.
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "wayggle-bg-package-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    /// Write a package with `manifest` into `directory`.
+    fn install(directory: &Path, manifest: &str) {
+        std::fs::create_dir_all(directory).unwrap();
+        std::fs::write(directory.join(MANIFEST_FILE), manifest).unwrap();
+        std::fs::write(directory.join("image.glsl"), "// image").unwrap();
+    }
+
+    const MANIFEST: &str = r#"
+        mode = "shadertoy"
+        description = "A test"
+
+        [[passes]]
+        fragment = "image.glsl"
+    "#;
+
+    fn load(name: &str, manifest: &str) -> Result<ShaderPackage> {
+        let directory = temp_directory(name).join(name);
+        install(&directory, manifest);
+        let package = ShaderPackage::load(&directory);
+        std::fs::remove_dir_all(directory.parent().unwrap()).unwrap();
+        package
+    }
+
+    #[test]
+    fn loads_a_manifest() {
+        let package = load(
+            "valid",
+            &format!("{}\n[channels]\naudio = 3\nkeyboard = 0\n", MANIFEST),
+        )
+        .unwrap();
+        assert_eq!(package.name, "valid");
+        assert_eq!(package.manifest.mode, ShaderMode::ShaderToy);
+        assert_eq!(package.manifest.description, "A test");
+        assert_eq!(package.manifest.channels.audio, Some(3));
+        assert_eq!(package.manifest.channels.keyboard, Some(0));
+        assert_eq!(package.manifest.url, None);
+    }
+
+    #[test]
+    fn rejects_invalid_manifests() {
+        let pass = "[[passes]]\nfragment = \"image.glsl\"\n";
+        for (name, manifest) in [
+            ("no-passes", "mode = \"shadertoy\"\npasses = []".to_string()),
+            (
+                "two-passes",
+                format!("mode = \"shadertoy\"\n{}{}", pass, pass),
+            ),
+            (
+                "channel-out-of-range",
+                format!("{}\n[channels]\naudio = {}\n", MANIFEST, CHANNEL_COUNT),
+            ),
+            ("unknown-field", format!("colour = \"red\"\n{}", MANIFEST)),
+            (
+                "unknown-pass-field",
+                format!("mode = \"shadertoy\"\n{}buffer = \"a\"\n", pass),
+            ),
+            ("unknown-mode", format!("mode = \"glsl\"\n{}", pass)),
+        ] {
+            assert!(
+                matches!(load(name, &manifest), Err(Error::Configuration(_))),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn searches_data_home_before_data_dirs() {
+        let root = temp_directory("xdg");
+        let (home, first, second) = (root.join("home"), root.join("first"), root.join("second"));
+        let shaders = |dir: &Path| dir.join("wayggle-bg/shaders");
+        install(&shaders(&home).join("shared"), MANIFEST);
+        install(&shaders(&first).join("shared"), MANIFEST);
+        install(&shaders(&first).join("tunnel"), MANIFEST);
+        install(&shaders(&second).join("tunnel"), MANIFEST);
+        install(&shaders(&second).join("waves"), MANIFEST);
+        install(&shaders(&second).join("broken"), "mode = \"shadertoy\"");
+
+        let data_dirs = std::env::join_paths([&first, Path::new("relative"), &second]).unwrap();
+        let search_path = search_path_with(|name| match name {
+            "XDG_DATA_HOME" => Some(home.clone().into()),
+            "XDG_DATA_DIRS" => Some(data_dirs.clone()),
+            _ => None,
+        });
+        let discovered: Vec<(String, PathBuf)> = discover_in(&search_path)
+            .into_iter()
+            .map(|package| (package.name, package.directory))
+            .collect();
+        let found = find_in(&search_path, "tunnel")
+            .unwrap()
+            .map(|package| package.directory);
+        let missing = find_in(&search_path, "missing").unwrap();
+        let path_like = find_in(&search_path, "../first").unwrap();
+
+        assert_eq!(
+            search_path,
+            [shaders(&home), shaders(&first), shaders(&second)]
+        );
+        assert_eq!(
+            discovered,
+            [
+                ("shared".to_string(), shaders(&home).join("shared")),
+                ("tunnel".to_string(), shaders(&first).join("tunnel")),
+                ("waves".to_string(), shaders(&second).join("waves")),
+            ]
+        );
+        assert_eq!(found, Some(shaders(&first).join("tunnel")));
+        assert!(missing.is_none());
+        assert!(path_like.is_none());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn suggests_installed_and_builtin_names() {
+        let root = temp_directory("suggest");
+        install(&root.join("tunnel"), MANIFEST);
+        let message = |name| match unknown_name(std::slice::from_ref(&root), name) {
+            Error::Configuration(message) => message,
+            e => panic!("{:?}", e),
+        };
+
+        assert_eq!(
+            message("tunel"),
+            "no shader named 'tunel'; did you mean 'tunnel'?"
+        );
+        assert_eq!(
+            message("voronio"),
+            "no shader named 'voronio'; did you mean 'voronoi'?"
+        );
+        assert_eq!(message("plasma"), "no shader named 'plasma'");
+        // Built-in lookups alone know nothing about packages.
+        assert!(matches!(
+            ShaderSource::builtin("tunel"),
+            Err(Error::Configuration(message)) if message == "no shader named 'tunel'"
+        ));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn search_path_defaults() {
+        let shaders = |dir: &str| PathBuf::from(dir).join("wayggle-bg/shaders");
+        assert_eq!(
+            search_path_with(|name| (name == "HOME").then(|| "/home/me".into())),
+            [
+                shaders("/home/me/.local/share"),
+                shaders("/usr/local/share/"),
+                shaders("/usr/share/"),
+            ]
+        );
+        // Empty and relative values are as good as unset.
+        assert_eq!(
+            search_path_with(|name| match name {
+                "XDG_DATA_HOME" => Some("".into()),
+                "XDG_DATA_DIRS" => Some("relative".into()),
+                _ => None,
+            }),
+            Vec::<PathBuf>::new()
+        );
+    }
+}
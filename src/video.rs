@@ -0,0 +1,224 @@
+//! Video files as channel textures, decoded with GStreamer.
+//!
+//! Decoding runs on GStreamer's threads at the video's own pace. Only the newest frame
+//! is kept, so frames the render loop is too slow for, e.g. while frame pacing limits
+//! the frame rate, are dropped rather than queued.
+
+use std::path::Path;
+
+use glow::HasContext;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use gstreamer_video as gst_video;
+use gstreamer_video::prelude::*;
+
+use crate::channels::ChannelSource;
+use crate::error::{Error, Result};
+
+/// How long opening a video may take to produce its first frame.
+const PREROLL_TIMEOUT: gst::ClockTime = gst::ClockTime::from_seconds(10);
+
+/// A video playing into a channel texture.
+pub struct VideoChannel {
+    playbin: gst::Element,
+    sink: gst_app::AppSink,
+    /// Start over at the end instead of stopping on the last frame.
+    looping: bool,
+    /// The newest decoded frame.
+    frame: gst::Sample,
+    size: (u32, u32),
+    /// The textures `frame` was uploaded into, one per pipeline.
+    uploaded: Vec<glow::Texture>,
+    /// The textures with storage allocated for frames of the current size.
+    allocated: Vec<glow::Texture>,
+}
+
+impl VideoChannel {
+    /// Start playing a video file, without its sound. Fails if the file can't be decoded.
+    pub fn open(path: &Path, looping: bool) -> Result<Self> {
+        let context = || format!("failed to play video {}", path.display());
+        let failed = |message: String| {
+            Error::io(
+                context(),
+                std::io::Error::new(std::io::ErrorKind::InvalidData, message),
+            )
+        };
+
+        gst::init().map_err(|e| failed(e.to_string()))?;
+        let path = path.canonicalize().map_err(|e| Error::io(context(), e))?;
+        let uri = gst::glib::filename_to_uri(&path, None).map_err(|e| failed(e.to_string()))?;
+        let caps = gst_video::VideoCapsBuilder::new()
+            .format(gst_video::VideoFormat::Rgba)
+            .build();
+        let sink = gst_app::AppSink::builder()
+            .caps(&caps)
+            .max_buffers(1)
+            .drop(true)
+            .sync(true)
+            .build();
+        let playbin = gst::ElementFactory::make("playbin")
+            .property("uri", uri.as_str())
+            .property("video-sink", &sink)
+            .property_from_str("flags", "video")
+            .build()
+            .map_err(|e| failed(e.to_string()))?;
+
+        // Decode the first frame up front, so broken files fail at startup.
+        let prerolled = playbin
+            .set_state(gst::State::Paused)
+            .and_then(|_| playbin.state(PREROLL_TIMEOUT).0);
+        if prerolled.is_err() {
+            let message = playbin
+                .bus()
+                .and_then(|bus| bus.pop_filtered(&[gst::MessageType::Error]))
+                .and_then(|message| match message.view() {
+                    gst::MessageView::Error(error) => Some(error.error().to_string()),
+                    _ => None,
+                })
+                .unwrap_or_else(|| "no playable video stream".to_string());
+            let _ = playbin.set_state(gst::State::Null);
+            return Err(failed(message));
+        }
+        let frame = sink.pull_preroll().map_err(|e| failed(e.to_string()))?;
+        let size = frame_size(&frame).ok_or_else(|| failed("no video size".to_string()))?;
+        playbin
+            .set_state(gst::State::Playing)
+            .map_err(|e| failed(e.to_string()))?;
+        tracing::info!("Playing {}x{} video {}", size.0, size.1, path.display());
+
+        Ok(VideoChannel {
+            playbin,
+            sink,
+            looping,
+            frame,
+            size,
+            uploaded: Vec::new(),
+            allocated: Vec::new(),
+        })
+    }
+
+    /// Start over at the end of the video, and report playback errors.
+    fn handle_messages(&mut self) {
+        let Some(bus) = self.playbin.bus() else {
+            return;
+        };
+        while let Some(message) = bus.pop() {
+            match message.view() {
+                gst::MessageView::Eos(_) if self.looping => {
+                    if let Err(e) = self.playbin.seek_simple(
+                        gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT,
+                        gst::ClockTime::ZERO,
+                    ) {
+                        tracing::warn!("Failed to loop the video: {}", e);
+                    }
+                }
+                gst::MessageView::Error(error) => {
+                    tracing::warn!("Video playback failed: {}", error.error());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Width and height of the frame in `sample`.
+fn frame_size(sample: &gst::Sample) -> Option<(u32, u32)> {
+    let info = gst_video::VideoInfo::from_caps(sample.caps()?).ok()?;
+    Some((info.width(), info.height()))
+}
+
+impl ChannelSource for VideoChannel {
+    fn init_texture(&mut self, _gl: &glow::Context, texture: glow::Texture) {
+        // A texture of a dropped pipeline may have had the same name.
+        self.uploaded.retain(|uploaded| *uploaded != texture);
+        self.allocated.retain(|allocated| *allocated != texture);
+    }
+
+    fn advance(&mut self) {
+        self.handle_messages();
+        if let Some(sample) = self.sink.try_pull_sample(gst::ClockTime::ZERO) {
+            let size = frame_size(&sample).unwrap_or(self.size);
+            if size != self.size {
+                self.size = size;
+                self.allocated.clear();
+            }
+            self.frame = sample;
+            self.uploaded.clear();
+        }
+    }
+
+    fn update(&mut self, gl: &glow::Context, texture: glow::Texture) {
+        if self.uploaded.contains(&texture) {
+            return;
+        }
+        let (Some(buffer), Some(caps)) = (self.frame.buffer(), self.frame.caps()) else {
+            return;
+        };
+        let Ok(info) = gst_video::VideoInfo::from_caps(caps) else {
+            return;
+        };
+        let Ok(frame) = gst_video::VideoFrameRef::from_buffer_ref_readable(buffer, &info) else {
+            return;
+        };
+        let Ok(pixels) = frame.plane_data(0) else {
+            return;
+        };
+
+        let (width, height) = (info.width() as i32, info.height() as i32);
+        unsafe {
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+            gl.pixel_store_i32(glow::UNPACK_ROW_LENGTH, frame.plane_stride()[0] / 4);
+            let pixels = glow::PixelUnpackData::Slice(Some(pixels));
+            if self.allocated.contains(&texture) {
+                gl.tex_sub_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    0,
+                    0,
+                    width,
+                    height,
+                    glow::RGBA,
+                    glow::UNSIGNED_BYTE,
+                    pixels,
+                );
+            } else {
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    glow::RGBA8 as i32,
+                    width,
+                    height,
+                    0,
+                    glow::RGBA,
+                    glow::UNSIGNED_BYTE,
+                    pixels,
+                );
+                self.allocated.push(texture);
+            }
+            gl.pixel_store_i32(glow::UNPACK_ROW_LENGTH, 0);
+        }
+        self.uploaded.push(texture);
+    }
+
+    fn resolution(&self) -> [f32; 3] {
+        [self.size.0 as f32, self.size.1 as f32, 1.0]
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        let state = if paused {
+            gst::State::Paused
+        } else {
+            gst::State::Playing
+        };
+        if let Err(e) = self.playbin.set_state(state) {
+            tracing::warn!("Failed to change the video state to {:?}: {}", state, e);
+        }
+    }
+}
+
+impl Drop for VideoChannel {
+    fn drop(&mut self) {
+        let _ = self.playbin.set_state(gst::State::Null);
+    }
+}
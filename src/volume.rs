@@ -0,0 +1,239 @@
+//! Volume textures in Shadertoy's binary format, as `sampler3D` channels.
+//!
+//! The format is a 20 byte little endian header followed by the voxels, x varying
+//! fastest, then y, then z:
+//!
+//! | Offset | Type  | Value                                  |
+//! |--------|-------|----------------------------------------|
+//! | 0      | bytes | signature `BIN\0`                      |
+//! | 4      | u32   | width                                  |
+//! | 8      | u32   | height                                 |
+//! | 12     | u32   | depth                                  |
+//! | 16     | u8    | channels per voxel, 1 to 4             |
+//! | 17     | u8    | layout, always 0                       |
+//! | 18     | u16   | voxel format: 0 for u8, 10 for f32     |
+
+use std::path::Path;
+
+use glow::HasContext;
+
+use crate::channels::{ChannelKind, ChannelSource};
+use crate::error::{Error, Result};
+
+const SIGNATURE: &[u8; 4] = b"BIN\0";
+const HEADER_SIZE: usize = 20;
+
+/// How the voxel values are stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum VoxelFormat {
+    U8,
+    F32,
+}
+
+/// A volume as a channel texture.
+pub struct VolumeChannel {
+    size: [u32; 3],
+    channels: u8,
+    format: VoxelFormat,
+    /// The voxels, with floats in native byte order.
+    voxels: Vec<u8>,
+}
+
+impl VolumeChannel {
+    /// Read a volume file such as the ones Shadertoy offers as 3D texture inputs.
+    pub fn open(path: &Path) -> Result<Self> {
+        let context = || format!("failed to read volume {}", path.display());
+        let data = std::fs::read(path).map_err(|e| Error::io(context(), e))?;
+        Self::parse(&data).map_err(|message| {
+            Error::io(
+                context(),
+                std::io::Error::new(std::io::ErrorKind::InvalidData, message),
+            )
+        })
+    }
+
+    fn parse(data: &[u8]) -> std::result::Result<Self, String> {
+        let header = data
+            .get(..HEADER_SIZE)
+            .filter(|header| header.starts_with(SIGNATURE))
+            .ok_or("not a Shadertoy volume file")?;
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap_or_default())
+        };
+        let size = [u32_at(4), u32_at(8), u32_at(12)];
+        let channels = header[16];
+        let layout = header[17];
+        let format = match u16::from_le_bytes([header[18], header[19]]) {
+            0 => VoxelFormat::U8,
+            10 => VoxelFormat::F32,
+            format => return Err(format!("unsupported voxel format {}", format)),
+        };
+        if !(1..=4).contains(&channels) {
+            return Err(format!("unsupported channel count {}", channels));
+        }
+        if layout != 0 {
+            return Err(format!("unsupported layout {}", layout));
+        }
+        if size.contains(&0) {
+            return Err("the volume is empty".to_string());
+        }
+
+        let value_size = match format {
+            VoxelFormat::U8 => 1,
+            VoxelFormat::F32 => 4,
+        };
+        let expected = size
+            .iter()
+            .try_fold(channels as usize * value_size, |total, &side| {
+                total.checked_mul(side as usize)
+            });
+        let voxels = &data[HEADER_SIZE..];
+        if expected != Some(voxels.len()) {
+            return Err(format!(
+                "{} bytes of voxels don't make a {}x{}x{} volume with {} channels of {} bytes",
+                voxels.len(),
+                size[0],
+                size[1],
+                size[2],
+                channels,
+                value_size
+            ));
+        }
+        let voxels = match format {
+            VoxelFormat::U8 => voxels.to_vec(),
+            VoxelFormat::F32 => voxels
+                .chunks_exact(4)
+                .flat_map(|value| {
+                    f32::from_le_bytes(value.try_into().unwrap_or_default()).to_ne_bytes()
+                })
+                .collect(),
+        };
+        Ok(VolumeChannel {
+            size,
+            channels,
+            format,
+            voxels,
+        })
+    }
+
+    /// The internal format, format and type to upload the voxels with.
+    fn texture_format(&self) -> (u32, u32, u32) {
+        let formats = [glow::RED, glow::RG, glow::RGB, glow::RGBA];
+        // 32 bit float textures can't be filtered in GLES 3.0, 16 bit ones can.
+        let internal_formats = match self.format {
+            VoxelFormat::U8 => [glow::R8, glow::RG8, glow::RGB8, glow::RGBA8],
+            VoxelFormat::F32 => [glow::R16F, glow::RG16F, glow::RGB16F, glow::RGBA16F],
+        };
+        let value_type = match self.format {
+            VoxelFormat::U8 => glow::UNSIGNED_BYTE,
+            VoxelFormat::F32 => glow::FLOAT,
+        };
+        let index = self.channels as usize - 1;
+        (internal_formats[index], formats[index], value_type)
+    }
+}
+
+impl ChannelSource for VolumeChannel {
+    fn init_texture(&mut self, gl: &glow::Context, _texture: glow::Texture) {
+        let max_size = unsafe { gl.get_parameter_i32(glow::MAX_3D_TEXTURE_SIZE) }.max(1) as u32;
+        let [width, height, depth] = self.size;
+        if self.size.iter().any(|side| *side > max_size) {
+            tracing::warn!(
+                "The {}x{}x{} volume is larger than the maximum 3D texture size of {}",
+                width,
+                height,
+                depth,
+                max_size
+            );
+            return;
+        }
+
+        let (internal_format, format, value_type) = self.texture_format();
+        unsafe {
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+            gl.tex_image_3d(
+                glow::TEXTURE_3D,
+                0,
+                internal_format as i32,
+                width as i32,
+                height as i32,
+                depth as i32,
+                0,
+                format,
+                value_type,
+                glow::PixelUnpackData::Slice(Some(&self.voxels)),
+            );
+        }
+    }
+
+    fn kind(&self) -> ChannelKind {
+        ChannelKind::Volume
+    }
+
+    fn resolution(&self) -> [f32; 3] {
+        self.size.map(|side| side as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume(size: [u32; 3], channels: u8, format: u16, voxels: &[u8]) -> Vec<u8> {
+        let mut data = SIGNATURE.to_vec();
+        for side in size {
+            data.extend(side.to_le_bytes());
+        }
+        data.extend([channels, 0]);
+        data.extend(format.to_le_bytes());
+        data.extend(voxels);
+        data
+    }
+
+    #[test]
+    fn parses_bytes() {
+        let parsed = VolumeChannel::parse(&volume([2, 1, 1], 1, 0, &[7, 9])).unwrap();
+        assert_eq!(parsed.size, [2, 1, 1]);
+        assert_eq!(parsed.format, VoxelFormat::U8);
+        assert_eq!(parsed.voxels, [7, 9]);
+    }
+
+    #[test]
+    fn parses_floats() {
+        let voxels: Vec<u8> = [0.5f32, -2.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let parsed = VolumeChannel::parse(&volume([1, 1, 1], 2, 10, &voxels)).unwrap();
+        assert_eq!(parsed.format, VoxelFormat::F32);
+        let values: Vec<f32> = parsed
+            .voxels
+            .chunks_exact(4)
+            .map(|value| f32::from_ne_bytes(value.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, [0.5, -2.0]);
+    }
+
+    #[test]
+    fn rejects_bad_signature() {
+        let mut data = volume([1, 1, 1], 1, 0, &[0]);
+        data[0] = b'X';
+        assert!(VolumeChannel::parse(&data).is_err());
+        assert!(VolumeChannel::parse(&data[..10]).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_size() {
+        assert!(VolumeChannel::parse(&volume([2, 2, 2], 1, 0, &[0; 7])).is_err());
+        assert!(VolumeChannel::parse(&volume([2, 2, 2], 1, 0, &[0; 9])).is_err());
+        assert!(VolumeChannel::parse(&volume([1, 1, 1], 1, 10, &[0; 1])).is_err());
+        assert!(VolumeChannel::parse(&volume([0, 1, 1], 1, 0, &[])).is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_formats() {
+        assert!(VolumeChannel::parse(&volume([1, 1, 1], 1, 1, &[0])).is_err());
+        assert!(VolumeChannel::parse(&volume([1, 1, 1], 0, 0, &[])).is_err());
+        assert!(VolumeChannel::parse(&volume([1, 1, 1], 5, 0, &[0; 5])).is_err());
+    }
+}
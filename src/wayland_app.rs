@@ -1,19 +1,219 @@
 mod app_state;
 mod graphics;
 
+pub use graphics::Graphics;
+
 use wayland_client::Connection;
 use wayland_protocols_wlr::layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
 
+use std::os::fd::AsFd;
+use std::path::Path;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::channels::{ChannelBinding, check_bindings};
+use crate::clock::ShaderClock;
+use crate::control::ControlSocket;
+use crate::cursor_support::{CursorProvider, Smoothing};
+use crate::daytime::Location;
+use crate::error::{Error, Result};
+use crate::playlist::Playlist;
+use crate::power_supply::PowerPolicy;
+use crate::scene::{Inputs, ShaderChoice, Textures};
+use crate::shader::{ShaderSource, package};
+
+/// How often frames are rendered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FramePacing {
+    /// Render on every frame callback of the compositor.
+    Unlimited,
+    /// Render at most once per interval.
+    Limited(Duration),
+    /// Keep showing the last rendered frame.
+    Frozen,
+}
 
+/// What the wallpaper renders and how. Created with [`AppConfiguration::builder`].
 pub struct AppConfiguration {
-    pub vertex_shader: String,
-    pub fragment_shader: String,
-    pub get_cursor: Option<Rc<fn() -> (f32, f32)>>,
+    pub shader: ShaderSource,
+    /// Tried when `shader` fails to build, before the built-in fallbacks.
+    pub fallback_shader: Option<ShaderSource>,
+    pub cursor_provider: Option<Rc<dyn CursorProvider>>,
+    pub cursor_smoothing: Smoothing,
+    /// Rendering is paused once the session has been idle for this long.
+    pub idle_timeout: Option<Duration>,
+    /// Frame pacing follows the power supply state when set.
+    pub power_policy: Option<PowerPolicy>,
+    pub channels: Vec<ChannelBinding>,
+    /// Rotate through the shaders of the playlist instead of showing `shader`.
+    pub playlist: Option<Playlist>,
+    /// How `u_time` follows the real time.
+    pub clock: ShaderClock,
+    /// Places the sun for `u_sun_elevation` and sunrise and sunset in playlists.
+    pub location: Option<Location>,
+    /// Give shaders the previous frame, see [`FeedbackPass`](crate::pipeline::feedback::FeedbackPass).
+    pub feedback: bool,
+    /// Reads `shader` from disk again for the `reload` control command.
+    pub shader_reloader: Option<Box<dyn Fn() -> Result<ShaderSource>>>,
+}
+
+impl AppConfiguration {
+    pub fn builder(shader: &ShaderSource) -> AppConfigurationBuilder {
+        AppConfigurationBuilder {
+            conf: AppConfiguration {
+                shader: shader.clone(),
+                fallback_shader: None,
+                cursor_provider: None,
+                cursor_smoothing: Smoothing::None,
+                idle_timeout: None,
+                power_policy: None,
+                channels: Vec::new(),
+                playlist: None,
+                clock: ShaderClock::default(),
+                location: None,
+                feedback: false,
+                shader_reloader: None,
+            },
+            package_channels: Default::default(),
+            inputs: Inputs::default(),
+            textures: Textures::default(),
+        }
+    }
+
+    /// A builder showing `choice`, with the channels it brings, its fallback and a
+    /// reloader reading it from disk again.
+    pub fn from_choice(choice: ShaderChoice) -> Result<AppConfigurationBuilder> {
+        let loaded = choice.load()?;
+        let mut builder =
+            AppConfiguration::builder(&loaded.source).shader_reloader(move || choice.reload());
+        builder.conf.fallback_shader = loaded.fallback;
+        builder.conf.channels = loaded.channels;
+        builder.package_channels = loaded.package_channels;
+        Ok(builder)
+    }
+}
+
+/// Builds an [`AppConfiguration`]. Everything but the shader is off by default.
+pub struct AppConfigurationBuilder {
+    conf: AppConfiguration,
+    /// Where the package of [`AppConfiguration::from_choice`] expects the `inputs`.
+    package_channels: package::Channels,
+    inputs: Inputs,
+    textures: Textures,
+}
+
+impl AppConfigurationBuilder {
+    pub fn fallback_shader(mut self, shader: &ShaderSource) -> Self {
+        self.conf.fallback_shader = Some(shader.clone());
+        self
+    }
+
+    /// A fragment shader file in the mode of the main shader as the fallback.
+    pub fn fallback_shader_file(self, path: &Path) -> Result<Self> {
+        let fallback = ShaderSource::from_files(self.conf.shader.mode, path, None)?;
+        Ok(self.fallback_shader(&fallback))
+    }
+
+    pub fn cursor_provider(mut self, provider: Rc<dyn CursorProvider>) -> Self {
+        self.conf.cursor_provider = Some(provider);
+        self
+    }
+
+    pub fn cursor_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.conf.cursor_smoothing = smoothing;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.conf.idle_timeout = Some(timeout);
+        self
+    }
+
+    pub fn power_policy(mut self, policy: PowerPolicy) -> Self {
+        self.conf.power_policy = Some(policy);
+        self
+    }
+
+    pub fn channel(mut self, channel: ChannelBinding) -> Self {
+        self.conf.channels.push(channel);
+        self
+    }
+
+    /// Started by [`build`](Self::build), bound after the channels added so far.
+    pub fn inputs(mut self, inputs: Inputs) -> Self {
+        self.inputs = inputs;
+        self
+    }
+
+    /// Opened by [`build`](Self::build), bound to channels left free by all other inputs.
+    pub fn textures(mut self, textures: Textures) -> Self {
+        self.textures = textures;
+        self
+    }
+
+    pub fn playlist(mut self, playlist: Playlist) -> Self {
+        self.conf.playlist = Some(playlist);
+        self
+    }
+
+    pub fn clock(mut self, clock: ShaderClock) -> Self {
+        self.conf.clock = clock;
+        self
+    }
+
+    pub fn location(mut self, location: Location) -> Self {
+        self.conf.location = Some(location);
+        self
+    }
+
+    pub fn feedback(mut self) -> Self {
+        self.conf.feedback = true;
+        self
+    }
+
+    pub fn shader_reloader(
+        mut self,
+        reloader: impl Fn() -> Result<ShaderSource> + 'static,
+    ) -> Self {
+        self.conf.shader_reloader = Some(Box::new(reloader));
+        self
+    }
+
+    /// Starts the inputs and opens the textures. Fails if one of them fails, if two
+    /// channels are bound to the same `iChannel`, or if the playlist follows sunrise or
+    /// sunset without a location.
+    pub fn build(mut self) -> Result<AppConfiguration> {
+        if self.conf.location.is_none()
+            && self
+                .conf
+                .playlist
+                .as_ref()
+                .is_some_and(Playlist::depends_on_sun)
+        {
+            return Err(Error::Configuration(
+                "the playlist follows sunrise or sunset, which needs a location".to_string(),
+            ));
+        }
+        let inputs = self.inputs.spawn(&self.package_channels)?;
+        self.conf.channels.extend(inputs);
+        let textures = self
+            .textures
+            .bind(&self.conf.channels, self.conf.feedback)?;
+        self.conf.channels.extend(textures);
+        let channels = &self.conf.channels;
+        check_bindings(channels)?;
+        if self.conf.feedback && channels.iter().any(|binding| binding.index == 0) {
+            tracing::warn!(
+                "An input is bound to iChannel0, so the previous frame is only available as u_backbuffer"
+            );
+        }
+        Ok(self.conf)
+    }
 }
 
-pub fn run(conf: AppConfiguration) {
-    let conn = Connection::connect_to_env().unwrap();
+/// Show the wallpaper until its surface is closed.
+pub fn run(conf: AppConfiguration) -> Result<()> {
+    let conn = Connection::connect_to_env()?;
     let mut event_queue = conn.new_event_queue();
     let qh = event_queue.handle();
 
@@ -23,17 +223,36 @@ pub fn run(conf: AppConfiguration) {
     let mut app_state = app_state::AppState::new(display.clone(), conf);
 
     tracing::info!("Waiting for globals...");
-    event_queue.roundtrip(&mut app_state).unwrap();
+    event_queue.roundtrip(&mut app_state)?;
     tracing::info!("Globals received.");
 
-    let compositor = app_state.compositor.as_ref().expect("Compositor not found");
+    if let Some(idle_timeout) = app_state.conf.idle_timeout {
+        match (app_state.idle_notifier.as_ref(), app_state.seat.as_ref()) {
+            (Some((idle_notifier, _)), Some((seat, _))) => {
+                let timeout = u32::try_from(idle_timeout.as_millis()).unwrap_or(u32::MAX);
+                let notification = idle_notifier.get_idle_notification(timeout, seat, &qh, ());
+                app_state.idle_notification = Some(notification);
+                tracing::info!("Rendering pauses after {:?} of inactivity", idle_timeout);
+            }
+            (None, _) => {
+                tracing::warn!("ext_idle_notifier_v1 not available; rendering won't pause on idle");
+            }
+            (_, None) => {
+                tracing::warn!("No seat available; rendering won't pause on idle");
+            }
+        }
+    }
+
+    let compositor = app_state
+        .compositor
+        .as_ref()
+        .ok_or(Error::MissingGlobal("wl_compositor"))?;
     let surface = compositor.0.create_surface(&qh, ());
     app_state.surface = Some(surface.clone());
 
-    let layer_shell = app_state
-        .layer_shell
-        .as_ref()
-        .expect("Layer shell not found");
+    let layer_shell = app_state.layer_shell.as_ref().ok_or(Error::MissingGlobal(
+        "wlr-layer-shell (zwlr_layer_shell_v1)",
+    ))?;
     let layer_surface = layer_shell.0.get_layer_surface(
         &surface,
         None,
@@ -55,9 +274,65 @@ pub fn run(conf: AppConfiguration) {
     surface.commit();
     tracing::info!("Initial commit done. Waiting for configure event...");
 
+    match ControlSocket::bind() {
+        Ok(control) => {
+            tracing::info!(
+                "Listening for control commands on {}",
+                control.path().display()
+            );
+            app_state.control = Some(control);
+        }
+        Err(e) => tracing::warn!("{}; 'status' and 'reload' won't reach this wallpaper", e),
+    }
+
     while app_state.is_running() {
-        event_queue.blocking_dispatch(&mut app_state).unwrap();
+        app_state.tick(&qh);
+        event_queue.flush()?;
+        if let Some(guard) = event_queue.prepare_read() {
+            let timeout = app_state
+                .next_wakeup()
+                .map(|wakeup| wakeup.saturating_duration_since(Instant::now()));
+            let control = app_state.control.as_ref().map(AsFd::as_fd);
+            if wait_readable(guard.connection_fd(), control, timeout)? {
+                match guard.read() {
+                    Ok(_) => {}
+                    Err(wayland_client::backend::WaylandError::Io(e))
+                        if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        event_queue.dispatch_pending(&mut app_state)?;
+        app_state.handle_control_requests(&qh);
     }
 
+    if let Some(error) = app_state.error.take() {
+        return Err(error);
+    }
     tracing::info!("Exiting.");
+    Ok(())
+}
+
+/// Wait until `fd` or `control` is readable or `timeout` has passed. Returns whether `fd`
+/// is readable.
+fn wait_readable(
+    fd: std::os::fd::BorrowedFd<'_>,
+    control: Option<std::os::fd::BorrowedFd<'_>>,
+    timeout: Option<Duration>,
+) -> Result<bool> {
+    use rustix::event::{PollFd, PollFlags, Timespec};
+
+    // Durations too long for a timespec are as good as no timeout at all.
+    let timeout = timeout.and_then(|timeout| Timespec::try_from(timeout).ok());
+    let mut fds = vec![PollFd::new(&fd, PollFlags::IN | PollFlags::ERR)];
+    if let Some(control) = control.as_ref() {
+        fds.push(PollFd::new(control, PollFlags::IN));
+    }
+    loop {
+        match rustix::event::poll(&mut fds, timeout.as_ref()) {
+            Ok(_) => return Ok(!fds[0].revents().is_empty()),
+            Err(rustix::io::Errno::INTR) => continue,
+            Err(e) => return Err(Error::io("failed to poll the Wayland socket", e.into())),
+        }
+    }
 }
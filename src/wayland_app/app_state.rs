@@ -1,45 +1,346 @@
 use std::time::Instant;
 
 use super::graphics::Graphics;
+use crate::error::Error;
+use crate::pipeline::FrameInput;
+use crate::renderer::Renderer;
 use wayland_client::protocol::wl_display;
 use wayland_client::{
-    Connection, Dispatch, QueueHandle,
-    protocol::{wl_callback, wl_compositor, wl_registry, wl_surface},
+    Connection, Dispatch, Proxy, QueueHandle, WEnum,
+    protocol::{
+        wl_callback, wl_compositor, wl_output, wl_pointer, wl_registry, wl_seat, wl_surface,
+    },
 };
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1, ext_idle_notifier_v1,
+};
+use wayland_protocols::xdg::xdg_output::zv1::client::{zxdg_output_manager_v1, zxdg_output_v1};
 use wayland_protocols_wlr::layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
 
-use super::AppConfiguration;
+use super::{AppConfiguration, FramePacing};
+use crate::control::{ControlCommand, ControlSocket};
+use crate::cursor_support::{self, CursorFilter, OutputGeometry, ShadertoyMouse};
+use crate::daytime;
+use crate::power_supply::PowerMonitor;
+
+pub struct OutputInfo {
+    pub output: wl_output::WlOutput,
+    pub name: u32,
+    /// Connector name such as `DP-1`, from `wl_output` version 4.
+    pub connector: Option<String>,
+    pub xdg_output: Option<zxdg_output_v1::ZxdgOutputV1>,
+    /// Logical geometry reported by xdg-output.
+    pub geometry: Option<OutputGeometry>,
+}
 
 pub struct AppState {
     pub graphics: Option<Graphics>,
     pub start_time: Instant,
+    pub shadertoy_mouse: ShadertoyMouse,
+    pub cursor_filter: CursorFilter,
+    /// Elapsed seconds at the last rendered frame.
+    pub last_frame_time: f64,
     pub conf: AppConfiguration,
     pub closed: bool,
+    /// The failure that stopped the event loop, raised inside an event handler.
+    pub error: Option<Error>,
+    /// The session is idle and rendering is paused.
+    pub idle: bool,
+    /// A frame callback has been requested and not yet delivered.
+    pub frame_pending: bool,
+    pub pacing: FramePacing,
+    pub power_monitor: Option<PowerMonitor>,
+    pub last_render: Option<Instant>,
+    /// A frame held back by `FramePacing::Limited`, rendered from `tick` once due.
+    pub deferred_render: Option<Instant>,
     // Wayland objects
     pub display: wl_display::WlDisplay,
     pub compositor: Option<(wl_compositor::WlCompositor, u32)>,
     pub layer_shell: Option<(zwlr_layer_shell_v1::ZwlrLayerShellV1, u32)>,
+    pub xdg_output_manager: Option<(zxdg_output_manager_v1::ZxdgOutputManagerV1, u32)>,
+    pub outputs: Vec<OutputInfo>,
+    pub seat: Option<(wl_seat::WlSeat, u32)>,
+    pub pointer: Option<wl_pointer::WlPointer>,
+    pub idle_notifier: Option<(ext_idle_notifier_v1::ExtIdleNotifierV1, u32)>,
+    pub idle_notification: Option<ext_idle_notification_v1::ExtIdleNotificationV1>,
     pub surface: Option<wl_surface::WlSurface>,
     pub layer_surface: Option<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1>,
+    /// The output the surface is shown on.
+    pub surface_output: Option<wl_output::WlOutput>,
+    /// Logical size of the surface from the last configure.
+    pub surface_size: (u32, u32),
+    /// Where the `status` and `reload` subcommands reach this wallpaper.
+    pub control: Option<ControlSocket>,
 }
 
 impl AppState {
     pub fn new(display: wl_display::WlDisplay, conf: AppConfiguration) -> Self {
+        let power_monitor = conf.power_policy.clone().map(PowerMonitor::new);
+        let cursor_filter = CursorFilter::new(conf.cursor_smoothing);
         AppState {
             graphics: None,
             start_time: Instant::now(),
+            shadertoy_mouse: ShadertoyMouse::default(),
+            cursor_filter,
+            last_frame_time: 0.0,
             conf,
             closed: false,
+            error: None,
+            idle: false,
+            frame_pending: false,
+            pacing: FramePacing::Unlimited,
+            power_monitor,
+            last_render: None,
+            deferred_render: None,
             display,
             compositor: None,
             layer_shell: None,
+            xdg_output_manager: None,
+            outputs: Vec::new(),
+            seat: None,
+            pointer: None,
+            idle_notifier: None,
+            idle_notification: None,
             surface: None,
             layer_surface: None,
+            surface_output: None,
+            surface_size: (0, 0),
+            control: None,
         }
     }
 
     pub fn is_running(&self) -> bool {
-        !self.closed
+        !self.closed && self.error.is_none()
+    }
+
+    /// Stop the event loop with `error`, keeping the first one if several occur.
+    fn fail(&mut self, error: Error) {
+        self.error.get_or_insert(error);
+    }
+
+    /// Tell the channel inputs which output the wallpaper is shown on.
+    fn update_channel_output(&self) {
+        let name = self
+            .surface_output
+            .as_ref()
+            .and_then(|output| self.outputs.iter().find(|info| info.output == *output))
+            .and_then(|info| info.connector.as_deref());
+        for binding in &self.conf.channels {
+            binding.source.borrow_mut().set_output(name);
+        }
+    }
+
+    /// Tell the channel inputs and the cursor provider whether frames are being rendered.
+    fn set_inputs_paused(&self, paused: bool) {
+        for binding in &self.conf.channels {
+            binding.source.borrow_mut().set_paused(paused);
+        }
+        if let Some(provider) = self.conf.cursor_provider.as_ref() {
+            provider.set_paused(paused);
+        }
+    }
+
+    /// Answer the requests waiting on the control socket.
+    pub fn handle_control_requests(&mut self, qh: &QueueHandle<Self>) {
+        let Some(control) = self.control.take() else {
+            return;
+        };
+        while let Some(request) = control.accept() {
+            let reply = match request.command {
+                ControlCommand::Status => self.status(),
+                ControlCommand::Reload => self.reload(qh),
+            };
+            request.reply(&reply);
+        }
+        self.control = Some(control);
+    }
+
+    fn status(&self) -> String {
+        let mut status = match self.graphics.as_ref() {
+            Some(graphics) => graphics.status(),
+            None => "waiting for the surface to be configured\n".to_string(),
+        };
+        if self.idle {
+            status += "paused while the session is idle\n";
+        } else if self.pacing == FramePacing::Frozen {
+            status += "paused by the power policy\n";
+        }
+        status
+    }
+
+    /// Read the shader from disk again and show it, or the fallbacks if it doesn't build.
+    fn reload(&mut self, qh: &QueueHandle<Self>) -> String {
+        if self.conf.playlist.is_some() {
+            return "reloading isn't supported for playlists\n".to_string();
+        }
+        let Some(reloader) = self.conf.shader_reloader.as_ref() else {
+            return "this wallpaper has no shader files to reload\n".to_string();
+        };
+        let shader = match reloader() {
+            Ok(shader) => shader,
+            Err(e) => {
+                tracing::warn!("Failed to reload the shader: {}", e);
+                return format!("failed to reload, keeping the current shader: {}\n", e);
+            }
+        };
+        let Some(graphics) = self.graphics.as_mut() else {
+            self.conf.shader = shader;
+            return "reloaded\n".to_string();
+        };
+        if let Err(e) = graphics.reload(&self.conf, &shader) {
+            self.fail(e);
+            return "reloading failed, exiting\n".to_string();
+        }
+        self.conf.shader = shader;
+        tracing::info!("Reloaded the shader");
+        // Show the new shader even while paused; later frames follow the usual pacing.
+        if !self.frame_pending {
+            self.render_frame(qh);
+        }
+        format!("reloaded\n{}", self.status())
+    }
+
+    /// The next point in time `tick` has work to do, if any.
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        let power_poll = self.power_monitor.as_ref().map(PowerMonitor::next_poll);
+        match (self.deferred_render, power_poll) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Handle time based work: power supply polling and frames held back by pacing.
+    pub fn tick(&mut self, qh: &QueueHandle<Self>) {
+        let now = Instant::now();
+        if let Some(pacing) = self
+            .power_monitor
+            .as_mut()
+            .and_then(|monitor| monitor.poll(now))
+            && pacing != self.pacing
+        {
+            tracing::info!("Frame pacing changed to {:?}", pacing);
+            let was_frozen = self.pacing == FramePacing::Frozen;
+            self.pacing = pacing;
+            self.deferred_render = None;
+            match pacing {
+                FramePacing::Frozen => {
+                    self.set_inputs_paused(true);
+                    if let Some(graphics) = self.graphics.as_ref()
+                        && let Err(e) = graphics.suspend()
+                    {
+                        tracing::warn!("{}", e);
+                    }
+                }
+                _ => {
+                    if was_frozen {
+                        self.set_inputs_paused(self.idle);
+                    }
+                    if !self.frame_pending {
+                        self.schedule_frame(qh);
+                    }
+                }
+            }
+        }
+        if let Some(deferred) = self.deferred_render
+            && deferred <= now
+        {
+            self.deferred_render = None;
+            self.render_frame(qh);
+        }
+    }
+
+    /// Render the next frame now or later, depending on idle state and frame pacing.
+    fn schedule_frame(&mut self, qh: &QueueHandle<Self>) {
+        if self.idle {
+            tracing::trace!("Session is idle, not scheduling another frame.");
+            return;
+        }
+        match self.pacing {
+            FramePacing::Unlimited => self.render_frame(qh),
+            FramePacing::Limited(interval) => {
+                let due = self
+                    .last_render
+                    .map_or_else(Instant::now, |last_render| last_render + interval);
+                if due <= Instant::now() {
+                    self.render_frame(qh);
+                } else {
+                    self.deferred_render = Some(due);
+                }
+            }
+            FramePacing::Frozen => {
+                tracing::trace!("Frame pacing is frozen, not scheduling another frame.");
+            }
+        }
+    }
+
+    /// The cursor position in surface pixels, `None` while it is unknown or elsewhere.
+    fn cursor_position(&self) -> Option<(f32, f32)> {
+        let position = self.conf.cursor_provider.as_ref()?.position()?;
+        let graphics = self.graphics.as_ref()?;
+        let output = self
+            .surface_output
+            .as_ref()
+            .and_then(|output| self.outputs.iter().find(|info| info.output == *output))
+            .and_then(|info| info.geometry.as_ref());
+        cursor_support::to_surface_pixels(position, output, self.surface_size, graphics.size())
+    }
+
+    fn bind_xdg_output(&mut self, qh: &QueueHandle<Self>) {
+        let Some((manager, _)) = self.xdg_output_manager.as_ref() else {
+            return;
+        };
+        for info in self
+            .outputs
+            .iter_mut()
+            .filter(|info| info.xdg_output.is_none())
+        {
+            info.xdg_output = Some(manager.get_xdg_output(&info.output, qh, ()));
+        }
+    }
+
+    /// Render a frame and request the callback that drives the next one.
+    fn render_frame(&mut self, qh: &QueueHandle<Self>) {
+        let now = chrono::Local::now();
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        let dt = (elapsed - self.last_frame_time).max(0.0) as f32;
+        self.last_frame_time = elapsed;
+        let cursor = self.cursor_filter.update(self.cursor_position(), dt);
+        let button_pressed = self
+            .conf
+            .cursor_provider
+            .as_ref()
+            .and_then(|provider| provider.button_pressed());
+        self.shadertoy_mouse.update(cursor, button_pressed);
+        let time = self.conf.clock.time(elapsed, &now);
+        let (daytime, sun_elevation) = daytime::uniforms(&now, self.conf.location);
+        if let Some(graphics) = self.graphics.as_mut()
+            && let Err(e) = graphics.advance_playlist(&self.conf, Instant::now())
+        {
+            self.fail(e);
+            return;
+        }
+        if let (Some(graphics), Some(surface)) = (self.graphics.as_ref(), self.surface.as_ref()) {
+            tracing::trace!("Rendering frame at elapsed time: {}", elapsed);
+            let rendered = graphics.render(&FrameInput {
+                time,
+                cursor,
+                cursor_velocity: self.cursor_filter.velocity(),
+                cursor_idle_time: self.cursor_filter.idle_time(),
+                shadertoy_mouse: self.shadertoy_mouse.uniform(),
+                daytime,
+                sun_elevation,
+            });
+            if let Err(e) = rendered {
+                self.fail(e);
+                return;
+            }
+            let _callback = surface.frame(qh, ());
+            surface.commit();
+            self.frame_pending = true;
+            self.last_render = Some(Instant::now());
+        } else {
+            tracing::trace!("No graphics or surface available for rendering.");
+        }
     }
 }
 
@@ -70,28 +371,84 @@ impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
                         tracing::info!("LayerShell found: {} (version {})", name, version);
                         state.layer_shell = Some((registry.bind(name, version, qh, ()), name));
                     }
+                    "wl_seat" if state.seat.is_none() => {
+                        tracing::info!("Seat found: {} (version {})", name, version);
+                        state.seat = Some((registry.bind(name, version.min(5), qh, ()), name));
+                    }
+                    "wl_output" => {
+                        tracing::info!("Output found: {} (version {})", name, version);
+                        state.outputs.push(OutputInfo {
+                            output: registry.bind(name, version.min(4), qh, ()),
+                            name,
+                            connector: None,
+                            xdg_output: None,
+                            geometry: None,
+                        });
+                        state.bind_xdg_output(qh);
+                    }
+                    "zxdg_output_manager_v1" => {
+                        tracing::info!("XdgOutputManager found: {} (version {})", name, version);
+                        state.xdg_output_manager =
+                            Some((registry.bind(name, version.min(3), qh, ()), name));
+                        state.bind_xdg_output(qh);
+                    }
+                    "ext_idle_notifier_v1" => {
+                        tracing::info!("IdleNotifier found: {} (version {})", name, version);
+                        state.idle_notifier = Some((registry.bind(name, 1, qh, ()), name));
+                    }
                     _ => {}
                 }
             }
             wl_registry::Event::GlobalRemove { name } => {
                 let _span_guard =
                     tracing::trace_span!("wl_registry::Event::GlobalRemove", name).entered();
-                if let Some((_, compositor_name)) = &state.compositor {
-                    if *compositor_name == name {
-                        tracing::warn!("Compositor {} removed", name);
-                        state.compositor = None;
-                    }
+                if let Some((_, compositor_name)) = &state.compositor
+                    && *compositor_name == name
+                {
+                    tracing::warn!("Compositor {} removed", name);
+                    state.compositor = None;
+                }
+                if let Some((_, layer_shell_name)) = &state.layer_shell
+                    && *layer_shell_name == name
+                {
+                    tracing::warn!("LayerShell {} removed", name);
+                    state.layer_shell = None;
+                }
+                if let Some((_, manager_name)) = &state.xdg_output_manager
+                    && *manager_name == name
+                {
+                    tracing::warn!("XdgOutputManager {} removed", name);
+                    state.xdg_output_manager = None;
                 }
-                if let Some((_, layer_shell_name)) = &state.layer_shell {
-                    if *layer_shell_name == name {
-                        tracing::warn!("LayerShell {} removed", name);
-                        state.layer_shell = None;
+                if let Some(index) = state.outputs.iter().position(|info| info.name == name) {
+                    tracing::info!("Output {} removed", name);
+                    let info = state.outputs.remove(index);
+                    if state.surface_output.as_ref() == Some(&info.output) {
+                        state.surface_output = None;
+                        state.update_channel_output();
+                    }
+                    if let Some(xdg_output) = info.xdg_output {
+                        xdg_output.destroy();
+                    }
+                    if info.output.version() >= 3 {
+                        info.output.release();
                     }
                 }
+                if let Some((_, seat_name)) = &state.seat
+                    && *seat_name == name
+                {
+                    tracing::warn!("Seat {} removed", name);
+                    state.seat = None;
+                }
+                if let Some((_, idle_notifier_name)) = &state.idle_notifier
+                    && *idle_notifier_name == name
+                {
+                    tracing::warn!("IdleNotifier {} removed", name);
+                    state.idle_notifier = None;
+                }
             }
             _ => {}
         };
-        return;
     }
 }
 
@@ -136,19 +493,22 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for AppState {
                     height
                 );
                 surface.ack_configure(serial);
+                state.surface_size = (width, height);
                 if let Some(surface) = state.surface.as_ref()
                     && state.graphics.is_none()
                 {
-                    let graphics =
-                        Graphics::new(&state.display, &surface, width, height, &state.conf);
-                    let elapsed = state.start_time.elapsed().as_secs_f32();
-                    graphics.render(elapsed);
-                    tracing::info!("Rendering initial frame");
-                    let _callback = surface.frame(qh, ());
-                    surface.commit();
-                    state.graphics = Some(graphics);
-                } else if let Some(graphics) = state.graphics.as_mut() {
-                    graphics.resize(width, height);
+                    match Graphics::new(&state.display, surface, width, height, &state.conf) {
+                        Ok(graphics) => {
+                            state.graphics = Some(graphics);
+                            tracing::info!("Rendering initial frame");
+                            state.render_frame(qh);
+                        }
+                        Err(e) => state.fail(e),
+                    }
+                } else if let Some(graphics) = state.graphics.as_mut()
+                    && let Err(e) = graphics.resize(width, height)
+                {
+                    state.fail(e);
                 }
             }
             zwlr_layer_surface_v1::Event::Closed => {
@@ -175,17 +535,8 @@ impl Dispatch<wl_callback::WlCallback, ()> for AppState {
             wl_callback::Event::Done { .. } => {
                 let _span_guard = tracing::trace_span!("wl_callback::Event::Done").entered();
                 // Frame callback done, can be used to trigger next render
-                if let (Some(graphics), Some(surface)) =
-                    (state.graphics.as_ref(), state.surface.as_ref())
-                {
-                    let elapsed = state.start_time.elapsed().as_secs_f32();
-                    tracing::trace!("Rendering frame at elapsed time: {}", elapsed);
-                    graphics.render(elapsed);
-                    let _callback = surface.frame(qh, ());
-                    surface.commit();
-                } else {
-                    tracing::trace!("No graphics or surface available for rendering.");
-                }
+                state.frame_pending = false;
+                state.schedule_frame(qh);
             }
             _ => {
                 // Do nothing
@@ -196,7 +547,7 @@ impl Dispatch<wl_callback::WlCallback, ()> for AppState {
 
 impl Dispatch<wl_surface::WlSurface, ()> for AppState {
     fn event(
-        _state: &mut Self,
+        state: &mut Self,
         _surface: &wl_surface::WlSurface,
         event: wl_surface::Event,
         _data: &(),
@@ -204,11 +555,16 @@ impl Dispatch<wl_surface::WlSurface, ()> for AppState {
         _qh: &QueueHandle<Self>,
     ) {
         match event {
-            wl_surface::Event::Enter { .. } => {
-                // Do nothing: Cursor enter event is not needed for background.
+            wl_surface::Event::Enter { output } => {
+                tracing::debug!("Surface entered output {}", output.id());
+                state.surface_output = Some(output);
+                state.update_channel_output();
             }
-            wl_surface::Event::Leave { .. } => {
-                // Do nothing: Cursor leave event is not needed for background.
+            wl_surface::Event::Leave { output }
+                if state.surface_output.as_ref() == Some(&output) =>
+            {
+                state.surface_output = None;
+                state.update_channel_output();
             }
             wl_surface::Event::PreferredBufferScale { factor } => {
                 // todo: HiDPI support
@@ -237,3 +593,175 @@ impl Dispatch<wl_compositor::WlCompositor, ()> for AppState {
         // Do nothing: Compositor never dispatches events.
     }
 }
+
+impl Dispatch<wl_seat::WlSeat, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        seat: &wl_seat::WlSeat,
+        event: wl_seat::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities {
+            capabilities: WEnum::Value(capabilities),
+        } = event
+        {
+            let _span_guard =
+                tracing::trace_span!("wl_seat::Event::Capabilities", ?capabilities).entered();
+            let wants_pointer = state
+                .conf
+                .cursor_provider
+                .as_ref()
+                .is_some_and(|provider| provider.uses_pointer_events());
+            let has_pointer = capabilities.contains(wl_seat::Capability::Pointer);
+            if wants_pointer && has_pointer && state.pointer.is_none() {
+                tracing::info!("Tracking the pointer of the seat");
+                state.pointer = Some(seat.get_pointer(qh, ()));
+            } else if !has_pointer && let Some(pointer) = state.pointer.take() {
+                tracing::warn!("Seat lost its pointer");
+                if pointer.version() >= 3 {
+                    pointer.release();
+                }
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_pointer::WlPointer, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _pointer: &wl_pointer::WlPointer,
+        event: wl_pointer::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let Some(cursor_provider) = state.conf.cursor_provider.as_ref() {
+            cursor_provider.handle_pointer_event(&event);
+        }
+    }
+}
+
+impl Dispatch<ext_idle_notifier_v1::ExtIdleNotifierV1, ()> for AppState {
+    fn event(
+        _state: &mut Self,
+        _idle_notifier: &ext_idle_notifier_v1::ExtIdleNotifierV1,
+        _event: ext_idle_notifier_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Do nothing: IdleNotifier never dispatches events.
+    }
+}
+
+impl Dispatch<ext_idle_notification_v1::ExtIdleNotificationV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _notification: &ext_idle_notification_v1::ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_idle_notification_v1::Event::Idled => {
+                let _span_guard =
+                    tracing::trace_span!("ext_idle_notification_v1::Event::Idled").entered();
+                tracing::info!("Session idle, pausing rendering");
+                state.idle = true;
+                state.deferred_render = None;
+                state.set_inputs_paused(true);
+                if let Some(graphics) = state.graphics.as_ref()
+                    && let Err(e) = graphics.suspend()
+                {
+                    tracing::warn!("{}", e);
+                }
+            }
+            ext_idle_notification_v1::Event::Resumed => {
+                let _span_guard =
+                    tracing::trace_span!("ext_idle_notification_v1::Event::Resumed").entered();
+                tracing::info!("Session resumed, restarting rendering");
+                state.idle = false;
+                state.set_inputs_paused(state.pacing == FramePacing::Frozen);
+                // The previous frame callback may still be in flight; it restarts the loop itself.
+                if !state.frame_pending && state.deferred_render.is_none() {
+                    state.schedule_frame(qh);
+                }
+            }
+            _ => {
+                // Do nothing
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // The logical geometry comes from xdg-output; only the name is of interest here.
+        if let wl_output::Event::Name { name } = event
+            && let Some(info) = state.outputs.iter_mut().find(|info| info.output == *output)
+        {
+            tracing::debug!("Output {} is {}", info.name, name);
+            info.connector = Some(name);
+            if state.surface_output.as_ref() == Some(output) {
+                state.update_channel_output();
+            }
+        }
+    }
+}
+
+impl Dispatch<zxdg_output_manager_v1::ZxdgOutputManagerV1, ()> for AppState {
+    fn event(
+        _state: &mut Self,
+        _manager: &zxdg_output_manager_v1::ZxdgOutputManagerV1,
+        _event: zxdg_output_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Do nothing: XdgOutputManager never dispatches events.
+    }
+}
+
+impl Dispatch<zxdg_output_v1::ZxdgOutputV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        xdg_output: &zxdg_output_v1::ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let Some(info) = state
+            .outputs
+            .iter_mut()
+            .find(|info| info.xdg_output.as_ref() == Some(xdg_output))
+        else {
+            return;
+        };
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => {
+                tracing::debug!("Output {} is at logical position {}x{}", info.name, x, y);
+                let geometry = info.geometry.get_or_insert_default();
+                geometry.x = x;
+                geometry.y = y;
+            }
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                tracing::debug!("Output {} has logical size {}x{}", info.name, width, height);
+                let geometry = info.geometry.get_or_insert_default();
+                geometry.width = width;
+                geometry.height = height;
+            }
+            _ => {}
+        }
+    }
+}
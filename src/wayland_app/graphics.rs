@@ -6,8 +6,25 @@ use wayland_client::protocol::wl_surface;
 use wayland_client::{Proxy, protocol::wl_display};
 use wayland_egl as wegl;
 
+use std::time::{Duration, Instant};
+
 use super::AppConfiguration;
-use std::rc::Rc;
+use crate::error::{EglResultExt as _, Error, Result};
+use crate::pipeline::feedback::FeedbackPass;
+use crate::pipeline::transition::TransitionPass;
+use crate::pipeline::{FrameInput, Pipeline, ShaderFailure};
+use crate::playlist::Schedule;
+use crate::renderer::Renderer;
+use crate::shader::ShaderSource;
+
+/// The state of a playlist being played.
+struct Slideshow {
+    schedule: Schedule,
+    transition: TransitionPass,
+    transition_duration: Duration,
+    /// The pipeline of the entry being switched to, and when the switch started.
+    incoming: Option<(Pipeline, Instant)>,
+}
 
 /// Struct to manage EGL/OpenGL ES initialization and rendering using `glow`
 pub struct Graphics {
@@ -20,68 +37,122 @@ pub struct Graphics {
     height: i32,
 
     gl: glow::Context,
-
-    shader_program: glow::Program,
-    vbo: glow::Buffer,
-    time_uniform_location: Option<glow::UniformLocation>,
-    resolution_uniform_location: Option<glow::UniformLocation>,
-    cursor_location_and_inspector: Option<(glow::UniformLocation, Rc<fn() -> (f32, f32)>)>,
+    pipeline: Pipeline,
+    /// Label in the fallback chain of the shader shown, e.g. `"fallback shader"`.
+    shown_shader: &'static str,
+    shader_failures: Vec<ShaderFailure>,
+    slideshow: Option<Slideshow>,
+    feedback: Option<FeedbackPass>,
 }
 
 impl Graphics {
-    pub fn render(&self, elapsed: f32) {
-        self.egl_instance
-            .make_current(
-                self.egl_display,
-                Some(self.egl_surface),
-                Some(self.egl_surface),
-                Some(self.egl_context),
-            )
-            .inspect_err(|e| {
-                tracing::error!("Failed to make EGL context current: {}", e);
-            })
-            .unwrap();
-
-        // glow functions must be called inside an unsafe block
+    /// Wait for queued GPU work and release the context so the driver can drop clocks
+    /// while nothing is being rendered. `render` makes the context current again.
+    pub fn suspend(&self) -> Result<()> {
         unsafe {
-            self.gl.viewport(0, 0, self.width, self.height);
-            // Pass the program handle wrapped in Option
-            self.gl.use_program(Some(self.shader_program));
-
-            // Pass a reference to the UniformLocation
-            if let Some(location) = self.time_uniform_location {
-                self.gl.uniform_1_f32(Some(&location), elapsed);
-            }
-            if let Some(location) = self.resolution_uniform_location {
-                self.gl
-                    .uniform_2_f32(Some(&location), self.width as f32, self.height as f32);
-            }
-            if let Some((cursor_location, get_cursor)) = self.cursor_location_and_inspector.as_ref()
-            {
-                let (x, y) = get_cursor();
-                self.gl.uniform_2_f32(Some(cursor_location), x, y);
-            }
+            self.gl.finish();
+        }
+        self.egl_instance
+            .make_current(self.egl_display, None, None, None)
+            .context("failed to release EGL context")
+    }
 
-            // Draw the rectangle
-            self.gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+    /// Which shader of the fallback chain is shown and why the ones before it failed, as
+    /// text for the `status` control command.
+    pub fn status(&self) -> String {
+        let mut status = format!("showing the {}\n", self.shown_shader);
+        for failure in &self.shader_failures {
+            status += &format!(
+                "the {} failed to build: {}\n",
+                failure.shader, failure.error
+            );
         }
+        status
+    }
 
-        self.egl_instance
-            .swap_buffers(self.egl_display, self.egl_surface)
-            .inspect_err(|e| {
-                tracing::error!("Failed to swap EGL buffers: {}", e);
-            })
-            .unwrap();
+    /// Replace the shown shader with `shader`, falling back like at startup if it doesn't
+    /// build. Not for playlists, whose entries are switched by `advance_playlist`.
+    pub fn reload(&mut self, conf: &AppConfiguration, shader: &ShaderSource) -> Result<()> {
+        self.make_current()?;
+        let chain = shader.fallback_chain(conf.fallback_shader.as_ref());
+        let (pipeline, shader_failures) =
+            Pipeline::with_fallbacks(&self.gl, &chain, &conf.channels)?;
+        std::mem::replace(&mut self.pipeline, pipeline).delete(&self.gl);
+        self.shown_shader = chain[shader_failures.len()].0;
+        self.shader_failures = shader_failures;
+        Ok(())
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<()> {
         self.width = width as i32;
         self.height = height as i32;
         self.wl_egl_surface
             .resize(width as i32, height as i32, 0, 0);
-        unsafe {
-            self.gl.viewport(0, 0, width as i32, height as i32);
+        if self.slideshow.is_none() && self.feedback.is_none() {
+            return Ok(());
+        }
+        self.make_current()?;
+        if let Some(slideshow) = self.slideshow.as_ref() {
+            slideshow.transition.resize(&self.gl, width, height)?;
         }
+        if let Some(feedback) = self.feedback.as_ref() {
+            feedback.resize(&self.gl, width, height)?;
+        }
+        Ok(())
+    }
+
+    /// Move the playlist of `conf` on: finish a transition that has run its course and
+    /// start the next one when the current entry is due. Does nothing without a playlist.
+    pub fn advance_playlist(&mut self, conf: &AppConfiguration, now: Instant) -> Result<()> {
+        let (Some(slideshow), Some(playlist)) = (self.slideshow.as_ref(), conf.playlist.as_ref())
+        else {
+            return Ok(());
+        };
+        let due = match slideshow.incoming {
+            Some((_, started)) => now.duration_since(started) >= slideshow.transition_duration,
+            None => now >= slideshow.schedule.next_switch(),
+        };
+        if !due {
+            return Ok(());
+        }
+        self.make_current()?;
+
+        let Some(slideshow) = self.slideshow.as_mut() else {
+            return Ok(());
+        };
+        if let Some((incoming, _)) = slideshow.incoming.take() {
+            std::mem::replace(&mut self.pipeline, incoming).delete(&self.gl);
+            return Ok(());
+        }
+        let Some(index) = slideshow.schedule.advance(now) else {
+            return Ok(());
+        };
+        tracing::info!("Switching to playlist entry {}", index);
+        let transition_duration = slideshow.transition_duration;
+        let entry = &playlist.entries[index];
+        let chain = entry.shader.fallback_chain(conf.fallback_shader.as_ref());
+        let (pipeline, shader_failures) =
+            Pipeline::with_fallbacks(&self.gl, &chain, &conf.channels)?;
+        pipeline.set_uniforms(&self.gl, &entry.uniforms);
+        self.shown_shader = chain[shader_failures.len()].0;
+        self.shader_failures = shader_failures;
+        if transition_duration.is_zero() {
+            std::mem::replace(&mut self.pipeline, pipeline).delete(&self.gl);
+        } else {
+            slideshow.incoming = Some((pipeline, now));
+        }
+        Ok(())
+    }
+
+    fn make_current(&self) -> Result<()> {
+        self.egl_instance
+            .make_current(
+                self.egl_display,
+                Some(self.egl_surface),
+                Some(self.egl_surface),
+                Some(self.egl_context),
+            )
+            .context("failed to make EGL context current")
     }
 
     pub fn new(
@@ -90,31 +161,21 @@ impl Graphics {
         width: u32,
         height: u32,
         conf: &AppConfiguration,
-    ) -> Self {
+    ) -> Result<Self> {
         let egl_instance = egl::Instance::<egl::Static>::new(egl::Static);
 
         let egl_display = unsafe {
             egl_instance
                 .get_display(display.id().as_ptr() as egl::NativeDisplayType)
-                .ok_or("Failed to get EGL display")
-                .inspect_err(|e| {
-                    tracing::error!("{}", e);
-                })
-                .unwrap()
+                .ok_or(Error::NoEglDisplay)?
         };
 
         egl_instance
             .initialize(egl_display)
-            .inspect_err(|e| {
-                tracing::error!("Failed to initialize EGL display: {}", e);
-            })
-            .unwrap();
+            .context("failed to initialize EGL display")?;
         egl_instance
             .bind_api(egl::OPENGL_ES_API)
-            .inspect_err(|e| {
-                tracing::error!("Failed to bind EGL API: {}", e);
-            })
-            .unwrap();
+            .context("failed to bind EGL API")?;
 
         let attributes = [
             egl::RED_SIZE,
@@ -131,26 +192,16 @@ impl Graphics {
         ];
         let config = egl_instance
             .choose_first_config(egl_display, &attributes)
-            .inspect_err(|e| {
-                tracing::error!("Failed to choose EGL config: {}", e);
-            })
-            .unwrap()
-            .ok_or("Failed to find suitable EGL config")
-            .inspect_err(|e| {
-                tracing::error!("{}", e);
-            })
-            .unwrap();
+            .context("failed to choose EGL config")?
+            .ok_or(Error::NoEglConfig("window surfaces and OpenGL ES 2"))?;
 
         let context_attributes = [egl::CONTEXT_CLIENT_VERSION, 2, egl::NONE];
         let egl_context = egl_instance
             .create_context(egl_display, config, None, &context_attributes)
-            .inspect_err(|e| {
-                tracing::error!("Failed to create EGL context: {}", e);
-            })
-            .unwrap();
+            .context("failed to create EGL context")?;
 
-        let wl_egl_surface =
-            wegl::WlEglSurface::new(surface.id(), width as i32, height as i32).unwrap();
+        let wl_egl_surface = wegl::WlEglSurface::new(surface.id(), width as i32, height as i32)
+            .map_err(|e| Error::Gl(format!("failed to create wl_egl_window: {}", e)))?;
 
         let egl_surface = unsafe {
             egl_instance
@@ -160,10 +211,7 @@ impl Graphics {
                     wl_egl_surface.ptr() as egl::NativeWindowType,
                     None,
                 )
-                .inspect_err(|e| {
-                    tracing::error!("Failed to create EGL surface: {}", e);
-                })
-                .unwrap()
+                .context("failed to create EGL surface")?
         };
 
         egl_instance
@@ -173,106 +221,54 @@ impl Graphics {
                 Some(egl_surface),
                 Some(egl_context),
             )
-            .unwrap();
+            .context("failed to make EGL context current")?;
 
         let gl = unsafe {
             glow::Context::from_loader_function(|s| {
-                egl_instance.get_proc_address(s).unwrap() as *const _
+                egl_instance
+                    .get_proc_address(s)
+                    .map_or(std::ptr::null(), |f| f as *const _)
             })
         };
 
-        let shader_program = unsafe {
-            let program = gl.create_program().expect("Cannot create program");
-
-            let vs = gl
-                .create_shader(glow::VERTEX_SHADER)
-                .inspect_err(|e| {
-                    tracing::error!("Cannot create vertex shader: {}", e);
-                })
-                .unwrap();
-            gl.shader_source(vs, &conf.vertex_shader);
-            gl.compile_shader(vs);
-            if !gl.get_shader_compile_status(vs) {
-                tracing::error!(
-                    "Vertex shader compilation failed: {}",
-                    gl.get_shader_info_log(vs)
-                );
-                std::process::exit(1);
-            }
-            gl.attach_shader(program, vs);
-
-            let fs = gl
-                .create_shader(glow::FRAGMENT_SHADER)
-                .inspect_err(|e| {
-                    tracing::error!("Cannot create fragment shader: {}", e);
+        let slideshow = conf
+            .playlist
+            .as_ref()
+            .map(|playlist| {
+                Ok::<_, Error>(Slideshow {
+                    schedule: Schedule::new(playlist, conf.location, Instant::now()),
+                    transition: TransitionPass::new(
+                        &gl,
+                        &playlist.transition.fragment_shader,
+                        width,
+                        height,
+                    )?,
+                    transition_duration: playlist.transition.duration,
+                    incoming: None,
                 })
-                .unwrap();
-            gl.shader_source(fs, &conf.fragment_shader);
-            gl.compile_shader(fs);
-            if !gl.get_shader_compile_status(fs) {
-                tracing::error!(
-                    "Fragment shader compilation failed: {}",
-                    gl.get_shader_info_log(fs)
-                );
-                std::process::exit(1);
-            }
-            gl.attach_shader(program, fs);
-
-            gl.link_program(program);
-            if !gl.get_program_link_status(program) {
-                panic!("{}", gl.get_program_info_log(program));
-            }
-
-            gl.detach_shader(program, fs);
-            gl.delete_shader(fs);
-            gl.detach_shader(program, vs);
-            gl.delete_shader(vs);
-
-            gl.use_program(Some(program));
-            program
-        };
-
-        let time_uniform_location = unsafe { gl.get_uniform_location(shader_program, "u_time") };
-
-        let resolution_uniform_location =
-            unsafe { gl.get_uniform_location(shader_program, "u_resolution") };
-
-        let cursor_location_and_inspector = match conf.get_cursor.as_ref() {
-            Some(get_cursor) => {
-                let cursor_location = unsafe { gl.get_uniform_location(shader_program, "u_mouse") };
-                match cursor_location {
-                    Some(cursor_location) => Some((cursor_location, get_cursor.clone())),
-                    None => None,
-                }
+            })
+            .transpose()?;
+        let shader = match (&slideshow, &conf.playlist) {
+            (Some(slideshow), Some(playlist)) => {
+                &playlist.entries[slideshow.schedule.current()].shader
             }
-            None => None,
+            _ => &conf.shader,
         };
-
-        let vbo = unsafe {
-            let vertices: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
-            let vertices_u8: &[u8] = core::slice::from_raw_parts(
-                vertices.as_ptr() as *const u8,
-                vertices.len() * std::mem::size_of::<f32>(),
+        let chain = shader.fallback_chain(conf.fallback_shader.as_ref());
+        let (pipeline, shader_failures) = Pipeline::with_fallbacks(&gl, &chain, &conf.channels)?;
+        let shown_shader = chain[shader_failures.len()].0;
+        if let (Some(slideshow), Some(playlist)) = (&slideshow, &conf.playlist) {
+            pipeline.set_uniforms(
+                &gl,
+                &playlist.entries[slideshow.schedule.current()].uniforms,
             );
+        }
+        let feedback = conf
+            .feedback
+            .then(|| FeedbackPass::new(&gl, width, height))
+            .transpose()?;
 
-            let vbo = gl.create_buffer().unwrap();
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
-            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, vertices_u8, glow::STATIC_DRAW);
-
-            let pos_attr_loc = gl
-                .get_attrib_location(shader_program, "a_position")
-                .ok_or("Failed to get attribute location for a_position")
-                .inspect_err(|e| {
-                    tracing::error!("{}", e);
-                })
-                .unwrap();
-            gl.enable_vertex_attrib_array(pos_attr_loc);
-            gl.vertex_attrib_pointer_f32(pos_attr_loc, 2, glow::FLOAT, false, 0, 0);
-
-            vbo
-        };
-
-        Graphics {
+        Ok(Graphics {
             egl_instance,
             egl_display,
             egl_context,
@@ -281,53 +277,122 @@ impl Graphics {
             width: width as i32,
             height: height as i32,
             gl,
-            shader_program,
-            vbo,
-            time_uniform_location,
-            resolution_uniform_location,
-            cursor_location_and_inspector,
+            pipeline,
+            shown_shader,
+            shader_failures,
+            slideshow,
+            feedback,
+        })
+    }
+}
+
+impl Renderer for Graphics {
+    fn render(&self, input: &FrameInput) -> Result<()> {
+        self.make_current()?;
+        // The incoming pipeline of a transition reads the same channels.
+        self.pipeline.advance_channels();
+
+        let (width, height) = (self.width as u32, self.height as u32);
+        let incoming = self
+            .slideshow
+            .as_ref()
+            .and_then(|slideshow| slideshow.incoming.as_ref())
+            .map(|(incoming, _)| incoming);
+        let output = self.feedback.as_ref().map(|feedback| {
+            let pipelines: Vec<&Pipeline> =
+                std::iter::once(&self.pipeline).chain(incoming).collect();
+            feedback.begin(&self.gl, &pipelines, width, height)
+        });
+        match self.slideshow.as_ref() {
+            Some(Slideshow {
+                transition,
+                transition_duration,
+                incoming: Some((incoming, started)),
+                ..
+            }) => {
+                // advance_playlist ends the transition once it has run for its duration.
+                let progress = started.elapsed().as_secs_f32() / transition_duration.as_secs_f32();
+                transition.draw(
+                    &self.gl,
+                    output,
+                    width,
+                    height,
+                    input,
+                    &self.pipeline,
+                    incoming,
+                    progress,
+                );
+            }
+            _ => {
+                unsafe {
+                    self.gl.bind_framebuffer(glow::FRAMEBUFFER, output);
+                }
+                self.pipeline.draw(&self.gl, width, height, input);
+            }
         }
+        if let Some(feedback) = self.feedback.as_ref() {
+            feedback.finish(&self.gl, None, width, height);
+        }
+
+        self.egl_instance
+            .swap_buffers(self.egl_display, self.egl_surface)
+            .context("failed to swap EGL buffers")
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.width as u32, self.height as u32)
     }
 }
 
 impl Drop for Graphics {
     fn drop(&mut self) {
-        unsafe {
-            // 1. Unbind EGL context
-            self.egl_instance
-                .make_current(self.egl_display, None, None, None)
-                .inspect_err(|e| {
-                    tracing::error!("Failed to unbind EGL context: {}", e);
-                })
-                .unwrap();
+        // 1. glow (OpenGL) resources cleanup, while the context is current
+        self.make_current()
+            .inspect_err(|e| {
+                tracing::error!("{}", e);
+            })
+            .ok();
+        self.pipeline.delete(&self.gl);
+        if let Some(slideshow) = self.slideshow.as_ref() {
+            slideshow.transition.delete(&self.gl);
+            if let Some((incoming, _)) = slideshow.incoming.as_ref() {
+                incoming.delete(&self.gl);
+            }
+        }
+        if let Some(feedback) = self.feedback.as_ref() {
+            feedback.delete(&self.gl);
+        }
 
-            // 2. glow (OpenGL) resources cleanup
-            self.gl.delete_program(self.shader_program);
-            self.gl.delete_buffer(self.vbo);
+        // 2. Unbind EGL context
+        self.egl_instance
+            .make_current(self.egl_display, None, None, None)
+            .inspect_err(|e| {
+                tracing::error!("Failed to unbind EGL context: {}", e);
+            })
+            .ok();
 
-            // 3. Destroy EGL surface
-            self.egl_instance
-                .destroy_surface(self.egl_display, self.egl_surface)
-                .inspect_err(|e| {
-                    tracing::error!("Failed to destroy EGL surface: {}", e);
-                })
-                .unwrap();
+        // 3. Destroy EGL surface
+        self.egl_instance
+            .destroy_surface(self.egl_display, self.egl_surface)
+            .inspect_err(|e| {
+                tracing::error!("Failed to destroy EGL surface: {}", e);
+            })
+            .ok();
 
-            // 4. Destroy EGL context
-            self.egl_instance
-                .destroy_context(self.egl_display, self.egl_context)
-                .inspect_err(|e| {
-                    tracing::error!("Failed to destroy EGL context: {}", e);
-                })
-                .unwrap();
+        // 4. Destroy EGL context
+        self.egl_instance
+            .destroy_context(self.egl_display, self.egl_context)
+            .inspect_err(|e| {
+                tracing::error!("Failed to destroy EGL context: {}", e);
+            })
+            .ok();
 
-            // 5. Terminate connection to EGL display
-            self.egl_instance
-                .terminate(self.egl_display)
-                .inspect_err(|e| {
-                    tracing::error!("Failed to terminate EGL display: {}", e);
-                })
-                .unwrap();
-        }
+        // 5. Terminate connection to EGL display
+        self.egl_instance
+            .terminate(self.egl_display)
+            .inspect_err(|e| {
+                tracing::error!("Failed to terminate EGL display: {}", e);
+            })
+            .ok();
     }
 }
@@ -0,0 +1,336 @@
+//! Golden-image tests: render the built-in shaders headlessly and compare them with the
+//! reference images in `tests/golden/`.
+//!
+//! Rendering needs an EGL driver, Mesa's llvmpipe is enough. Without one the tests are
+//! skipped, unless `WAYGGLE_REQUIRE_EGL=1` is set. Run with `WAYGGLE_BLESS=1` to
+//! regenerate the references after an intended change.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+const SIZE: &str = "160x90";
+/// Largest difference of a channel value that still counts as the same.
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Share of pixels allowed to differ by more than `CHANNEL_TOLERANCE`, for rasterizer
+/// differences along edges.
+const MAX_DIFFERING_PIXELS: f64 = 0.005;
+
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+fn read_png(path: &Path) -> Image {
+    let file = std::fs::File::open(path)
+        .unwrap_or_else(|e| panic!("Cannot open {}: {}", path.display(), e));
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgba, "{}", path.display());
+    pixels.truncate(info.buffer_size());
+    Image {
+        width: info.width,
+        height: info.height,
+        pixels,
+    }
+}
+
+fn shader_path(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("shaders")
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn test_image(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/images")
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn temp_path(name: &str, suffix: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "wayggle-bg-golden-{}-{}{}",
+        std::process::id(),
+        name,
+        suffix
+    ))
+}
+
+/// Exit code of wayggle-bg when EGL or GL fail.
+const EXIT_GRAPHICS: i32 = 6;
+
+/// Whether frames can be rendered here, found out by rendering a pixel once. Only a
+/// failure of EGL or GL counts as not being able to; anything else is left to fail the
+/// tests.
+fn can_render() -> bool {
+    static CAN_RENDER: OnceLock<bool> = OnceLock::new();
+    *CAN_RENDER.get_or_init(|| {
+        let out = temp_path("probe", ".png");
+        let output = Command::new(env!("CARGO_BIN_EXE_wayggle-bg"))
+            .args(["render", "--size", "1x1", "--out"])
+            .arg(&out)
+            .args([
+                "shadertoy",
+                "--fragment-shader",
+                &shader_path("default-shadertoy.glsl"),
+            ])
+            .output()
+            .expect("Failed to run wayggle-bg");
+        let _ = std::fs::remove_file(&out);
+        if output.status.code() != Some(EXIT_GRAPHICS)
+            || std::env::var_os("WAYGGLE_REQUIRE_EGL").is_some()
+        {
+            return true;
+        }
+        eprintln!(
+            "No EGL driver to render with, skipping the golden-image tests:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        false
+    })
+}
+
+/// Run a wayggle-bg `command`, failing the test if it fails.
+fn run(name: &str, command: &mut Command) {
+    let output = command.output().expect("Failed to run wayggle-bg");
+    assert!(
+        output.status.success(),
+        "wayggle-bg failed for {}:\n{}",
+        name,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Render a frame with the `render` subcommand and compare it with `tests/golden/<name>.png`.
+fn check(name: &str, time: f32, shader_args: &[&str]) {
+    check_with_options(name, time, &[], shader_args);
+}
+
+/// Like [`check`], passing `options` before the subcommand.
+fn check_with_options(name: &str, time: f32, options: &[&str], shader_args: &[&str]) {
+    if !can_render() {
+        eprintln!("Skipping {}", name);
+        return;
+    }
+    let out = temp_path(name, ".png");
+    run(
+        name,
+        Command::new(env!("CARGO_BIN_EXE_wayggle-bg"))
+            .args(options)
+            .arg("render")
+            .args(["--time", &time.to_string(), "--size", SIZE, "--out"])
+            .arg(&out)
+            .args(shader_args),
+    );
+    compare(name, &out);
+}
+
+/// Record a second at 12 fps with the `record` subcommand and compare the last frame with
+/// `tests/golden/<name>.png`, for effects building up over several frames.
+fn check_recording(name: &str, options: &[&str], shader_args: &[&str]) {
+    if !can_render() {
+        eprintln!("Skipping {}", name);
+        return;
+    }
+    let out = temp_path(name, "");
+    run(
+        name,
+        Command::new(env!("CARGO_BIN_EXE_wayggle-bg"))
+            .args(options)
+            .arg("record")
+            .args(["--duration", "1s", "--fps", "12", "--size", SIZE, "--out"])
+            .arg(&out)
+            .args(shader_args),
+    );
+    compare(name, &out.join("frame_00011.png"));
+    std::fs::remove_dir_all(&out).unwrap();
+}
+
+/// Compare the frame at `out` with `tests/golden/<name>.png`, or replace the reference
+/// with it when blessing. `out` is removed unless they differ.
+fn compare(name: &str, out: &Path) {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name));
+
+    if std::env::var_os("WAYGGLE_BLESS").is_some() {
+        std::fs::copy(out, &golden).unwrap();
+        std::fs::remove_file(out).unwrap();
+        return;
+    }
+
+    let actual = read_png(out);
+    let expected = read_png(&golden);
+    assert_eq!(
+        (actual.width, actual.height),
+        (expected.width, expected.height),
+        "{}: size differs from the reference",
+        name
+    );
+    let differing = actual
+        .pixels
+        .chunks_exact(4)
+        .zip(expected.pixels.chunks_exact(4))
+        .filter(|(a, e)| {
+            a.iter()
+                .zip(*e)
+                .any(|(a, e)| a.abs_diff(*e) > CHANNEL_TOLERANCE)
+        })
+        .count();
+    let share = differing as f64 / (actual.width * actual.height) as f64;
+    if share > MAX_DIFFERING_PIXELS {
+        panic!(
+            "{}: {:.2}% of the pixels differ from {}, the frame is at {}",
+            name,
+            share * 100.0,
+            golden.display(),
+            out.display()
+        );
+    }
+    std::fs::remove_file(out).unwrap();
+}
+
+#[test]
+fn default_box() {
+    check("default-box-t0", 0.0, &["default", "--name", "box"]);
+    check("default-box-t2.5", 2.5, &["default", "--name", "box"]);
+}
+
+#[test]
+fn default_voronoi() {
+    check("default-voronoi-t1", 1.0, &["default", "--name", "voronoi"]);
+}
+
+#[test]
+fn shadertoy_default_shader() {
+    let shader = shader_path("default-shadertoy.glsl");
+    check(
+        "shadertoy-default-t1",
+        1.0,
+        &["shadertoy", "--fragment-shader", &shader],
+    );
+}
+
+#[test]
+fn book_of_shaders_default_shader() {
+    let shader = shader_path("default-frag.glsl");
+    check(
+        "book-of-shaders-default-t0",
+        0.0,
+        &["the_book_of_shaders", "--fragment-shader", &shader],
+    );
+    check(
+        "book-of-shaders-default-t3",
+        3.0,
+        &["the_book_of_shaders", "--fragment-shader", &shader],
+    );
+}
+
+#[test]
+fn book_of_shaders_custom_vertex_shader() {
+    let fragment = shader_path("default-frag.glsl");
+    let vertex = shader_path("default-vert.glsl");
+    check(
+        "book-of-shaders-vertex-t1",
+        1.0,
+        &[
+            "the_book_of_shaders",
+            "--fragment-shader",
+            &fragment,
+            "--vertex-shader",
+            &vertex,
+        ],
+    );
+}
+
+#[test]
+fn image_fit_modes() {
+    let image = test_image("portrait.png");
+    for fit in ["fill", "fit", "center", "tile", "stretch"] {
+        check(
+            &format!("image-{}", fit),
+            0.0,
+            &[
+                "image",
+                "--file",
+                &image,
+                "--fit",
+                fit,
+                "--background",
+                "#336699",
+            ],
+        );
+    }
+}
+
+#[test]
+fn image_effect() {
+    let image = test_image("portrait.png");
+    let effect = shader_path("image-effect-colour-shift.glsl");
+    check(
+        "image-colour-shift-t4",
+        4.0,
+        &["image", "--file", &image, "--effect", &effect],
+    );
+}
+
+#[test]
+fn feedback_trail() {
+    let shader = shader_path("feedback-trail.glsl");
+    check_recording(
+        "feedback-trail",
+        &["--feedback"],
+        &["shadertoy", "--fragment-shader", &shader],
+    );
+}
+
+#[test]
+fn cubemap_panorama() {
+    let panorama = test_image("panorama.png");
+    let shader = shader_path("cubemap-skybox.glsl");
+    for time in [0.0, 4.0] {
+        check_with_options(
+            &format!("cubemap-panorama-t{}", time),
+            time,
+            &["--cubemap", &panorama],
+            &["shadertoy", "--fragment-shader", &shader],
+        );
+    }
+}
+
+#[test]
+fn cubemap_faces() {
+    let faces =
+        ["px", "nx", "py", "ny", "pz", "nz"].map(|face| test_image(&format!("cube-{}.png", face)));
+    let options: Vec<&str> = faces
+        .iter()
+        .flat_map(|face| ["--cubemap", face.as_str()])
+        .collect();
+    let shader = shader_path("cubemap-skybox.glsl");
+    check_with_options(
+        "cubemap-faces-t0",
+        0.0,
+        &options,
+        &["shadertoy", "--fragment-shader", &shader],
+    );
+}
+
+#[test]
+fn volume_slice() {
+    let volume = test_image("volume.bin");
+    let shader = shader_path("volume-slice.glsl");
+    check_with_options(
+        "volume-slice-t0",
+        0.0,
+        &["--volume", &volume],
+        &["shadertoy", "--fragment-shader", &shader],
+    );
+}